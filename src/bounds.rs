@@ -0,0 +1,169 @@
+#[cfg(feature = "serde")]
+use std::convert::TryFrom;
+
+use rand::prelude::*;
+use rand_distr::{Distribution,Uniform};
+
+#[cfg(feature = "serde")]
+use serde::{Deserialize,Serialize};
+
+use crate::Real;
+use crate::config::{ConfigError,check};
+
+/// How to bring a candidate which has left the bounds back inside
+#[derive(Clone,Copy,Debug,PartialEq,Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize,Deserialize))]
+pub enum Repair {
+    /// Clamp to the violated bound
+    Clip,
+
+    /// Mirror back inside off the violated bound
+    Reflect,
+
+    /// Come back in from the opposite bound, treating the dimension as periodic
+    Wrap,
+
+    /// Sample uniformly within the bounds
+    Resample,
+
+    /// Move halfway between the parent and the violated bound.  Falls back to
+    /// clipping when there is no parent.
+    Midpoint
+}
+
+/// Per dimension box constraints
+#[derive(Clone,Debug)]
+#[cfg_attr(feature = "serde", derive(Serialize,Deserialize), serde(try_from = "BoundsDef<T>", bound(deserialize = "T: Real")))]
+pub struct Bounds<T = f32> {
+    pub lower: Vec<T>,
+    pub upper: Vec<T>,
+    pub repair: Repair
+}
+
+impl <T: Real> Bounds<T> {
+
+    pub fn new(lower: Vec<T>, upper: Vec<T>, repair: Repair) -> Self {
+        Bounds::try_new(lower, upper, repair).unwrap_or_else(|e| panic!("{}", e))
+    }
+
+    /// Like `new`, but returns an error for mismatched, unordered or
+    /// infinite limits
+    pub fn try_new(lower: Vec<T>, upper: Vec<T>, repair: Repair) -> Result<Self, ConfigError> {
+        let bounds = Bounds { lower, upper, repair };
+        bounds.validate()?;
+        Ok(bounds)
+    }
+
+    /// Checks the limits pair up, are ordered and are finite
+    pub fn validate(&self) -> Result<(), ConfigError> {
+        check(self.lower.len() == self.upper.len(), "bounds",
+              "need a lower and upper limit per dimension")?;
+        check(self.lower.iter().chain(self.upper.iter()).all(|x| x.is_finite()), "bounds",
+              "limits need to be finite")?;
+        check(self.lower.iter().zip(self.upper.iter()).all(|(l, u)| l <= u), "bounds",
+              "lower bounds need to be at most the upper bounds")
+    }
+
+    /// Same limits for every dimension
+    pub fn uniform(dims: usize, lower: T, upper: T, repair: Repair) -> Self {
+        Bounds::new(vec![lower; dims], vec![upper; dims], repair)
+    }
+
+    pub fn dims(&self) -> usize {
+        self.lower.len()
+    }
+
+    pub fn contains(&self, x: &[T]) -> bool {
+        x.iter().zip(self.lower.iter().zip(self.upper.iter()))
+            .all(|(xi, (l, u))| l <= xi && xi <= u)
+    }
+
+    /// Repairs every dimension of `x` which falls outside the bounds.  `parent`
+    /// is the point `x` was derived from, used by `Repair::Midpoint`.
+    pub fn repair<R: Rng>(&self, x: &mut [T], parent: Option<&[T]>, rng: &mut R) {
+        assert_eq!(x.len(), self.dims(), "Candidate doesn't match the bounds' dimensions");
+        for i in 0..x.len() {
+            let (l, u) = (self.lower[i], self.upper[i]);
+            let xi = x[i];
+            if l <= xi && xi <= u {
+                continue
+            }
+
+            let width = u - l;
+            let two = T::from_f32(2.);
+            x[i] = if !xi.is_finite() || width == T::ZERO {
+                // Nothing sensible to mirror or wrap, so clamp
+                xi.max(l).min(u)
+            } else {
+                match self.repair {
+                    Repair::Clip => xi.max(l).min(u),
+                    Repair::Reflect => {
+                        let t = (xi - l).rem_euclid(two * width);
+                        l + if t > width { two * width - t } else { t }
+                    },
+                    Repair::Wrap => l + (xi - l).rem_euclid(width),
+                    Repair::Resample => Uniform::new_inclusive(l, u).sample(rng),
+                    Repair::Midpoint => {
+                        let bound = if xi < l { l } else { u };
+                        match parent {
+                            Some(p) => (p[i].max(l).min(u) + bound) / two,
+                            None    => bound
+                        }
+                    }
+                }
+            };
+
+            // Guard against rounding pushing us just past the limit
+            x[i] = x[i].max(l).min(u);
+        }
+    }
+}
+
+/// Unchecked form of `Bounds`, validated on the way in
+#[cfg(feature = "serde")]
+#[derive(Deserialize)]
+#[serde(rename = "Bounds")]
+struct BoundsDef<T> {
+    lower: Vec<T>,
+    upper: Vec<T>,
+    repair: Repair
+}
+
+#[cfg(feature = "serde")]
+impl <T: Real> TryFrom<BoundsDef<T>> for Bounds<T> {
+    type Error = ConfigError;
+
+    fn try_from(def: BoundsDef<T>) -> Result<Self, ConfigError> {
+        Bounds::try_new(def.lower, def.upper, def.repair)
+    }
+}
+
+#[cfg(test)]
+mod test_bounds {
+    use super::*;
+
+    fn repaired(repair: Repair, x: f32) -> f32 {
+        let bounds = Bounds::uniform(1, 0., 10., repair);
+        let mut rng = StdRng::seed_from_u64(2020);
+        let mut v = vec![x];
+        bounds.repair(&mut v, Some(&[4.]), &mut rng);
+        v[0]
+    }
+
+    #[test]
+    fn test_repairs() {
+        assert_eq!(repaired(Repair::Clip, 12.), 10.);
+        assert_eq!(repaired(Repair::Clip, -3.), 0.);
+        assert_eq!(repaired(Repair::Reflect, 12.), 8.);
+        assert_eq!(repaired(Repair::Reflect, -3.), 3.);
+        assert_eq!(repaired(Repair::Reflect, 23.), 3.);
+        assert_eq!(repaired(Repair::Wrap, 12.), 2.);
+        assert_eq!(repaired(Repair::Wrap, -3.), 7.);
+        assert_eq!(repaired(Repair::Midpoint, 12.), 7.);
+        assert_eq!(repaired(Repair::Midpoint, -3.), 2.);
+        assert_eq!(repaired(Repair::Clip, 5.), 5.);
+
+        let x = repaired(Repair::Resample, 12.);
+        assert!((0. ..=10.).contains(&x));
+    }
+}
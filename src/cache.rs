@@ -0,0 +1,347 @@
+use std::collections::{BTreeMap,HashMap};
+use std::hash::Hash;
+use std::sync::Mutex;
+
+use crate::{Direction,Fitness,Real};
+
+/// Maps candidates to cache keys.  Candidates with the same key share a score.
+pub trait Keying<D>: Send + Sync {
+    type Key: Hash + Eq + Clone + Send;
+
+    fn key(&self, candidate: &D) -> Self::Key;
+}
+
+/// Keys candidates by themselves, for discrete encodings
+#[derive(Clone,Copy,Debug)]
+pub struct Exact;
+
+impl <D: Hash + Eq + Clone + Send + Sync> Keying<D> for Exact {
+    type Key = D;
+
+    fn key(&self, candidate: &D) -> D {
+        candidate.clone()
+    }
+}
+
+/// Keys continuous candidates by their exact bit patterns
+#[derive(Clone,Copy,Debug)]
+pub struct Bitwise;
+
+impl <T: Real> Keying<Vec<T>> for Bitwise {
+    type Key = Vec<u64>;
+
+    fn key(&self, candidate: &Vec<T>) -> Vec<u64> {
+        candidate.iter().map(|xi| xi.to_bits()).collect()
+    }
+}
+
+/// Keys continuous candidates by snapping each dimension to a grid of the
+/// given step, so near identical candidates share the first one's score
+#[derive(Clone,Copy,Debug)]
+pub struct Quantized(pub f64);
+
+impl <T: Real> Keying<Vec<T>> for Quantized {
+    type Key = Vec<i64>;
+
+    fn key(&self, candidate: &Vec<T>) -> Vec<i64> {
+        candidate.iter().map(|xi| (xi.to_f64() / self.0).round() as i64).collect()
+    }
+}
+
+/// How well a cache has been doing
+#[derive(Clone,Copy,Debug,Default,PartialEq,Eq)]
+pub struct CacheStats {
+    pub hits: usize,
+    pub misses: usize,
+    pub evictions: usize
+}
+
+impl CacheStats {
+
+    /// Fraction of lookups served from the cache
+    pub fn hit_rate(&self) -> f32 {
+        let lookups = self.hits + self.misses;
+        if lookups == 0 { 0. } else { self.hits as f32 / lookups as f32 }
+    }
+}
+
+/// Scores kept in least recently used order
+#[derive(Debug)]
+struct Lru<K, V> {
+    capacity: usize,
+    entries: HashMap<K, (V, u64)>,
+    order: BTreeMap<u64, K>,
+    tick: u64,
+    stats: CacheStats
+}
+
+impl <K: Hash + Eq + Clone, V: Copy> Lru<K, V> {
+
+    fn get(&mut self, key: &K) -> Option<V> {
+        let tick = self.tick;
+        let (score, last) = self.entries.get_mut(key)?;
+        let key = self.order.remove(last).expect("Entry missing from LRU order");
+        *last = tick;
+        self.order.insert(tick, key);
+        self.tick += 1;
+        Some(*score)
+    }
+
+    fn insert(&mut self, key: K, score: V) {
+        if let Some((_, last)) = self.entries.remove(&key) {
+            self.order.remove(&last);
+        } else if self.entries.len() == self.capacity {
+            let oldest = *self.order.keys().next().expect("Capacity should be positive");
+            let evicted = self.order.remove(&oldest).expect("Oldest should exist");
+            self.entries.remove(&evicted);
+            self.stats.evictions += 1;
+        }
+        self.entries.insert(key.clone(), (score, self.tick));
+        self.order.insert(self.tick, key);
+        self.tick += 1;
+    }
+}
+
+/// Memoises a fitness function, so candidates which come up again aren't
+/// rescored.  Holds at most `capacity` scores, evicting the least recently
+/// used.  Failed (NaN) scores are never cached.
+///
+/// Hits are charged to the budget like any other evaluation unless
+/// `free_hits` is set.  A converged population can then be served entirely
+/// from the cache, so pair the budget with a criterion such as `Stagnation`.
+#[derive(Debug)]
+pub struct Cached<F: Fitness, K: Keying<F::Data>> {
+    fit_fn: F,
+    keying: K,
+    free_hits: bool,
+    lru: Mutex<Lru<K::Key, F::Value>>
+}
+
+impl <F: Fitness, K: Keying<F::Data>> Cached<F, K> {
+
+    pub fn new(fit_fn: F, keying: K, capacity: usize) -> Self {
+        assert!(capacity > 0, "Cache needs room for at least one score");
+        let lru = Lru {
+            capacity,
+            entries: HashMap::new(),
+            order: BTreeMap::new(),
+            tick: 0,
+            stats: CacheStats::default()
+        };
+        Cached { fit_fn, keying, free_hits: false, lru: Mutex::new(lru) }
+    }
+
+    /// Stops cache hits from counting against the optimizer's budget
+    pub fn free_hits(mut self) -> Self {
+        self.free_hits = true;
+        self
+    }
+
+    pub fn inner(&self) -> &F {
+        &self.fit_fn
+    }
+
+    pub fn stats(&self) -> CacheStats {
+        self.lock().stats
+    }
+
+    /// Number of scores currently held
+    pub fn len(&self) -> usize {
+        self.lock().entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Drops every score, keeping the stats
+    pub fn clear(&self) {
+        let mut lru = self.lock();
+        lru.entries.clear();
+        lru.order.clear();
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, Lru<K::Key, F::Value>> {
+        self.lru.lock().expect("Cache poisoned")
+    }
+
+    fn store(lru: &mut Lru<K::Key, F::Value>, key: K::Key, score: F::Value) {
+        if !score.is_nan() {
+            lru.insert(key, score);
+        }
+    }
+}
+
+impl <F, K> Fitness for Cached<F, K>
+where
+    F: Fitness,
+    F::Data: Clone,
+    K: Keying<F::Data>
+{
+    type Data = F::Data;
+    type Value = F::Value;
+
+    fn score(&self, candidate: &F::Data) -> F::Value {
+        self.score_metered(candidate).0
+    }
+
+    fn score_batch(&self, candidates: &[F::Data]) -> Vec<F::Value> {
+        self.score_batch_metered(candidates).into_iter()
+            .map(|(s, _)| s)
+            .collect()
+    }
+
+    fn score_metered(&self, candidate: &F::Data) -> (F::Value, bool) {
+        let key = self.keying.key(candidate);
+        {
+            let mut lru = self.lock();
+            if let Some(score) = lru.get(&key) {
+                lru.stats.hits += 1;
+                return (score, self.free_hits)
+            }
+            lru.stats.misses += 1;
+        }
+
+        let (score, _) = self.fit_fn.score_metered(candidate);
+        Cached::<F, K>::store(&mut self.lock(), key, score);
+        (score, false)
+    }
+
+    /// Only the misses are passed on to the wrapped batch scorer.  Repeats
+    /// within the batch are scored once and count as hits.
+    fn score_batch_metered(&self, candidates: &[F::Data]) -> Vec<(F::Value, bool)> {
+        let keys: Vec<_> = candidates.iter().map(|c| self.keying.key(c)).collect();
+        let mut scores = vec![(F::Value::NAN, false); candidates.len()];
+
+        // Index into the misses for every candidate not in the cache
+        let mut pending = HashMap::new();
+        let mut missed = Vec::new();
+        let mut lookups = Vec::new();
+        {
+            let mut lru = self.lock();
+            for (i, key) in keys.iter().enumerate() {
+                if let Some(score) = lru.get(key) {
+                    lru.stats.hits += 1;
+                    scores[i] = (score, self.free_hits);
+                } else if let Some(m) = pending.get(key) {
+                    lru.stats.hits += 1;
+                    lookups.push((i, *m, self.free_hits));
+                } else {
+                    lru.stats.misses += 1;
+                    pending.insert(key.clone(), missed.len());
+                    lookups.push((i, missed.len(), false));
+                    missed.push(i);
+                }
+            }
+        }
+
+        if !missed.is_empty() {
+            let batch: Vec<_> = missed.iter().map(|i| candidates[*i].clone()).collect();
+            let fresh = self.fit_fn.score_batch_metered(&batch);
+            assert_eq!(fresh.len(), batch.len(), "Expected a score per candidate");
+
+            let mut lru = self.lock();
+            for (slot, idx) in missed.iter().enumerate() {
+                Cached::<F, K>::store(&mut lru, keys[*idx].clone(), fresh[slot].0);
+            }
+            for (i, m, free) in lookups {
+                scores[i] = (fresh[m].0, free);
+            }
+        }
+        scores
+    }
+
+    fn direction(&self) -> Direction {
+        self.fit_fn.direction()
+    }
+}
+
+#[cfg(test)]
+mod test_cache {
+    use std::sync::atomic::{AtomicUsize,Ordering};
+
+    use rand_distr::Normal;
+
+    use super::*;
+    use crate::Optimizer;
+    use crate::exp::*;
+    use crate::termination::{Any,Stagnation};
+    use crate::ga::fastga::FastGA;
+    use crate::ga::genome::Continuous;
+    use crate::ga::mutator::ContinuousMutator;
+    use crate::ga::crossover::{CrossoverType,Linear};
+    use crate::ga::selector::Tournament;
+
+    /// Matyas which counts how many candidates it has actually scored
+    struct Counted(AtomicUsize);
+
+    impl Fitness for Counted {
+        type Data = Vec<f32>;
+        type Value = f32;
+
+        fn score(&self, candidate: &Vec<f32>) -> f32 {
+            self.0.fetch_add(1, Ordering::SeqCst);
+            if candidate[0].is_nan() { f32::NAN } else { MatyasEnv(-10., 10.).score(candidate) }
+        }
+    }
+
+    fn counted() -> Counted {
+        Counted(AtomicUsize::new(0))
+    }
+
+    #[test]
+    fn test_lru() {
+        let cache = Cached::new(counted(), Bitwise, 2);
+        let (a, b, c) = (vec![1., 2.], vec![3., 4.], vec![5., 6.]);
+        let scores = cache.score_batch(&[a.clone(), b.clone(), a.clone()]);
+        assert_eq!(scores[0], scores[2]);
+        assert_eq!(cache.inner().0.load(Ordering::SeqCst), 2);
+        assert_eq!(cache.stats(), CacheStats { hits: 1, misses: 2, evictions: 0 });
+
+        // Touching a keeps it, so c evicts b
+        cache.score(&a);
+        cache.score(&c);
+        assert_eq!(cache.stats().evictions, 1);
+        cache.score(&a);
+        cache.score(&b);
+        assert_eq!(cache.stats(), CacheStats { hits: 3, misses: 4, evictions: 2 });
+        assert_eq!(cache.len(), 2);
+
+        // Failures are rescored
+        let nan = vec![f32::NAN, 0.];
+        assert!(cache.score(&nan).is_nan());
+        assert!(cache.score(&nan).is_nan());
+        assert_eq!(cache.stats().misses, 6);
+
+        let grid = Cached::new(counted(), Quantized(0.5), 10);
+        assert_eq!(grid.score(&vec![1., 1.]), grid.score(&vec![1.1, 0.9]));
+        assert_eq!(grid.stats().hits, 1);
+    }
+
+    #[test]
+    fn test_free_hits() {
+        let ga: FastGA<_,_,_,_,Vec<f32>> = FastGA::new(
+            50,
+            true,
+            1.,
+            Continuous::new(2, Normal::new(0., 1f32).unwrap()),
+            ContinuousMutator::new(Normal::new(0., 1f32).unwrap()),
+            Linear::new(CrossoverType::TwoPoint),
+            Tournament(4));
+
+        // A coarse grid makes repeats common
+        let charged = Cached::new(counted(), Quantized(1.), 1000);
+        let result = ga.fit(&charged, 3000, 2020, None, |_stats, _fns_remaining| {});
+        let stats = charged.stats();
+        assert!(stats.hits > 0);
+        assert_eq!(result.evaluations, stats.hits + stats.misses);
+
+        let free = Cached::new(counted(), Quantized(1.), 1000).free_hits();
+        let termination = Any(3000, Stagnation::new(100, 0.));
+        let result = ga.fit(&free, termination, 2020, None, |_stats, _fns_remaining| {});
+        let stats = free.stats();
+        assert!(stats.hits > 0);
+        assert_eq!(result.evaluations, stats.misses);
+        assert_eq!(free.inner().0.load(Ordering::SeqCst), stats.misses);
+    }
+}
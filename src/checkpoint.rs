@@ -0,0 +1,258 @@
+use std::fs::{self,File};
+use std::io::{self,BufReader,BufWriter};
+use std::path::{Path,PathBuf};
+use std::time::{Duration,Instant};
+
+use serde::{Deserialize,Serialize};
+use serde::de::DeserializeOwned;
+
+use crate::{AskTell,Control,Direction,Fitness,GenerationStats,OptimizationResult,Real,Termination,UpdateMode};
+use crate::objective::Oriented;
+
+/// How often a run saves itself
+#[derive(Clone,Copy,Debug,PartialEq,Eq)]
+pub enum Period {
+    /// After every N generations
+    Generations(usize),
+
+    /// Once at least this much wall clock time has passed since the last save
+    Elapsed(Duration)
+}
+
+/// Where and how often a run is checkpointed.  Each checkpoint replaces the
+/// last one at `path`.
+#[derive(Clone,Debug)]
+pub struct CheckpointPolicy {
+    pub path: PathBuf,
+    pub every: Period
+}
+
+impl CheckpointPolicy {
+
+    pub fn new<P: Into<PathBuf>>(path: P, every: Period) -> Self {
+        CheckpointPolicy { path: path.into(), every }
+    }
+
+    /// Builds the hook `drive_from` calls after each generation
+    fn hook<'a, S: Serialize, V: Real>(
+        &'a self,
+        direction: Direction
+    ) -> impl FnMut(&S, &[GenerationStats<(), V>]) -> io::Result<()> + 'a {
+        let mut generations = 0;
+        let mut last = Instant::now();
+        move |state, history| {
+            generations += 1;
+            let due = match self.every {
+                Period::Generations(n) => generations >= n,
+                Period::Elapsed(interval) => last.elapsed() >= interval
+            };
+            if due {
+                generations = 0;
+                last = Instant::now();
+                save(&self.path, &CheckpointRef { state, direction, history })?;
+            }
+            Ok(())
+        }
+    }
+}
+
+/// Everything needed to carry a run on: the optimizer's state along with the
+/// history `fit` has collected so far.  The termination criteria aren't
+/// saved; they're supplied again on resume.
+#[derive(Debug,Serialize,Deserialize)]
+pub struct Checkpoint<S, V = f32> {
+    pub state: S,
+    pub direction: Direction,
+    pub history: Vec<GenerationStats<(), V>>
+}
+
+/// Borrowed form of `Checkpoint`, which serializes identically
+#[derive(Serialize)]
+struct CheckpointRef<'a, S, V> {
+    state: &'a S,
+    direction: Direction,
+    history: &'a [GenerationStats<(), V>]
+}
+
+impl <S: DeserializeOwned, V: Real> Checkpoint<S, V> {
+
+    pub fn load<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        let reader = BufReader::new(File::open(path)?);
+        bincode::deserialize_from(reader)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+    }
+}
+
+impl <S: Serialize, V: Real> Checkpoint<S, V> {
+
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        save(path.as_ref(), self)
+    }
+}
+
+/// Writes to a temporary file first, so a crash mid-write never clobbers the
+/// last good checkpoint
+fn save<C: Serialize>(path: &Path, checkpoint: &C) -> io::Result<()> {
+    let tmp = path.with_extension("tmp");
+    {
+        let mut writer = BufWriter::new(File::create(&tmp)?);
+        bincode::serialize_into(&mut writer, checkpoint)
+            .map_err(io::Error::other)?;
+        writer.into_inner()?.sync_all()?;
+    }
+    fs::rename(tmp, path)
+}
+
+/// Ask/tell optimizers whose runs can be saved and resumed.  Resuming
+/// continues bit for bit where the checkpoint left off, so long as neither
+/// the termination criteria nor the fitness function keep state of their
+/// own.  Criteria are handed in afresh on resume, so a `Stagnation` count
+/// starts over, and a `Cached` fitness starts out empty, charging again for
+/// scores it would have served free.  Time based criteria also depend on
+/// how long the run actually took.  Steady-state runs can't be checkpointed.
+pub trait Checkpointed: AskTell
+where
+    Self::State: Serialize + DeserializeOwned
+{
+    /// Runs like `Optimizer::fit`, checkpointing according to `policy`
+    fn fit_checkpointed<F, T, FN, R>(
+        &self,
+        fit_fn: &F,
+        termination: T,
+        seed: u64,
+        x_in: Option<&Self::Data>,
+        policy: &CheckpointPolicy,
+        callback: FN
+    ) -> io::Result<OptimizationResult<Self::Data, (), Self::Value>>
+    where
+        F: Fitness<Data=Self::Data, Value=Self::Value>,
+        T: Termination<Self::Data, Self::Value> + 'static,
+        FN: FnMut(Self::Stats, Option<usize>) -> R,
+        R: Into<Control>
+    {
+        steady_unsupported(self)?;
+        let direction = fit_fn.direction();
+        let state = self.init(Oriented(termination, direction), seed, x_in);
+        crate::drive_from(self, direction, state, Vec::new(), callback,
+                          |opt, state, candidates, _errors| {
+            crate::evaluate(opt, fit_fn, state, candidates);
+            Ok(())
+        }, policy.hook(direction))
+    }
+
+    /// Picks a run back up from the checkpoint at `policy.path`, carrying on
+    /// checkpointing there.  The optimizer needs to be configured as it was
+    /// for the original run.  Budgets and time limits count what was spent
+    /// before the checkpoint; `termination` otherwise starts fresh.
+    fn resume<F, T, FN, R>(
+        &self,
+        fit_fn: &F,
+        termination: T,
+        policy: &CheckpointPolicy,
+        callback: FN
+    ) -> io::Result<OptimizationResult<Self::Data, (), Self::Value>>
+    where
+        F: Fitness<Data=Self::Data, Value=Self::Value>,
+        T: Termination<Self::Data, Self::Value> + 'static,
+        FN: FnMut(Self::Stats, Option<usize>) -> R,
+        R: Into<Control>
+    {
+        steady_unsupported(self)?;
+        let Checkpoint { mut state, direction, history } = Checkpoint::load(&policy.path)?;
+        if direction != fit_fn.direction() {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput,
+                                      "Checkpoint was taken with a different objective direction"))
+        }
+
+        self.ledger_mut(&mut state).set_termination(Oriented(termination, direction));
+        crate::drive_from(self, direction, state, history, callback,
+                          |opt, state, candidates, _errors| {
+            crate::evaluate(opt, fit_fn, state, candidates);
+            Ok(())
+        }, policy.hook(direction))
+    }
+}
+
+impl <O: AskTell> Checkpointed for O where O::State: Serialize + DeserializeOwned {}
+
+/// Steady-state runs have evaluations out at any moment, so there's never a
+/// settled state to save
+fn steady_unsupported<O: AskTell>(opt: &O) -> io::Result<()> {
+    if opt.update_mode() == UpdateMode::SteadyState {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput,
+                                  "Steady-state runs can't be checkpointed"))
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test_checkpoint {
+    use rand_distr::StandardNormal;
+
+    use super::*;
+    use crate::Optimizer;
+    use crate::de::*;
+    use crate::exp::*;
+
+    #[test]
+    fn test_save_load() {
+        let path = std::env::temp_dir().join("darkopt_test_save_load.ckpt");
+        let checkpoint: Checkpoint<_> = Checkpoint {
+            state: vec![1f32, f32::NEG_INFINITY],
+            direction: Direction::Minimize,
+            history: Vec::new()
+        };
+        checkpoint.save(&path).unwrap();
+        let loaded: Checkpoint<Vec<f32>> = Checkpoint::load(&path).unwrap();
+        assert_eq!(loaded.state, checkpoint.state);
+        assert_eq!(loaded.direction, Direction::Minimize);
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_resume_de() {
+        let de = DePlus::builder(2, StandardNormal)
+            .lambda(30)
+            .f(0.1, 1.)
+            .polish_on_stale(5)
+            .restart_on_stale(15)
+            .build().unwrap();
+
+        let fit_fn = RastriginEnv { dims: 2 };
+        let path = std::env::temp_dir().join("darkopt_test_resume_de.ckpt");
+        let policy = CheckpointPolicy::new(&path, Period::Generations(7));
+        let expected = de.fit(&fit_fn, 6000, 2020, None, |_stats, _fns_remaining| {});
+
+        // Crash partway through, then pick up from the last checkpoint
+        let crashed = de.fit_checkpointed(&fit_fn, 6000, 2020, None, &policy, |stats, _| {
+            if stats.generation == 100 { Control::Stop } else { Control::Continue }
+        }).unwrap();
+        assert_eq!(crashed.generations, 100);
+
+        let result = de.resume(&fit_fn, 6000, &policy, |_stats, _fns_remaining| {}).unwrap();
+        assert_eq!(result.population, expected.population);
+        assert_eq!(result.population_fitness, expected.population_fitness);
+        assert_eq!(result.candidate, expected.candidate);
+        assert_eq!(result.evaluations, expected.evaluations);
+        assert_eq!(result.restarts, expected.restarts);
+        assert_eq!(result.history.len(), expected.history.len());
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_steady_state_refused() {
+        let de = DePlus::builder(2, StandardNormal)
+            .lambda(30)
+            .update(UpdateMode::SteadyState)
+            .build().unwrap();
+
+        let fit_fn = RastriginEnv { dims: 2 };
+        let path = std::env::temp_dir().join("darkopt_test_steady_state_refused.ckpt");
+        let policy = CheckpointPolicy::new(&path, Period::Generations(7));
+        let err = de.fit_checkpointed(&fit_fn, 6000, 2020, None, &policy, |_, _| {}).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+        let err = de.resume(&fit_fn, 6000, &policy, |_, _| {}).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+        assert!(!path.exists());
+    }
+}
@@ -0,0 +1,241 @@
+#[cfg(feature = "serde")]
+use std::convert::TryFrom;
+use std::error::Error;
+use std::fmt;
+
+use rand::Rng;
+use rand_distr::{Distribution,Normal,Uniform};
+
+#[cfg(feature = "serde")]
+use serde::{Deserialize,Serialize};
+
+use crate::Real;
+
+/// Why an optimizer's configuration was rejected
+#[derive(Clone,Debug,PartialEq,Eq)]
+pub struct ConfigError {
+    /// Offending setting
+    pub field: &'static str,
+    pub reason: String
+}
+
+impl ConfigError {
+    pub fn new<S: Into<String>>(field: &'static str, reason: S) -> Self {
+        ConfigError { field, reason: reason.into() }
+    }
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Invalid {}: {}", self.field, self.reason)
+    }
+}
+
+impl Error for ConfigError {}
+
+/// Fails with `reason` unless `cond` holds
+pub(crate) fn check(cond: bool, field: &'static str, reason: &str) -> Result<(), ConfigError> {
+    if cond { Ok(()) } else { Err(ConfigError::new(field, reason)) }
+}
+
+/// Checks a (low, high) range is finite and strictly ordered
+pub(crate) fn check_range<T: Real>(field: &'static str, (low, high): (T, T)) -> Result<(), ConfigError> {
+    check(low.is_finite() && high.is_finite(), field, "needs finite limits")?;
+    check(low < high, field, "low needs to be below high")
+}
+
+/// Checks `x` is a probability
+pub(crate) fn check_prob(field: &'static str, x: f32) -> Result<(), ConfigError> {
+    check((0. ..=1.).contains(&x), field, "needs to be within [0, 1]")
+}
+
+/// Distributions which can be written down in a config file, for seeding
+/// populations and perturbing genes
+#[derive(Clone,Copy,Debug,PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize,Deserialize), serde(try_from = "SamplerDef"))]
+pub enum Sampler {
+    Normal { mean: f64, std_dev: f64 },
+    Uniform { low: f64, high: f64 }
+}
+
+impl Sampler {
+
+    pub fn normal(mean: f64, std_dev: f64) -> Result<Self, ConfigError> {
+        Sampler::Normal { mean, std_dev }.validate()
+    }
+
+    pub fn uniform(low: f64, high: f64) -> Result<Self, ConfigError> {
+        Sampler::Uniform { low, high }.validate()
+    }
+
+    fn validate(self) -> Result<Self, ConfigError> {
+        match self {
+            Sampler::Normal { mean, std_dev } => {
+                check(mean.is_finite(), "mean", "needs to be finite")?;
+                // Zero spread is allowed and always draws the mean
+                check(std_dev.is_finite() && std_dev >= 0., "std_dev", "needs to be non-negative")?;
+            },
+            Sampler::Uniform { low, high } => {
+                check(low.is_finite() && high.is_finite(), "low", "needs finite limits")?;
+                check(low < high, "low", "needs to be below high")?;
+            }
+        }
+        Ok(self)
+    }
+}
+
+impl Distribution<f64> for Sampler {
+    fn sample<R: Rng + ?Sized>(&self, rng: &mut R) -> f64 {
+        match *self {
+            Sampler::Normal { mean, std_dev } => {
+                Normal::new(mean, std_dev).expect("Invalid normal").sample(rng)
+            },
+            Sampler::Uniform { low, high } => Uniform::new(low, high).sample(rng)
+        }
+    }
+}
+
+impl Distribution<f32> for Sampler {
+    fn sample<R: Rng + ?Sized>(&self, rng: &mut R) -> f32 {
+        Distribution::<f64>::sample(self, rng) as f32
+    }
+}
+
+/// Unchecked form of `Sampler`, validated on the way in
+#[cfg(feature = "serde")]
+#[derive(Deserialize)]
+#[serde(rename = "Sampler")]
+enum SamplerDef {
+    Normal { mean: f64, std_dev: f64 },
+    Uniform { low: f64, high: f64 }
+}
+
+#[cfg(feature = "serde")]
+impl TryFrom<SamplerDef> for Sampler {
+    type Error = ConfigError;
+
+    fn try_from(def: SamplerDef) -> Result<Self, ConfigError> {
+        match def {
+            SamplerDef::Normal { mean, std_dev } => Sampler::normal(mean, std_dev),
+            SamplerDef::Uniform { low, high } => Sampler::uniform(low, high)
+        }
+    }
+}
+
+#[cfg(test)]
+mod test_config {
+    use rand_distr::{Normal,StandardNormal};
+
+    use super::*;
+    use crate::{Bounds,Optimizer,Repair};
+    use crate::de::*;
+    use crate::exp::*;
+    use crate::pso::PSO;
+    use crate::ga::fastga::FastGA;
+    use crate::ga::genome::Continuous;
+    use crate::ga::mutator::{Bounded,ContinuousMutator};
+    use crate::ga::crossover::{CrossoverType as GaCrossover,Linear};
+    use crate::ga::selector::Tournament;
+
+    fn field<T: std::fmt::Debug>(result: Result<T, ConfigError>) -> &'static str {
+        result.expect_err("Config should be rejected").field
+    }
+
+    #[test]
+    fn test_builders() {
+        let de = DePlus::builder(2, StandardNormal).lambda(30).f(0.1, 1.).build().unwrap();
+        let result = de.fit(&MatyasEnv(-10., 10.), 3000, 2020, None, |_stats, _fns_remaining| {});
+        assert!(result.fitness > -1e-3);
+
+        assert_eq!(field(DePlus::<_>::builder(1, StandardNormal).build()), "dims");
+        assert_eq!(field(DePlus::<_>::builder(2, StandardNormal).lambda(1).build()), "lambda");
+        assert_eq!(field(DePlus::<_>::builder(2, StandardNormal).f(0.9, 0.1).build()), "f");
+        assert_eq!(field(DePlus::<_>::builder(2, StandardNormal).cr(CrossoverType::Uniform(1.5)).build()), "cr");
+        let bounds = Bounds::uniform(3, -1., 1., Repair::Clip);
+        assert_eq!(field(DePlus::builder(2, StandardNormal).bounds(bounds).build()), "bounds");
+
+        let pso = PSO::<f32>::builder(2).x_range(-20., 20.).build().unwrap();
+        assert_eq!(pso.swarm_size(), 30);
+        assert_eq!(field(PSO::<f32>::builder(2).swarm_size(0).build()), "swarm_size");
+        assert_eq!(field(PSO::builder(2).x_range(1., -1.).build()), "x_range");
+
+        let ga = |dims, k| FastGA::builder(
+            Continuous::new(dims, Normal::new(0., 1f32).unwrap()),
+            ContinuousMutator::new(Normal::new(0., 1f32).unwrap()),
+            Linear::<f32>::new(GaCrossover::TwoPoint),
+            Tournament(k));
+        assert!(ga(2, 4).lambda(50).build::<_, f32>().is_ok());
+        assert_eq!(field(ga(2, 4).lambda(0).build::<_, f32>()), "lambda");
+        assert_eq!(field(ga(1, 4).build::<_, f32>()), "dims");
+        assert_eq!(field(ga(2, 0).build::<_, f32>()), "tournament");
+
+        let bounded = |dims, bounds| FastGA::builder(
+            Continuous::new(dims, Normal::new(0., 1f32).unwrap()),
+            Bounded(ContinuousMutator::new(Normal::new(0., 1f32).unwrap()), bounds),
+            Linear::<f32>::new(GaCrossover::TwoPoint),
+            Tournament(4));
+        assert!(bounded(2, Bounds::uniform(2, -1., 1., Repair::Clip)).build::<_, f32>().is_ok());
+        assert_eq!(field(bounded(3, Bounds::uniform(2, -1., 1., Repair::Clip)).build::<_, f32>()), "mutator");
+        let unordered = Bounds { lower: vec![1., 1.], upper: vec![0., 2.], repair: Repair::Clip };
+        assert_eq!(field(bounded(2, unordered).build::<_, f32>()), "bounds");
+
+        assert_eq!(field(Sampler::uniform(1., 1.)), "low");
+        assert_eq!(field(Bounds::try_new(vec![1.], vec![0.], Repair::Clip)), "bounds");
+        assert_eq!(field(Bounds::try_new(vec![0.], vec![f32::INFINITY], Repair::Clip)), "bounds");
+        let unpaired = Bounds { lower: vec![0., 0.], upper: vec![1.], repair: Repair::Clip };
+        assert_eq!(field(DePlus::builder(2, StandardNormal).bounds(unpaired).build()), "bounds");
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_load() {
+        let de: DePlus<Sampler> = toml::from_str(r#"
+            dims = 2
+            lambda = 30
+            f = [0.1, 1.0]
+            cr = { Uniform = 0.9 }
+            restart_on_stale = 20
+            init_dist = { Normal = { mean = 0.0, std_dev = 1.0 } }
+            execution = { Threads = 2 }
+
+            [bounds]
+            lower = [-10.0, -10.0]
+            upper = [10.0, 10.0]
+            repair = "Reflect"
+        "#).unwrap();
+        assert_eq!(de.lambda, 30);
+        assert_eq!(de.m, 0.1);
+        let result = de.fit(&MatyasEnv(-10., 10.), 3000, 2020, None, |_stats, _fns_remaining| {});
+        assert!(result.fitness > -1e-3);
+
+        // What's written out reads back in as the same optimizer
+        let reloaded: DePlus<Sampler> = serde_json::from_str(&serde_json::to_string(&de).unwrap()).unwrap();
+        let expected = reloaded.fit(&MatyasEnv(-10., 10.), 3000, 2020, None, |_stats, _fns_remaining| {});
+        assert_eq!(result.candidate, expected.candidate);
+
+        let pso: PSO = serde_json::from_str(r#"{ "dims": 2, "x_range": [-20, 20] }"#).unwrap();
+        assert_eq!(pso.swarm_size(), 30);
+
+        type Ga = FastGA<Continuous<Sampler>, ContinuousMutator<Sampler>, Linear<f32>, Tournament, Vec<f32>>;
+        let ga: Ga = serde_json::from_str(r#"{
+            "lambda": 50,
+            "genome": { "dims": 2, "dist": { "Normal": { "mean": 0, "std_dev": 1 } } },
+            "mutator": { "Uniform": { "low": -0.5, "high": 0.5 } },
+            "cross_over": "TwoPoint",
+            "selector": 4,
+            "execution": "Sequential"
+        }"#).unwrap();
+        assert!(ga.elitism);
+        let reloaded: Ga = serde_json::from_str(&serde_json::to_string(&ga).unwrap()).unwrap();
+        assert_eq!(reloaded.lambda, 50);
+
+        // Configs go through the same checks as the builders
+        let err = serde_json::from_str::<PSO>(r#"{ "dims": 2, "swarm_size": 0 }"#).unwrap_err();
+        assert!(err.to_string().contains("swarm_size"));
+        let err = toml::from_str::<DePlus<Sampler>>(r#"
+            dims = 2
+            init_dist = { Uniform = { low = 1.0, high = -1.0 } }
+        "#).unwrap_err();
+        assert!(err.to_string().contains("low"));
+    }
+}
@@ -0,0 +1,325 @@
+use std::cmp::Ordering;
+
+use rand::prelude::*;
+
+#[cfg(feature = "serde")]
+use serde::{Deserialize,Serialize};
+
+use crate::config::{ConfigError,check,check_prob};
+use crate::{AskTell,Control,Direction,Minimize,OptimizationResult,Real,Termination};
+
+/// A fitness function with constraints reported separately from the score,
+/// rather than folded in as hand tuned penalties.
+pub trait ConstrainedFitness: Send + Sync {
+    type Data: Send + Sync;
+
+    /// Precision of the scores and violations, usually `f32`
+    type Value: Real;
+
+    /// Objective to maximize
+    fn score(&self, candidate: &Self::Data) -> Self::Value;
+
+    /// Inequality constraints, each satisfied when at or below zero
+    fn inequalities(&self, _candidate: &Self::Data) -> Vec<Self::Value> {
+        Vec::new()
+    }
+
+    /// Equality constraints, each satisfied when within `equality_tol` of zero
+    fn equalities(&self, _candidate: &Self::Data) -> Vec<Self::Value> {
+        Vec::new()
+    }
+
+    /// Slack allowed on the equality constraints
+    fn equality_tol(&self) -> Self::Value {
+        Self::Value::from_f64(1e-4)
+    }
+
+    /// Total amount by which the candidate violates its constraints.  Zero
+    /// means feasible.
+    fn violation(&self, candidate: &Self::Data) -> Self::Value {
+        let tol = self.equality_tol();
+        let g = self.inequalities(candidate).into_iter()
+            .map(|gi| gi.max(Self::Value::ZERO))
+            .sum::<Self::Value>();
+        let h = self.equalities(candidate).into_iter()
+            .map(|hi| (hi.abs() - tol).max(Self::Value::ZERO))
+            .sum::<Self::Value>();
+        g + h
+    }
+
+    /// Scores a whole batch, returning the fitnesses and the violations
+    fn evaluate_batch(&self, candidates: &[Self::Data]) -> (Vec<Self::Value>, Vec<Self::Value>) {
+        crate::execution::map_batch(candidates, |c| (self.score(c), self.violation(c)))
+            .into_iter()
+            .unzip()
+    }
+
+    /// Direction of the user's objective
+    fn direction(&self) -> Direction {
+        Direction::Maximize
+    }
+}
+
+impl <C: ConstrainedFitness> ConstrainedFitness for Minimize<C> {
+    type Data = C::Data;
+    type Value = C::Value;
+
+    fn score(&self, candidate: &C::Data) -> C::Value {
+        -self.0.score(candidate)
+    }
+
+    fn violation(&self, candidate: &C::Data) -> C::Value {
+        self.0.violation(candidate)
+    }
+
+    fn evaluate_batch(&self, candidates: &[C::Data]) -> (Vec<C::Value>, Vec<C::Value>) {
+        let (mut scores, violations) = self.0.evaluate_batch(candidates);
+        scores.iter_mut().for_each(|s| *s = -*s);
+        (scores, violations)
+    }
+
+    fn direction(&self) -> Direction {
+        self.0.direction().flip()
+    }
+}
+
+/// How candidates with constraint violations are compared.  Without any
+/// violations, every mode reduces to comparing fitness.
+#[derive(Clone,Copy,Debug,PartialEq,Default)]
+#[cfg_attr(feature = "serde", derive(Serialize,Deserialize))]
+pub enum ConstraintHandling {
+    /// Deb's feasibility rules: feasible beats infeasible, feasible candidates
+    /// compare on fitness and infeasible ones on violation.
+    #[default]
+    Feasibility,
+
+    /// Feasibility rules where violations up to epsilon count as feasible.
+    /// Epsilon starts at `level` and shrinks to zero over `generations`
+    /// generations, following `(1 - t / generations)^cp`.
+    Epsilon { level: f32, generations: usize, cp: f32 },
+
+    /// Runarsson and Yao's stochastic ranking: infeasible candidates are
+    /// compared on fitness with probability `pf`, otherwise on violation.
+    /// 0.45 is a good default.
+    StochasticRanking { pf: f32 }
+}
+
+impl ConstraintHandling {
+
+    /// Checks the mode's parameters are in range
+    pub fn validate(&self) -> Result<(), ConfigError> {
+        match *self {
+            ConstraintHandling::Feasibility => Ok(()),
+            ConstraintHandling::Epsilon { level, cp, .. } => {
+                check(level >= 0., "constraints", "epsilon level needs to be non-negative")?;
+                check(cp > 0., "constraints", "epsilon cp needs to be positive")
+            },
+            ConstraintHandling::StochasticRanking { pf } => check_prob("constraints", pf)
+        }
+    }
+
+    /// Violation under which a candidate counts as feasible at a generation
+    pub fn epsilon(&self, generation: usize) -> f32 {
+        match *self {
+            ConstraintHandling::Epsilon { level, generations, cp } if generation < generations => {
+                level * (1. - generation as f32 / generations as f32).powf(cp)
+            },
+            _ => 0.
+        }
+    }
+
+    /// Whether candidate a, with fitness and violation, beats candidate b
+    pub fn better<V: Real, R: Rng>(
+        &self,
+        a: (V, V),
+        b: (V, V),
+        generation: usize,
+        rng: &mut R
+    ) -> bool {
+        match *self {
+            ConstraintHandling::StochasticRanking { pf } => {
+                let both_feasible = a.1 <= V::ZERO && b.1 <= V::ZERO;
+                if both_feasible || rng.gen::<f32>() < pf {
+                    a.0 > b.0
+                } else {
+                    a.1 < b.1
+                }
+            },
+            _ => {
+                let eps = V::from_f32(self.epsilon(generation));
+                rank_cmp(a, b, eps) == Ordering::Greater
+            }
+        }
+    }
+
+    /// Index of the best candidate.  Stochastic ranking picks it with the
+    /// feasibility rules so the choice is stable.
+    pub fn best_index<V: Real>(&self, fitnesses: &[V], violations: &[V], generation: usize) -> usize {
+        let eps = V::from_f32(self.epsilon(generation));
+        (0..fitnesses.len())
+            .max_by(|i, j| rank_cmp((fitnesses[*i], violations[*i]), (fitnesses[*j], violations[*j]), eps))
+            .expect("Population should never be empty!")
+    }
+
+    /// Orders the population best to worst and returns a surrogate fitness for
+    /// each candidate: its rank, higher being better.
+    pub fn rank<V: Real, R: Rng>(
+        &self,
+        fitnesses: &[V],
+        violations: &[V],
+        generation: usize,
+        rng: &mut R
+    ) -> Vec<V> {
+        let n = fitnesses.len();
+        let mut order: Vec<_> = (0..n).collect();
+        match *self {
+            ConstraintHandling::StochasticRanking { .. } => {
+                // Bubble sort where each comparison is stochastic
+                for _ in 0..n {
+                    let mut swapped = false;
+                    for j in 0..n.saturating_sub(1) {
+                        let (a, b) = (order[j], order[j + 1]);
+                        let a_v = (fitnesses[a], violations[a]);
+                        let b_v = (fitnesses[b], violations[b]);
+                        if self.better(b_v, a_v, generation, rng) {
+                            order.swap(j, j + 1);
+                            swapped = true;
+                        }
+                    }
+                    if !swapped {
+                        break
+                    }
+                }
+            },
+            _ => {
+                let eps = V::from_f32(self.epsilon(generation));
+                order.sort_by(|i, j| {
+                    rank_cmp((fitnesses[*j], violations[*j]), (fitnesses[*i], violations[*i]), eps)
+                });
+            }
+        }
+
+        let mut ranks = vec![V::ZERO; n];
+        order.into_iter().enumerate().for_each(|(pos, i)| ranks[i] = V::from_usize(n - pos));
+        ranks
+    }
+}
+
+/// Orders candidates by the feasibility rules: violations within epsilon are
+/// ignored, then lower violation is better, then higher fitness.
+fn rank_cmp<V: Real>(a: (V, V), b: (V, V), eps: V) -> Ordering {
+    let v = |c: (V, V)| if c.1 <= eps { V::ZERO } else { c.1 };
+    (-v(a)).cmp_float(&-v(b)).then(a.0.cmp_float(&b.0))
+}
+
+/// Whether a beats b under Deb's feasibility rules.  Used for tracking the best
+/// candidate of a run, whichever handling is used for selection.
+pub(crate) fn feasibility_better<V: Real>(a: (V, V), b: (V, V)) -> bool {
+    rank_cmp(a, b, V::ZERO) == Ordering::Greater
+}
+
+/// Indices of the `n` fittest candidates under the feasibility rules, best first
+pub(crate) fn fittest<V: Real>(fitnesses: &[V], violations: &[V], n: usize) -> Vec<usize> {
+    let mut order: Vec<_> = (0..fitnesses.len()).collect();
+    order.sort_by(|i, j| {
+        rank_cmp((fitnesses[*j], violations[*j]), (fitnesses[*i], violations[*i]), V::ZERO)
+    });
+    order.truncate(n);
+    order
+}
+
+/// Ask/tell optimizers which can select on constraint violations
+pub trait ConstrainedAskTell: AskTell {
+
+    /// Reports the fitnesses and violations for the batch returned by the last `ask`
+    fn tell_constrained(
+        &self,
+        state: &mut Self::State,
+        fitnesses: Vec<Self::Value>,
+        violations: Vec<Self::Value>
+    );
+
+    /// Runs like `Optimizer::fit`, comparing candidates with the optimizer's
+    /// constraint handling
+    fn fit_constrained<C, T, FN, R>(
+        &self,
+        fit_fn: &C,
+        termination: T,
+        seed: u64,
+        x_in: Option<&Self::Data>,
+        callback: FN
+    ) -> OptimizationResult<Self::Data, (), Self::Value>
+    where
+        C: ConstrainedFitness<Data=Self::Data, Value=Self::Value>,
+        T: Termination<Self::Data, Self::Value> + 'static,
+        FN: FnMut(Self::Stats, Option<usize>) -> R,
+        R: Into<Control>
+    {
+        run(self, fit_fn, termination, seed, x_in, callback)
+    }
+}
+
+/// Runs a constrained ask/tell optimizer to completion, scoring each batch at once
+pub(crate) fn run<O, C, T, FN, R>(
+    opt: &O,
+    fit_fn: &C,
+    termination: T,
+    seed: u64,
+    x_in: Option<&O::Data>,
+    callback: FN
+) -> OptimizationResult<O::Data, (), O::Value>
+where
+    O: ConstrainedAskTell,
+    C: ConstrainedFitness<Data=O::Data, Value=O::Value>,
+    T: Termination<O::Data, O::Value> + 'static,
+    FN: FnMut(O::Stats, Option<usize>) -> R,
+    R: Into<Control>
+{
+    let result = crate::drive(opt, fit_fn.direction(), termination, seed, x_in, callback,
+                              |opt, state, candidates, _errors| {
+        let (mut fitnesses, violations): (Vec<_>, Vec<_>) = opt.execution().batch(
+            candidates,
+            |cs| {
+                let (fitnesses, violations) = fit_fn.evaluate_batch(cs);
+                fitnesses.into_iter().zip(violations).collect()
+            }).into_iter().unzip();
+        assert_eq!(fitnesses.len(), candidates.len(), "Expected a score per candidate");
+        let failures = crate::fallible::scrub(&mut fitnesses);
+        opt.ledger_mut(state).fail(failures);
+        opt.tell_constrained(state, fitnesses, violations);
+        Ok(())
+    });
+    result.unwrap_or_else(|()| unreachable!("Infallible evaluation failed"))
+}
+
+#[cfg(test)]
+mod test_constraints {
+    use super::*;
+
+    #[test]
+    fn test_feasibility_rules() {
+        let mut rng = StdRng::seed_from_u64(2020);
+        let deb = ConstraintHandling::Feasibility;
+        assert!(deb.better((1., 0.), (2., 0.5), 0, &mut rng));
+        assert!(deb.better((1., 0.1), (2., 0.5), 0, &mut rng));
+        assert!(deb.better((2., 0.), (1., 0.), 0, &mut rng));
+
+        let eps = ConstraintHandling::Epsilon { level: 1., generations: 10, cp: 2. };
+        assert!(eps.better((2., 0.5), (1., 0.), 0, &mut rng));
+        assert!(!eps.better((2., 0.5), (1., 0.), 10, &mut rng));
+        assert_eq!(eps.epsilon(5), 0.25);
+    }
+
+    #[test]
+    fn test_rank() {
+        let mut rng = StdRng::seed_from_u64(2020);
+        let fits = [3., 1., 2., 5.];
+        let viols = [0., 0., 0.2, 1.];
+        let ranks = ConstraintHandling::Feasibility.rank(&fits, &viols, 0, &mut rng);
+        assert_eq!(ranks, vec![4., 3., 2., 1.]);
+        assert_eq!(ConstraintHandling::Feasibility.best_index(&fits, &viols, 0), 0);
+
+        let sr = ConstraintHandling::StochasticRanking { pf: 0. };
+        assert_eq!(sr.rank(&fits, &viols, 0, &mut rng), vec![4., 3., 2., 1.]);
+    }
+}
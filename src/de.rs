@@ -2,22 +2,243 @@ extern crate rand;
 extern crate rayon;
 extern crate float_ord;
 
-use float_ord::FloatOrd;
+#[cfg(feature = "serde")]
+use std::convert::TryFrom;
+use std::sync::Arc;
 
 use rand::prelude::*;
-use rand_distr::{Distribution,Normal,Uniform};
-use rayon::prelude::*;
-
-use crate::{Fitness,Optimizer};
+use rand_distr::{Cauchy,Distribution,Normal,Uniform};
+
+#[cfg(feature = "serde")]
+use serde::{Deserialize,Serialize};
+
+use crate::{AskTell,Control,Fitness,Optimizer,OptimizationResult,Real,RestartCause,Termination,TerminationReason};
+use crate::ledger::Ledger;
+use crate::stats::{GenerationStats,diversity};
+use crate::bounds::Bounds;
+use crate::execution::Execution;
+use crate::seed::{SeedSequence,Stream};
+use crate::config::{ConfigError,check,check_prob,check_range};
+use crate::noise::{Estimate,Noise,Sampling,pooled_variance};
+use crate::constraints::{self,ConstrainedAskTell,ConstrainedFitness,ConstraintHandling,feasibility_better,fittest};
+use crate::fallible::{self,FailurePolicy,FallibleFitness};
+use crate::init::{self,Initializer,select};
+use crate::polish::{LocalSearch,Polisher};
+use crate::steady::{self,Kind,Slots,SteadyState,Ticket,UpdateMode};
 
 #[derive(Clone,Copy,Debug)]
+#[cfg_attr(feature = "serde", derive(Serialize,Deserialize))]
 pub enum CrossoverType {
     TwoPoint,
     Uniform(f32)
 }
 
-#[derive(Clone,Copy,Debug)]
-pub struct DePlus<D> {
+/// How each trial's mutant is built.  r1, r2, ... are distinct members
+/// other than the target x, and F is the dithered learning rate.
+#[derive(Clone,Copy,Debug,PartialEq,Default)]
+#[cfg_attr(feature = "serde", derive(Serialize,Deserialize))]
+pub enum MutationStrategy {
+    /// r1 + F * (r2 - r3)
+    Rand1,
+
+    /// r1 + F * (r2 - r3) + F * (r4 - r5)
+    Rand2,
+
+    /// best + F * (r1 - r2)
+    #[default]
+    Best1,
+
+    /// best + F * (r1 - r2) + F * (r3 - r4)
+    Best2,
+
+    /// x + F * (best - x) + F * (r1 - r2)
+    CurrentToBest1,
+
+    /// x + K * (r1 - x) + F * (r2 - r3), with K drawn from [0, 1) per trial
+    CurrentToRand1,
+
+    /// x + F * (pbest - x) + F * (r1 - r2), with pbest drawn from the fittest
+    /// `p` fraction of the population
+    CurrentToPBest1 { p: f32 }
+}
+
+impl MutationStrategy {
+
+    /// Distinct members, other than the target, each trial draws
+    pub fn donors(&self) -> usize {
+        match self {
+            MutationStrategy::Rand1 | MutationStrategy::CurrentToRand1 => 3,
+            MutationStrategy::Rand2 => 5,
+            MutationStrategy::Best2 => 4,
+            MutationStrategy::Best1 | MutationStrategy::CurrentToBest1 |
+                MutationStrategy::CurrentToPBest1 { .. } => 2
+        }
+    }
+
+    pub fn validate(&self) -> Result<(), ConfigError> {
+        if let MutationStrategy::CurrentToPBest1 { p } = self {
+            check(*p > 0. && *p <= 1., "mutation", "p needs to be within (0, 1]")?;
+        }
+        Ok(())
+    }
+
+    /// Mutant's value in dimension i
+    #[allow(clippy::too_many_arguments)]
+    fn mutant<T: Real>(
+        &self,
+        i: usize,
+        x: &[T],
+        best: &[T],
+        pbest: &[T],
+        r: &[&Vec<T>],
+        f: T,
+        k: T
+    ) -> T {
+        let diff = |a: usize, b: usize| r[a][i] - r[b][i];
+        match self {
+            MutationStrategy::Rand1 => r[0][i] + f * diff(1, 2),
+            MutationStrategy::Rand2 => r[0][i] + f * diff(1, 2) + f * diff(3, 4),
+            MutationStrategy::Best1 => best[i] + f * diff(0, 1),
+            MutationStrategy::Best2 => best[i] + f * diff(0, 1) + f * diff(2, 3),
+            MutationStrategy::CurrentToBest1 => x[i] + f * (best[i] - x[i]) + f * diff(0, 1),
+            MutationStrategy::CurrentToRand1 => x[i] + k * (r[0][i] - x[i]) + f * diff(1, 2),
+            MutationStrategy::CurrentToPBest1 { .. } => {
+                x[i] + f * (pbest[i] - x[i]) + f * diff(0, 1)
+            }
+        }
+    }
+}
+
+/// Self-adaptive control of F and CR from the values behind successful
+/// trials, replacing the dithered `f` and fixed crossover rate.  Replaced
+/// parents go into an archive, which current-to-pbest/1 draws its last donor
+/// from as well as the population, so pair these with that strategy.  DE+'s
+/// random perturbations work against them, so turn `m` off too.
+#[derive(Clone,Copy,Debug,PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize,Deserialize))]
+pub enum Adaptation {
+    /// JADE: single means for F and CR, moved towards each generation's
+    /// successes at learning rate `c`.  0.1 is a good default.
+    Jade { c: f32 },
+
+    /// SHADE: a history of `memory` means, each generation's successes
+    /// overwriting the oldest
+    Shade { memory: usize },
+
+    /// L-SHADE: SHADE with the population shrunk linearly from lambda down to
+    /// `min_lambda` as the budget is used.  Needs a budget to shrink over.
+    LShade { memory: usize, min_lambda: usize }
+}
+
+impl Adaptation {
+
+    /// Slots of history kept
+    fn memory(&self) -> usize {
+        match self {
+            Adaptation::Jade { .. } => 1,
+            Adaptation::Shade { memory } | Adaptation::LShade { memory, .. } => *memory
+        }
+    }
+
+    pub fn validate(&self) -> Result<(), ConfigError> {
+        match self {
+            Adaptation::Jade { c } => check(*c > 0. && *c <= 1., "adaptation", "c needs to be within (0, 1]"),
+            Adaptation::Shade { memory } | Adaptation::LShade { memory, .. } => {
+                check(*memory > 0, "adaptation", "needs at least one slot of memory")
+            }
+        }
+    }
+}
+
+/// Success history behind adaptive F and CR
+#[derive(Clone,Debug,Default)]
+#[cfg_attr(feature = "serde", derive(Serialize,Deserialize))]
+struct History {
+    /// Means F and CR are sampled around, one pair per slot
+    f: Vec<f32>,
+    cr: Vec<f32>,
+
+    /// Slot the next successful generation overwrites
+    k: usize,
+
+    /// F and CR each pending trial was built with
+    trial: Vec<(f32, f32)>
+}
+
+impl History {
+
+    fn new(adaptation: Option<&Adaptation>) -> Self {
+        // Configs built without the builder may ask for no memory at all
+        let slots = adaptation.map(|a| a.memory().max(1)).unwrap_or(0);
+        History { f: vec![0.5; slots], cr: vec![0.5; slots], k: 0, trial: Vec::new() }
+    }
+
+    /// Draws F from a Cauchy and CR from a normal around a random slot's means
+    fn sample<R: Rng>(&self, rng: &mut R) -> (f32, f32) {
+        let slot = rng.gen_range(0, self.f.len());
+        let cr = Normal::new(self.cr[slot], 0.1).unwrap().sample(rng).clamp(0., 1.);
+        let cauchy = Cauchy::new(self.f[slot], 0.1).unwrap();
+        let f = loop {
+            let f: f32 = cauchy.sample(rng);
+            if f > 0. {
+                break f.min(1.)
+            }
+        };
+        (f, cr)
+    }
+
+    /// Moves the means towards a generation's successes, given as their F,
+    /// CR and fitness gain
+    fn update(&mut self, adaptation: &Adaptation, successes: &[(f32, f32, f64)]) {
+        if successes.is_empty() {
+            return
+        }
+
+        // JADE weighs successes equally, SHADE by how much they gained.  F
+        // takes the Lehmer mean, which leans towards larger steps, and CR the
+        // plain mean.
+        let total: f64 = successes.iter().map(|s| s.2).sum();
+        let weight = |gain: f64| match adaptation {
+            Adaptation::Jade { .. } => 1.,
+            _ if total > 0. && total.is_finite() => gain / total,
+            _ => 1.
+        };
+        let (num, den) = successes.iter().fold((0., 0.), |(n, d), s| {
+            let (w, f) = (weight(s.2), s.0 as f64);
+            (n + w * f * f, d + w * f)
+        });
+        let f = if den > 0. { (num / den) as f32 } else { 0. };
+        let (sum, weights) = successes.iter().fold((0., 0.), |(n, d), s| {
+            let w = weight(s.2);
+            (n + w * s.1 as f64, d + w)
+        });
+        let cr = (sum / weights) as f32;
+
+        match adaptation {
+            Adaptation::Jade { c } => {
+                self.f[0] = (1. - c) * self.f[0] + c * f;
+                self.cr[0] = (1. - c) * self.cr[0] + c * cr;
+            },
+            _ => {
+                self.f[self.k] = f;
+                self.cr[self.k] = cr;
+                self.k = (self.k + 1) % self.f.len();
+            }
+        }
+    }
+}
+
+/// Draws `n` distinct members of a population of `lambda`, never the target
+fn donors<R: Rng>(rng: &mut R, lambda: usize, target: usize, n: usize) -> Vec<usize> {
+    rand::seq::index::sample(rng, lambda - 1, n).into_iter()
+        .map(|j| if j >= target { j + 1 } else { j })
+        .collect()
+}
+
+#[derive(Clone,Debug)]
+#[cfg_attr(feature = "serde", derive(Serialize,Deserialize),
+           serde(try_from = "DePlusBuilder<D, T>", bound(deserialize = "D: Deserialize<'de>, T: Real")))]
+pub struct DePlus<D, T = f32> {
     /// Input space
     pub dims: usize,
 
@@ -30,6 +251,15 @@ pub struct DePlus<D> {
     /// Crossover type
     pub cr: CrossoverType,
 
+    /// If provided, adapts F and the uniform crossover rate per trial in
+    /// place of `f` and the rate in `cr`
+    pub adaptation: Option<Adaptation>,
+
+    /// How mutants are built from the population.  best/1 is the classic
+    /// DE+ choice, but converges quickly; rand/1 and current-to-pbest/1 keep
+    /// multimodal searches exploring for longer.
+    pub mutation: MutationStrategy,
+
     /// Likelihood of replacing a candidate with a randomly perturb best.  0.1 is a good
     /// default.
     pub m: f32,
@@ -44,27 +274,401 @@ pub struct DePlus<D> {
     /// If enabled, fully restarts the job with new random values.  0 means turn off.
     pub restart_on_stale: Option<usize>,
 
+    /// If provided, refines the best member with a local search when it goes
+    /// stale or the budget runs low
+    pub polisher: Option<Polisher>,
+
     /// Distribution to sample from for initialization
-    pub init_dist: D
+    pub init_dist: D,
+
+    /// If provided, draws each pass's starting population in place of
+    /// `init_dist`, with any `x_in` as its first member
+    #[cfg_attr(feature = "serde", serde(skip))]
+    pub initializer: Option<Arc<dyn Initializer<T>>>,
+
+    /// If provided, keeps candidates within the box, repairing any which leave it
+    pub bounds: Option<Bounds<T>>,
+
+    /// How trial vectors with constraint violations compete with their targets.
+    /// Only matters when fit with a `ConstrainedFitness`.
+    pub constraints: ConstraintHandling,
+
+    /// Where trial vectors are generated and scored
+    pub execution: Execution,
+
+    /// Whether trials replace their targets a generation at a time or as soon
+    /// as each is scored
+    pub update: UpdateMode,
+
+    /// If provided, averages repeated samples of each trial and rescores the
+    /// population, so lucky draws don't get locked in
+    pub noise: Option<Noise>
+}
+
+impl <D, T> DePlus<D, T> {
+
+    /// Starts a validated configuration from the settings without defaults
+    pub fn builder(dims: usize, init_dist: D) -> DePlusBuilder<D, T> {
+        DePlusBuilder {
+            dims,
+            lambda: None,
+            f: default_f(),
+            cr: default_cr(),
+            adaptation: None,
+            mutation: MutationStrategy::default(),
+            m: default_m(),
+            exp: default_exp(),
+            polish_on_stale: None,
+            restart_on_stale: None,
+            polisher: None,
+            init_dist,
+            initializer: None,
+            bounds: None,
+            constraints: ConstraintHandling::default(),
+            execution: Execution::default(),
+            update: UpdateMode::default(),
+            noise: None
+        }
+    }
+}
+
+/// Builds a `DePlus`, checking its settings hang together.  Configs are read
+/// through here too, so any setting left out takes the builder's default.
+#[derive(Clone,Debug)]
+#[cfg_attr(feature = "serde", derive(Deserialize),
+           serde(bound(deserialize = "D: Deserialize<'de>, T: Real")))]
+pub struct DePlusBuilder<D, T = f32> {
+    dims: usize,
+    #[cfg_attr(feature = "serde", serde(default))]
+    lambda: Option<usize>,
+    #[cfg_attr(feature = "serde", serde(default = "default_f"))]
+    f: (f32, f32),
+    #[cfg_attr(feature = "serde", serde(default = "default_cr"))]
+    cr: CrossoverType,
+    #[cfg_attr(feature = "serde", serde(default))]
+    adaptation: Option<Adaptation>,
+    #[cfg_attr(feature = "serde", serde(default))]
+    mutation: MutationStrategy,
+    #[cfg_attr(feature = "serde", serde(default = "default_m"))]
+    m: f32,
+    #[cfg_attr(feature = "serde", serde(default = "default_exp"))]
+    exp: f32,
+    #[cfg_attr(feature = "serde", serde(default))]
+    polish_on_stale: Option<usize>,
+    #[cfg_attr(feature = "serde", serde(default))]
+    restart_on_stale: Option<usize>,
+    #[cfg_attr(feature = "serde", serde(default))]
+    polisher: Option<Polisher>,
+    init_dist: D,
+    #[cfg_attr(feature = "serde", serde(skip))]
+    initializer: Option<Arc<dyn Initializer<T>>>,
+    #[cfg_attr(feature = "serde", serde(default))]
+    bounds: Option<Bounds<T>>,
+    #[cfg_attr(feature = "serde", serde(default))]
+    constraints: ConstraintHandling,
+    #[cfg_attr(feature = "serde", serde(default))]
+    execution: Execution,
+    #[cfg_attr(feature = "serde", serde(default))]
+    update: UpdateMode,
+    #[cfg_attr(feature = "serde", serde(default))]
+    noise: Option<Noise>
+}
+
+fn default_f() -> (f32, f32) { (0.1, 0.9) }
+fn default_cr() -> CrossoverType { CrossoverType::Uniform(0.9) }
+fn default_m() -> f32 { 0.1 }
+fn default_exp() -> f32 { 3. }
+
+impl <D, T: Real> DePlusBuilder<D, T> {
+
+    /// Population size.  Defaults to ten per dimension.
+    pub fn lambda(mut self, lambda: usize) -> Self {
+        self.lambda = Some(lambda);
+        self
+    }
+
+    /// Range F is dithered over.  Defaults to (0.1, 0.9).
+    pub fn f(mut self, low: f32, high: f32) -> Self {
+        self.f = (low, high);
+        self
+    }
+
+    /// Defaults to uniform crossover with a rate of 0.9
+    pub fn cr(mut self, cr: CrossoverType) -> Self {
+        self.cr = cr;
+        self
+    }
+
+    pub fn adaptation(mut self, adaptation: Adaptation) -> Self {
+        self.adaptation = Some(adaptation);
+        self
+    }
+
+    /// Defaults to best/1
+    pub fn mutation(mut self, mutation: MutationStrategy) -> Self {
+        self.mutation = mutation;
+        self
+    }
+
+    /// Defaults to 0.1
+    pub fn m(mut self, m: f32) -> Self {
+        self.m = m;
+        self
+    }
+
+    /// Defaults to 3
+    pub fn exp(mut self, exp: f32) -> Self {
+        self.exp = exp;
+        self
+    }
+
+    pub fn polish_on_stale(mut self, k: usize) -> Self {
+        self.polish_on_stale = Some(k);
+        self
+    }
+
+    pub fn restart_on_stale(mut self, k: usize) -> Self {
+        self.restart_on_stale = Some(k);
+        self
+    }
+
+    pub fn polisher(mut self, polisher: Polisher) -> Self {
+        self.polisher = Some(polisher);
+        self
+    }
+
+    /// Draws starting populations from `initializer` rather than `init_dist`
+    pub fn initializer<I: Initializer<T> + 'static>(mut self, initializer: I) -> Self {
+        self.initializer = Some(Arc::new(initializer));
+        self
+    }
+
+    pub fn bounds(mut self, bounds: Bounds<T>) -> Self {
+        self.bounds = Some(bounds);
+        self
+    }
+
+    pub fn constraints(mut self, constraints: ConstraintHandling) -> Self {
+        self.constraints = constraints;
+        self
+    }
+
+    pub fn execution(mut self, execution: Execution) -> Self {
+        self.execution = execution;
+        self
+    }
+
+    /// Defaults to generational
+    pub fn update(mut self, update: UpdateMode) -> Self {
+        self.update = update;
+        self
+    }
+
+    pub fn noise(mut self, noise: Noise) -> Self {
+        self.noise = Some(noise);
+        self
+    }
+
+    pub fn build(self) -> Result<DePlus<D, T>, ConfigError> {
+        let lambda = self.lambda.unwrap_or(10 * self.dims);
+        check(self.dims > 1, "dims", "crossover needs at least two dimensions")?;
+        check(lambda > 1, "lambda", "needs at least two members to pick donors from")?;
+        self.mutation.validate()?;
+        check(lambda > self.mutation.donors(), "lambda",
+              "needs more members than the mutation strategy draws donors")?;
+        if let Some(adaptation) = &self.adaptation {
+            adaptation.validate()?;
+            check(matches!(self.cr, CrossoverType::Uniform(_)), "adaptation",
+                  "adapts the crossover rate, so needs uniform crossover")?;
+            if let Adaptation::LShade { min_lambda, .. } = adaptation {
+                check(*min_lambda > self.mutation.donors() && *min_lambda <= lambda, "adaptation",
+                      "min_lambda needs to be at most lambda, with room for the donors")?;
+            }
+        }
+        check_range("f", self.f)?;
+        check(self.f.0 >= 0., "f", "needs to be non-negative")?;
+        if let CrossoverType::Uniform(cr) = self.cr {
+            check_prob("cr", cr)?;
+        }
+        check_prob("m", self.m)?;
+        check(self.exp > 0. && self.exp.is_finite(), "exp", "needs to be positive")?;
+        if let Some(bounds) = &self.bounds {
+            bounds.validate()?;
+            check(bounds.dims() == self.dims, "bounds", "need a limit per dimension")?;
+        }
+        if let Some(initializer) = &self.initializer {
+            init::check_init(initializer.as_ref(), self.dims)?;
+        }
+        if let Some(polisher) = &self.polisher {
+            polisher.validate()?;
+        }
+        self.constraints.validate()?;
+        if let Some(noise) = &self.noise {
+            noise.validate()?;
+        }
+        if self.update == UpdateMode::SteadyState {
+            check(self.noise.is_none(), "update", "steady-state updates don't support noise")?;
+            check(self.polisher.is_none(), "update", "steady-state updates don't support a polisher")?;
+            check(!matches!(self.adaptation, Some(Adaptation::LShade { .. })), "update",
+                  "steady-state updates can't shrink the population under L-SHADE")?;
+        }
+
+        Ok(DePlus {
+            dims: self.dims,
+            lambda,
+            f: self.f,
+            cr: self.cr,
+            adaptation: self.adaptation,
+            mutation: self.mutation,
+            m: self.m,
+            exp: self.exp,
+            polish_on_stale: self.polish_on_stale,
+            restart_on_stale: self.restart_on_stale,
+            polisher: self.polisher,
+            init_dist: self.init_dist,
+            initializer: self.initializer,
+            bounds: self.bounds,
+            constraints: self.constraints,
+            execution: self.execution,
+            update: self.update,
+            noise: self.noise
+        })
+    }
+}
+
+#[cfg(feature = "serde")]
+impl <D, T: Real> TryFrom<DePlusBuilder<D, T>> for DePlus<D, T> {
+    type Error = ConfigError;
+
+    fn try_from(builder: DePlusBuilder<D, T>) -> Result<Self, ConfigError> {
+        builder.build()
+    }
+}
+
+#[derive(Clone,Copy,Debug,PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize,Deserialize))]
+enum Phase {
+    /// Fresh population waiting on its initial scores
+    Init,
+
+    /// Population is scored and ready for the next step
+    Ready,
+
+    /// Waiting on the scores for the population rebuilt around the best
+    Polish,
+
+    /// Waiting on the scores for the trial vectors
+    Trial,
+
+    /// Waiting on the scores for the polisher's local search
+    Local
+}
+
+/// Local search under way from the best member
+#[derive(Debug)]
+#[cfg_attr(feature = "serde", derive(Serialize,Deserialize))]
+struct Polishing<T> {
+    search: LocalSearch<T>,
+
+    /// Member the search started from, which its best replaces
+    target: usize,
+
+    /// Evaluations the search may still use, if there's a budget
+    budget: Option<usize>,
+
+    /// Best point scored so far, with its samples and violation
+    best: (Vec<T>, Estimate<T>, T)
 }
 
-impl <D: Distribution<f64> + Sync + Clone> DePlus<D> {
+/// What breeding a trial draws on, shared by every target in a generation
+struct Breeding<'a, T> {
+    pop: &'a [Vec<T>],
+
+    /// Parents replaced by adaptive trials
+    archive: &'a [Vec<T>],
+
+    best_idx: usize,
+
+    /// Members current-to-pbest/1 draws its pbest from
+    elite: Vec<usize>,
+
+    /// F for trials which don't adapt their own
+    f: T
+}
+
+/// Ask/tell state for a DePlus run
+#[derive(Debug)]
+#[cfg_attr(feature = "serde", derive(Serialize,Deserialize))]
+pub struct DeState<T = f32> {
+    /// Streams for the run, one child per pass
+    seeds: SeedSequence,
+
+    /// Streams for the current pass, one child per generation
+    pass_seeds: SeedSequence,
+
+    rng: Stream,
+
+    x_in: Option<Vec<T>>,
+
+    pop: Vec<Vec<T>>,
+
+    fits: Vec<T>,
+
+    /// Fitness samples behind each member's fitness
+    estimates: Vec<Estimate<T>>,
+
+    /// Samples per trial and incumbents being rescored
+    sampling: Sampling,
+
+    /// Constraint violation of each member.  Always zero when unconstrained.
+    viols: Vec<T>,
+
+    /// Trial vectors handed out by the last ask
+    trials: Vec<Vec<T>>,
+
+    /// Whether a trial was a random perturbation, which replaces its target
+    /// unconditionally
+    forced: Vec<bool>,
+
+    /// Success history, when F and CR adapt
+    history: History,
+
+    /// Parents replaced by adaptive trials, kept no larger than the population
+    archive: Vec<Vec<T>>,
+
+    polishing: Option<Polishing<T>>,
+
+    /// Whether the polish held back for the end of the budget has run
+    end_polished: bool,
+
+    /// Bookkeeping for steady-state runs, which update the population as
+    /// each evaluation finishes
+    #[cfg_attr(feature = "serde", serde(skip))]
+    steady: Option<Slots>,
+
+    phase: Phase,
+
+    /// Index of the best member at the time of the last ask
+    best_idx: usize,
+
+    /// Best fitness and violation seen this pass, used to track staleness
+    pass_best: (T, T),
+    stale_len: usize,
+    last_update: usize,
 
-    fn run_pass<F: Fitness<Data=Vec<f32>>, FN: FnMut(f32, usize) -> ()>(
-        &self, 
-        fit_fn: &F, 
-        total_fns: usize, 
-        seed: u64, 
-        x_in: Option<&Vec<f32>>,
-        callback: &mut FN
-    ) -> (usize, f32, Vec<f32>) {
+    ledger: Ledger<Vec<T>, T>,
 
-        let mut rng = rand::rngs::StdRng::seed_from_u64(seed);
+    best_fit: T,
+    best_viol: T,
+    best: Vec<T>
+}
+
+impl <D: Distribution<f64> + Sync + Clone, T: Real> DePlus<D, T> {
 
-        // Initialize population
+    fn init_pop<R: Rng>(&self, rng: &mut R, x_in: Option<&Vec<T>>) -> Vec<Vec<T>> {
         let mut pop: Vec<_> = (0..self.lambda).map(|_| {
-            let mut v = vec![0.; self.dims];
-            v.iter_mut().for_each(|vi| *vi = self.init_dist.sample(&mut rng) as f32);
+            let mut v = vec![T::ZERO; self.dims];
+            v.iter_mut().for_each(|vi| *vi = T::from_f64(self.init_dist.sample(rng)));
             v
         }).collect();
 
@@ -76,234 +680,981 @@ impl <D: Distribution<f64> + Sync + Clone> DePlus<D> {
                     if i == 0 {
                         *pi = *vi;
                     } else {
-                        *pi += vi;
+                        *pi += *vi;
                     }
                 });
             });
         }
 
-        let mut tmp_pop = pop.clone();
+        if let Some(bounds) = &self.bounds {
+            pop.iter_mut().for_each(|p| bounds.repair(p, None, rng));
+        }
+        pop
+    }
+
+    /// Draws a starting population from the initializer.  It can hold more
+    /// than lambda members, which are cut down once scored.
+    fn draw_pop(&self, initializer: &dyn Initializer<T>, pass_seeds: &SeedSequence, x_in: Option<&Vec<T>>) -> Vec<Vec<T>> {
+        let mut rng = init::stream(pass_seeds);
+        let mut pop = initializer.initialize(self.lambda, &mut rng);
+        if let Some(x_in) = x_in {
+            pop[0] = x_in.clone();
+        }
+        if let Some(bounds) = &self.bounds {
+            pop.iter_mut().for_each(|p| bounds.repair(p, None, &mut rng));
+        }
+        pop
+    }
+
+    /// Starts a new pass from a freshly sampled population
+    fn start_pass(&self, state: &mut DeState<T>, pass_seeds: SeedSequence) {
+        let mut rng = pass_seeds.rng();
+        state.pop = match &self.initializer {
+            Some(initializer) => self.draw_pop(initializer.as_ref(), &pass_seeds, state.x_in.as_ref()),
+            None => self.init_pop(&mut rng, state.x_in.as_ref())
+        };
+        state.trials = state.pop.clone();
+        state.fits = vec![T::NEG_INFINITY; self.lambda];
+        state.estimates = vec![Estimate::default(); self.lambda];
+        state.sampling = Sampling::new(self.noise.as_ref());
+        state.viols = vec![T::ZERO; self.lambda];
+        state.forced = vec![false; self.lambda];
+        state.history = History::new(self.adaptation.as_ref());
+        state.archive = Vec::new();
+        state.polishing = None;
+        state.rng = rng;
+        state.pass_seeds = pass_seeds;
+        state.phase = Phase::Init;
+        state.pass_best = (T::NEG_INFINITY, T::INFINITY);
+        state.stale_len = 0;
+        state.last_update = 0;
+    }
+
+    /// Restarts from a fresh population, on the next pass's streams.  The pass
+    /// gets what's left of the budget; if that can't cover a full population
+    /// the run ends instead.
+    fn next_pass(&self, state: &mut DeState<T>, cause: RestartCause) {
+        let remaining = state.ledger.remaining();
+        let cost = Sampling::new(self.noise.as_ref()).cost(self.lambda);
+        if remaining.map(|r| r < cost).unwrap_or(false) {
+            state.ledger.stop(TerminationReason::BudgetExhausted);
+            return
+        }
+
+        let pass_seeds = state.seeds.spawn(state.ledger.restarts() as u64 + 1);
+        self.start_pass(state, pass_seeds);
+
+        // Extras drawn by the initializer are cut down to what's affordable
+        if let Some(remaining) = remaining {
+            let n = (remaining / state.sampling.cost(1)).max(self.lambda);
+            state.pop.truncate(n);
+            state.trials.truncate(n);
+        }
+        if state.steady.is_some() {
+            seat(state);
+        }
+        state.ledger.restart(cause);
+    }
+
+    /// Evaluations the next batch will cost at most, before it is stepped to
+    fn next_cost(&self, state: &DeState<T>) -> usize {
+        if let Some(polishing) = &state.polishing {
+            return state.sampling.cost(polishing.search.ask().len())
+        }
+
+        let rescore = self.noise.as_ref()
+            .map(|n| n.reevaluates(state.ledger.generation()))
+            .unwrap_or(false);
+        let incumbents = if rescore { state.pop.len() } else { 0 };
+        state.sampling.cost(state.pop.len()) + incumbents
+    }
+
+    /// Moves a scored population on to its next step, generating either a
+    /// polished population or a new set of trial vectors.
+    fn step(&self, state: &mut DeState<T>) {
+        let best_idx = match self.settle(state) {
+            Some(best_idx) => best_idx,
+            None => return
+        };
+
+        state.best_idx = best_idx;
+        if self.start_polish(state, best_idx) {
+            return
+        }
+
+        if self.scatter(state, best_idx) {
+            state.phase = Phase::Polish;
+            return
+        }
 
-        // Generate initial fitnesses
-        let mut fits: Vec<_> = pop.iter().map(|p| fit_fn.score(&p)).collect();
+        let f_lr = T::from_f32(Uniform::new(self.f.0, self.f.1).sample(&mut state.rng));
 
-        // Initial function counts
-        let mut fns = self.lambda;
+        // Adaptive runs draw each trial's F and CR from the success history
+        if self.adaptation.is_some() {
+            let (history, rng) = (&state.history, &mut state.rng);
+            let trial = (0..state.pop.len()).map(|_| history.sample(rng)).collect();
+            state.history.trial = trial;
+        }
+        let params = &state.history.trial;
+        let breeding = Breeding {
+            pop: &state.pop,
+            archive: &state.archive,
+            best_idx,
+            elite: self.elite(&state.fits, &state.viols, best_idx),
+            f: f_lr
+        };
 
-        let norm_dist = Normal::new(0.0, 1.0).unwrap();
+        // Generate mutation vector
+        let gen_seeds = state.pass_seeds.spawn(state.ledger.generation() as u64);
+        self.execution.for_each_mut(&mut state.trials, &mut state.forced, |idx, x, forced| {
+            let mut local_rng = gen_seeds.spawn(idx as u64).rng();
+            *forced = self.breed(&breeding, idx, params.get(idx).copied(), &mut local_rng, x);
+        });
 
-        // Tracks time since last update of the best candidate.  We use this to
-        // determine polishing.
-        let mut best_fit = std::f32::NEG_INFINITY;
-        let mut stale_len = 0;
+        state.phase = Phase::Trial;
+    }
 
-        // Checks how long until the next update
-        let mut last_update = 0;
+    /// Tracks whether the pass has improved, restarting it once it's gone
+    /// stale for long enough.  Returns the best member, unless it restarted.
+    fn settle(&self, state: &mut DeState<T>) -> Option<usize> {
+        // Get the best candidate
+        let best_idx = self.constraints.best_index(
+            &state.fits, &state.viols, state.ledger.generation());
+
+        // Check if we've improved
+        let best = (state.fits[best_idx], state.viols[best_idx]);
+        if feasibility_better(best, state.pass_best) {
+            state.pass_best = best;
+            state.stale_len = 0;
+            state.last_update = 0;
+        }
 
+        // check if we're early terminating, in which case we restart with a new pass
         let early_terminate = self.restart_on_stale.unwrap_or(0);
+        if early_terminate > 0 && state.last_update == early_terminate {
+            self.next_pass(state, RestartCause::Stale);
+            return None
+        }
+        Some(best_idx)
+    }
+
+    /// Rebuilds the population around the best member once it's gone stale,
+    /// returning whether it did.  The rest then need to be scored again.
+    fn scatter(&self, state: &mut DeState<T>, best_idx: usize) -> bool {
         let pos = self.polish_on_stale.unwrap_or(0);
-        while fns < total_fns {
-            // Get the best candidate
-            let best_idx = (0..self.lambda).max_by_key(|i| FloatOrd(fits[*i]))
-                .expect("Should never be empty!");
-
-            // Check if we've improved
-            if fits[best_idx] > best_fit {
-                best_fit = fits[best_idx];
-                stale_len = 0;
-                last_update = 0;
-            }
+        if pos == 0 || state.stale_len != pos {
+            state.stale_len += 1;
+            state.last_update += 1;
+            return false
+        }
+
+        let best = state.pop[best_idx].clone();
+
+        // Re build population, preserving the best one
+        let rng = &mut state.rng;
+        state.pop.iter_mut().enumerate().for_each(|(i, p)| {
+            if i != best_idx {
+                p.iter_mut().zip(best.iter())
+                    .for_each(|(vi, bi)| *vi = *bi + T::from_f64(self.init_dist.sample(rng)));
 
-            // check if we're early terminating
-            if early_terminate > 0 && last_update == early_terminate {
-                break
+                if let Some(bounds) = &self.bounds {
+                    bounds.repair(p, Some(&best), rng);
+                }
             }
+        });
+        true
+    }
 
-            if pos > 0 && stale_len == pos {
+    /// Members current-to-pbest/1 draws its pbest from
+    fn elite(&self, fits: &[T], viols: &[T], best_idx: usize) -> Vec<usize> {
+        match self.mutation {
+            MutationStrategy::CurrentToPBest1 { p } => {
+                let n = ((p * fits.len() as f32).ceil() as usize).max(1);
+                fittest(fits, viols, n)
+            },
+            _ => vec![best_idx]
+        }
+    }
 
-                let best = pop[best_idx].clone();
+    /// Breeds the trial for target `idx` into `x`, with the F and CR from
+    /// `params` if it adapts them.  Returns whether it's a random
+    /// perturbation, which replaces its target unconditionally.
+    fn breed(
+        &self,
+        breeding: &Breeding<T>,
+        idx: usize,
+        params: Option<(f32, f32)>,
+        rng: &mut Stream,
+        x: &mut [T]
+    ) -> bool {
+        let Breeding { pop, archive, best_idx, elite, f: f_lr } = breeding;
+        let orig_x = &pop[idx];
+        let best = &pop[*best_idx];
+        let norm_dist = Normal::new(0.0, 1.0f64).unwrap();
+        let uniform = Uniform::new(0., 1.);
+        let exp = T::from_f32(self.exp);
+
+        // Randomize a candidate in the population
+        let forced = idx != *best_idx && uniform.sample(rng) < self.m;
+        if forced {
+
+            // Figure out magnitude between current x and the best
+            x.iter_mut().zip(orig_x.iter()).enumerate().for_each(|(i, (xi, oxi))| {
+                *xi = *oxi - best[i];
+            });
 
-                // Re build population, preserving the best one
-                pop.iter_mut().enumerate().for_each(|(i, p)| {
-                    if i != best_idx {
-                        p.iter_mut().zip(best.iter())
-                            .for_each(|(vi, bi)| *vi = *bi + self.init_dist.sample(&mut rng) as f32);
-                    }
-                });
+            let orig_mag = l2norm(x);
 
-                // Recompute the fits, sans the best
-                fits.iter_mut().enumerate().for_each(|(i, f)| {
-                    if i != best_idx {
-                        *f = fit_fn.score(&pop[i]);
+            // Ok, generate new vector
+            x.iter_mut().for_each(|xi| {
+                *xi = T::from_f64(norm_dist.sample(rng));
+            });
+
+            // Normalize it
+            let v_mag = l2norm(x);
+
+            // Expand it out within the expansion radius
+            x.iter_mut().enumerate().for_each(|(i, xi)| {
+                *xi = best[i] + orig_mag * exp * (*xi) / v_mag;
+            });
+
+        } else {
+            // Select distinct donors for the combination, never the target
+            let picked = donors(rng, pop.len(), idx, self.mutation.donors());
+            let mut r: Vec<_> = picked.iter().map(|j| &pop[*j]).collect();
+
+            // current-to-pbest's last donor can also come from the archive
+            let uses_archive = matches!(self.mutation, MutationStrategy::CurrentToPBest1 { .. });
+            if uses_archive && !archive.is_empty() {
+                let j = loop {
+                    let j = rng.gen_range(0, pop.len() + archive.len());
+                    if j != idx && j != picked[0] {
+                        break j
                     }
-                });
-                fns += pop.len() - 1;
-                stale_len = 0;
-                continue
+                };
+                r[1] = if j < pop.len() { &pop[j] } else { &archive[j - pop.len()] };
             }
+            let (f_lr, cr_rate) = match params {
+                Some((f, cr)) => (T::from_f32(f), Some(cr)),
+                None => (*f_lr, None)
+            };
+            let pbest = match self.mutation {
+                MutationStrategy::CurrentToPBest1 { .. } => &pop[*elite.choose(rng).unwrap()],
+                _ => best
+            };
+            let k = match self.mutation {
+                MutationStrategy::CurrentToRand1 => T::from_f32(uniform.sample(rng)),
+                _ => T::ZERO
+            };
+            let mutant = |i: usize| self.mutation.mutant(i, orig_x, best, pbest, &r, f_lr, k);
+
+            match self.cr {
+                CrossoverType::TwoPoint => {
+                    let d = Uniform::new(0, self.dims);
+                    let mut start = d.sample(rng);
+                    let end       = d.sample(rng);
+                    if start == end {
+                        start = (end + 1) % self.dims;
+                    }
+                    x.copy_from_slice(orig_x.as_slice());
+                    while start != end {
+                        x[start] = mutant(start);
+                        start = (start + 1) % self.dims;
+                    }
+                },
 
-            stale_len += 1;
-            last_update += 1;
+                CrossoverType::Uniform(cr) => {
+                    let cr = cr_rate.unwrap_or(cr);
 
-            let best = &pop[best_idx];
+                    // Generate new vector
+                    x.iter_mut().enumerate().for_each(|(i, xi)| {
+                        // If we are mutating
+                        if uniform.sample(rng) < cr {
+                            *xi = mutant(i);
+                        } else {
+                            *xi = orig_x[i];
+                        }
+                    });
+                }
+            }
+        }
 
-            let f_lr = Uniform::new(self.f.0, self.f.1).sample(&mut rng);
+        if let Some(bounds) = &self.bounds {
+            bounds.repair(x, Some(orig_x), rng);
+        }
+        forced
+    }
 
-            // Generate mutation vector
-            let uniform = Uniform::new(0., 1.);
-            tmp_pop.par_iter_mut().zip(fits.par_iter_mut())
-                    .enumerate().for_each(|(idx, (x, f))| {
+    /// Starts a local search from the best member if the polisher is due,
+    /// returning whether it did
+    fn start_polish(&self, state: &mut DeState<T>, best_idx: usize) -> bool {
+        let polisher = match &self.polisher {
+            Some(polisher) => polisher,
+            None => return false
+        };
 
-                let orig_x = &pop[idx];
-                let mut local_rng = rand::rngs::StdRng::seed_from_u64(
-                    seed + (idx + fns) as u64);
+        let share = polisher.share as f64;
+        let remaining = state.ledger.remaining();
+        let at_end = polisher.at_end && !state.end_polished && remaining
+            .map(|r| r as f64 <= share * (state.ledger.evaluations() + r) as f64)
+            .unwrap_or(false);
+        let stale = polisher.on_stale.map(|k| state.stale_len >= k).unwrap_or(false);
+        if !at_end && !stale {
+            return false
+        }
 
-                // Randomize a candidate in the population
-                if idx != best_idx && uniform.sample(&mut local_rng) < self.m {
+        // The end polish gets everything left, stale ones their share of it
+        let budget = if at_end {
+            remaining
+        } else {
+            remaining.map(|r| (r as f64 * share) as usize)
+        };
+        let x0 = &state.pop[best_idx];
+        let search = polisher.start(x0, state.fits[best_idx], &state.pop, self.bounds.as_ref());
+        state.end_polished |= at_end;
+        state.stale_len = 0;
+        if budget.map(|b| b < state.sampling.cost(search.ask().len())).unwrap_or(false) {
+            return false
+        }
 
-                    // Figure out magnitude between current x and the best
-                    x.iter_mut().zip(orig_x.iter()).enumerate().for_each(|(i, (xi, oxi))| {
-                        *xi = oxi - best[i];
-                    });
+        state.polishing = Some(Polishing {
+            search,
+            target: best_idx,
+            budget,
+            best: (x0.clone(), state.estimates[best_idx], state.viols[best_idx])
+        });
+        state.phase = Phase::Local;
+        true
+    }
 
-                    let orig_mag = l2norm(&x);
+    /// Shrinks the population on L-SHADE's linear schedule, dropping the
+    /// least fit, and keeps the archive no larger than the population
+    fn reduce(&self, state: &mut DeState<T>) {
+        if let (Some(Adaptation::LShade { min_lambda, .. }), Some(remaining)) =
+            (self.adaptation, state.ledger.remaining()) {
+            let used = state.ledger.evaluations();
+            let progress = used as f64 / (used + remaining).max(1) as f64;
+            let size = self.lambda as f64 - (self.lambda - min_lambda) as f64 * progress;
+            let size = (size.round() as usize).max(min_lambda);
+            if size < state.pop.len() {
+                let keep = fittest(&state.fits, &state.viols, size);
+                state.pop = select(&state.pop, &keep);
+                state.fits = select(&state.fits, &keep);
+                state.estimates = select(&state.estimates, &keep);
+                state.viols = select(&state.viols, &keep);
+                state.trials = state.pop.clone();
+                state.forced.truncate(size);
+            }
+        }
 
-                    // Ok, generate new vector
-                    x.iter_mut().for_each(|xi| {
-                        *xi = norm_dist.sample(&mut local_rng) as f32;
-                    });
+        while state.archive.len() > state.pop.len() {
+            let i = state.rng.gen_range(0, state.archive.len());
+            state.archive.swap_remove(i);
+        }
+    }
 
-                    // Normalize it
-                    let v_mag = l2norm(&x);
+    /// Records evaluations and updates the best candidate seen so far.  With
+    /// noise, the best is always the population's current best estimate.
+    fn record(&self, state: &mut DeState<T>, n: usize) {
+        state.ledger.record(n);
+        self.track_best(state);
+    }
 
-                    // Expand it out within the expansion radius
-                    x.iter_mut().enumerate().for_each(|(i, xi)| {
-                        *xi = best[i] + orig_mag * self.exp * (*xi) / v_mag;
-                    });
+    fn track_best(&self, state: &mut DeState<T>) {
+        let best_idx = ConstraintHandling::Feasibility.best_index(&state.fits, &state.viols, 0);
+        let best = (state.fits[best_idx], state.viols[best_idx]);
+        if best.1 <= T::ZERO {
+            state.ledger.observe(best.0, self.noise.is_some());
+        }
+        if self.noise.is_some() || feasibility_better(best, (state.best_fit, state.best_viol)) {
+            state.best_fit = best.0;
+            state.best_viol = best.1;
+            state.best.clone_from(&state.pop[best_idx]);
+        }
+    }
+}
 
-                    // Just override the fitness
-                    let new_f = fit_fn.score(&x);
-                    if new_f.is_finite() {
-                        *f = new_f;
-                    } else {
-                        x.iter_mut().zip(orig_x.iter()).for_each(|(xi, oxi)| {
-                            *xi = *oxi;
-                        });
-                    }
+impl <D, T> AskTell for DePlus<D, T>
+where
+    D: Distribution<f64> + Sync + Send + Clone + std::fmt::Debug,
+    T: Real
+{
+    type State = DeState<T>;
+
+    fn init<TT: Termination<Vec<T>, T> + 'static>(
+        &self,
+        termination: TT,
+        seed: u64,
+        x_in: Option<&Vec<T>>
+    ) -> DeState<T> {
+        let seeds = SeedSequence::new(seed);
+        let mut state = DeState {
+            pass_seeds: seeds.spawn(0),
+            rng: seeds.rng(),
+            seeds,
+            x_in: x_in.cloned(),
+            pop: Vec::new(),
+            fits: Vec::new(),
+            estimates: Vec::new(),
+            sampling: Sampling::default(),
+            viols: Vec::new(),
+            trials: Vec::new(),
+            forced: vec![false; self.lambda],
+            history: History::default(),
+            archive: Vec::new(),
+            polishing: None,
+            end_polished: false,
+            steady: None,
+            phase: Phase::Init,
+            best_idx: 0,
+            pass_best: (T::NEG_INFINITY, T::INFINITY),
+            stale_len: 0,
+            last_update: 0,
+            ledger: Ledger::new(termination),
+            best_fit: T::NEG_INFINITY,
+            best_viol: T::INFINITY,
+            best: vec![T::ZERO; self.dims]
+        };
+        let pass_seeds = state.seeds.spawn(0);
+        self.start_pass(&mut state, pass_seeds);
+        state
+    }
+
+    fn ask(&self, state: &mut DeState<T>) -> Vec<Vec<T>> {
+        if self.is_done(state) {
+            return Vec::new()
+        }
+
+        if state.phase == Phase::Ready {
+            self.step(state);
+
+            // A stale restart ends the run if the budget can't cover it
+            if self.is_done(state) {
+                return Vec::new()
+            }
+        }
+
+        let fresh = match state.phase {
+            Phase::Init => state.pop.clone(),
+            Phase::Polish => {
+                // The best is preserved, so only the rest need to be rescored
+                let best_idx = state.best_idx;
+                state.pop.iter().enumerate()
+                    .filter(|(i, _)| *i != best_idx)
+                    .map(|(_, p)| p.clone())
+                    .collect()
+            },
+            Phase::Trial => state.trials.clone(),
+            Phase::Local => state.polishing.as_ref()
+                .expect("Polishing without a local search")
+                .search.ask().to_vec(),
+            Phase::Ready => unreachable!("Stepping always leaves a pending batch")
+        };
+
+        // Targets are rescored alongside their trials
+        let rescore = state.phase == Phase::Trial && self.noise.as_ref()
+            .map(|n| n.reevaluates(state.ledger.generation()))
+            .unwrap_or(false);
+        let incumbents = if rescore {
+            state.pop.iter().cloned().enumerate().collect()
+        } else {
+            Vec::new()
+        };
+        state.sampling.expand(fresh, incumbents)
+    }
+
+    fn tell(&self, state: &mut DeState<T>, fitnesses: Vec<T>) {
+        let violations = vec![T::ZERO; fitnesses.len()];
+        self.tell_constrained(state, fitnesses, violations);
+    }
+
+    fn termination(&self, state: &DeState<T>) -> Option<TerminationReason> {
+        // Never exceed the budget.  A pass's first population is always
+        // scored, since restarts only go ahead when they can afford one.
+        let stopped = state.ledger.stopped();
+        if stopped.is_some() || state.phase == Phase::Init || state.steady.is_some() {
+            return stopped
+        }
+
+        let cost = self.next_cost(state);
+        match state.ledger.remaining() {
+            Some(remaining) if remaining < cost => Some(TerminationReason::BudgetExhausted),
+            _ => None
+        }
+    }
+
+    fn restart(&self, state: &mut DeState<T>) {
+        self.next_pass(state, RestartCause::Requested);
+    }
+
+    fn resample(&self, state: &mut DeState<T>, idx: usize) -> Vec<T> {
+        // Rescored members are scored again as they are
+        let idx = match state.sampling.slot(idx) {
+            Some(slot) => slot,
+            None => return state.pop[state.sampling.incumbent(idx)].clone()
+        };
+
+        // Local search points are rescored as they are
+        if let Some(polishing) = &state.polishing {
+            return polishing.search.ask()[idx].clone()
+        }
+
+        let slot = match state.phase {
+            Phase::Init => &mut state.pop[idx],
+            Phase::Polish => {
+                let j = if idx < state.best_idx { idx } else { idx + 1 };
+                &mut state.pop[j]
+            },
+            Phase::Trial => {
+                // A fresh trial competes with its target like any other
+                state.forced[idx] = false;
+                &mut state.trials[idx]
+            },
+            Phase::Local => unreachable!("Local search points are returned as they are"),
+            Phase::Ready => panic!("resample called without a pending ask!")
+        };
+
+        // So are candidates with other samples already in
+        if state.sampling.samples() > 1 {
+            return slot.clone()
+        }
+
+        // Draw a fresh vector from the initial distribution
+        let rng = &mut state.rng;
+        slot.iter_mut().for_each(|xi| *xi = T::from_f64(self.init_dist.sample(rng)));
+        if let Some(bounds) = &self.bounds {
+            bounds.repair(slot, None, rng);
+        }
+        slot.clone()
+    }
+
+    fn ledger<'a>(&self, state: &'a DeState<T>) -> &'a Ledger<Vec<T>, T> {
+        &state.ledger
+    }
+
+    fn ledger_mut<'a>(&self, state: &'a mut DeState<T>) -> &'a mut Ledger<Vec<T>, T> {
+        &mut state.ledger
+    }
+
+    fn execution(&self) -> &Execution {
+        &self.execution
+    }
+
+    fn best<'a>(&self, state: &'a DeState<T>) -> (T, &'a Vec<T>) {
+        (state.best_fit, &state.best)
+    }
+
+    fn stats(&self, state: &DeState<T>) -> GenerationStats<Vec<T>, T> {
+        state.ledger.stats(
+            &state.fits,
+            Some(diversity(&state.pop)),
+            (state.best_fit, state.best.clone()))
+    }
+
+    fn population(&self, state: &DeState<T>) -> (Vec<Vec<T>>, Vec<T>) {
+        (state.pop.clone(), state.fits.clone())
+    }
+
+    fn update_mode(&self) -> UpdateMode {
+        self.update
+    }
 
+    fn fit_fallible<F, TT, FN, R>(
+        &self,
+        fit_fn: &F,
+        policy: FailurePolicy,
+        termination: TT,
+        seed: u64,
+        x_in: Option<&Vec<T>>,
+        callback: FN
+    ) -> Result<OptimizationResult<Vec<T>, F::Error, T>, F::Error>
+    where
+        F: FallibleFitness<Data=Vec<T>, Value=T>,
+        TT: Termination<Vec<T>, T> + 'static,
+        FN: FnMut(Self::Stats, Option<usize>) -> R,
+        R: Into<Control>
+    {
+        match self.update {
+            UpdateMode::Generational => fallible::run(self, fit_fn, policy, termination, seed, x_in, callback),
+            UpdateMode::SteadyState => steady::run_fallible(self, fit_fn, policy, termination, seed, x_in, callback)
+        }
+    }
+}
+
+impl <D, T> ConstrainedAskTell for DePlus<D, T>
+where
+    D: Distribution<f64> + Sync + Send + Clone + std::fmt::Debug,
+    T: Real
+{
+
+    fn tell_constrained(&self, state: &mut DeState<T>, fitnesses: Vec<T>, violations: Vec<T>) {
+        assert_eq!(fitnesses.len(), violations.len(), "Expected a violation per score");
+        let evaluations = fitnesses.len();
+        let (estimates, rescored) = state.sampling.collapse(&fitnesses);
+        let (viols, _) = state.sampling.collapse(&violations);
+        let violations: Vec<_> = viols.iter().map(|v| v.mean()).collect();
+
+        // Fold the new samples of rescored members into their estimates
+        for (i, f) in rescored {
+            state.estimates[i].push(f);
+            state.fits[i] = state.estimates[i].mean();
+        }
+
+        match state.phase {
+            Phase::Init => {
+                assert_eq!(estimates.len(), state.pop.len(), "Expected a score per member");
+                state.fits = estimates.iter().map(|e| e.mean()).collect();
+                state.estimates = estimates;
+                state.viols = violations;
+
+                // Initializers may draw extras, leaving only the fittest to carry on
+                if state.pop.len() > self.lambda {
+                    let keep = fittest(&state.fits, &state.viols, self.lambda);
+                    state.pop = select(&state.pop, &keep);
+                    state.fits = select(&state.fits, &keep);
+                    state.estimates = select(&state.estimates, &keep);
+                    state.viols = select(&state.viols, &keep);
+                    state.trials = state.pop.clone();
+                }
+            },
+            Phase::Polish => {
+                assert_eq!(estimates.len(), state.pop.len() - 1, "Expected a score per member");
 
+                // Recompute the fits, sans the best
+                let best_idx = state.best_idx;
+                let others = (0..state.pop.len()).filter(|i| *i != best_idx);
+                others.zip(estimates.into_iter().zip(violations)).for_each(|(i, (e, v))| {
+                    state.fits[i] = e.mean();
+                    state.estimates[i] = e;
+                    state.viols[i] = v;
+                });
+                state.stale_len = 0;
+            },
+            Phase::Trial => {
+                assert_eq!(estimates.len(), state.pop.len(), "Expected a score per trial");
+                let mut successes = Vec::new();
+                let generation = state.ledger.generation();
+                let pooled = if self.noise.is_some() {
+                    pooled_variance(state.estimates.iter().chain(estimates.iter()))
                 } else {
-                    // x_b +  F * (x_a - x_b)
-
-                    // Select two candidates for the combination
-                    let mut slice = pop.choose_multiple(&mut local_rng, 2);
-                    let a = slice.next().expect("Number of candidates to low!");
-                    let b = slice.next().expect("Number of candidates to low!");
-
-                    match self.cr {
-                        CrossoverType::TwoPoint => {
-                            let d = Uniform::new(0, self.dims);
-                            let mut start = d.sample(&mut local_rng);
-                            let end       = d.sample(&mut local_rng);
-                            if start == end {
-                                start = (end + 1) % self.dims;
-                            }
-                            x.copy_from_slice(orig_x.as_slice());
-                            while start != end {
-                                x[start] = best[start] + f_lr * (a[start] - b[start]);
-                                start = (start + 1) % self.dims;
-                            }
+                    None
+                };
+                for (idx, (new_e, new_v)) in estimates.into_iter().zip(violations).enumerate() {
+                    // Random perturbations just override the fitness; mutations
+                    // have to beat their target.  Noisy, equally feasible ones
+                    // have to beat it convincingly.
+                    let new_f = new_e.mean();
+                    let target = (state.fits[idx], state.viols[idx]);
+                    let accept = new_f.is_finite() && (state.forced[idx] || match &self.noise {
+                        Some(noise) if new_v == target.1 => {
+                            state.sampling.replaces(noise, &new_e, &state.estimates[idx], pooled)
                         },
-                        
-                        CrossoverType::Uniform(cr) => {
-                    
-                            // Generate new vector
-                            x.iter_mut().enumerate().for_each(|(i, xi)| {
-                                // If we are mutating
-                                if uniform.sample(&mut local_rng) < cr {
-                                    *xi = best[i] + f_lr * (a[i] - b[i]);
-                                } else {
-                                    *xi = orig_x[i];
-                                }
-                            });
+                        _ => self.constraints.better((new_f, new_v), target, generation, &mut state.rng)
+                    });
+
+                    // Adaptive trials which win archive their parent, and
+                    // those which gained feed the success history
+                    if accept && !state.forced[idx] && self.adaptation.is_some() {
+                        let gain = (new_f - target.0).abs() + (target.1 - new_v).abs();
+                        if gain > T::ZERO {
+                            let (f, cr) = state.history.trial[idx];
+                            successes.push((f, cr, gain.to_f64()));
                         }
+                        state.archive.push(state.pop[idx].clone());
                     }
 
-                    // Score the new individual
-                    let new_fitness = fit_fn.score(&x);
-                    if new_fitness.is_finite() && new_fitness > *f {
-                        *f = new_fitness;
-                    } else {
-                        // Copy over the original x
-                        x.iter_mut().zip(orig_x.iter()).for_each(|(xi, oxi)| {
-                            *xi = *oxi;
-                        });
+                    if accept {
+                        state.fits[idx] = new_f;
+                        state.estimates[idx] = new_e;
+                        state.viols[idx] = new_v;
+                        std::mem::swap(&mut state.pop[idx], &mut state.trials[idx]);
                     }
                 }
-            });
+                if let Some(noise) = &self.noise {
+                    state.sampling.adapt(noise);
+                }
+                if let Some(adaptation) = &self.adaptation {
+                    state.history.update(adaptation, &successes);
+                }
+            },
+            Phase::Local => {
+                let polishing = state.polishing.as_mut().expect("Polishing without a local search");
+                let points = polishing.search.ask().to_vec();
+                assert_eq!(estimates.len(), points.len(), "Expected a score per point");
+                let fits: Vec<_> = estimates.iter().map(|e| e.mean()).collect();
+                for (x, (e, v)) in points.into_iter().zip(estimates.into_iter().zip(violations)) {
+                    if feasibility_better((e.mean(), v), (polishing.best.1.mean(), polishing.best.2)) {
+                        polishing.best = (x, e, v);
+                    }
+                }
+                polishing.search.tell(&fits, self.bounds.as_ref());
+                polishing.budget = polishing.budget.map(|b| b.saturating_sub(evaluations));
+
+                // The search's best stands in for the member it started from
+                let (x, e, v) = &polishing.best;
+                let target = polishing.target;
+                state.pop[target].clone_from(x);
+                state.fits[target] = e.mean();
+                state.estimates[target] = *e;
+                state.viols[target] = *v;
+
+                // Done once it converges or can't afford its next batch
+                let tolerance = T::from_f32(self.polisher.as_ref().map(|p| p.tolerance).unwrap_or(0.));
+                let cost = state.sampling.cost(polishing.search.ask().len());
+                if polishing.search.converged(tolerance) || polishing.budget.map(|b| b < cost).unwrap_or(false) {
+                    state.polishing = None;
+                }
+            },
+            Phase::Ready => panic!("tell called without a pending ask!")
+        }
+        self.record(state, evaluations);
+        if state.phase == Phase::Trial {
+            self.reduce(state);
+        }
+        state.phase = if state.polishing.is_some() { Phase::Local } else { Phase::Ready };
 
-            // Swap tmp with orig
-            std::mem::swap(&mut pop, &mut tmp_pop);
-            fns += self.lambda;
+        let stats = self.stats(state);
+        state.ledger.check(&stats);
+    }
+
+    fn fit_constrained<C, TT, FN, R>(
+        &self,
+        fit_fn: &C,
+        termination: TT,
+        seed: u64,
+        x_in: Option<&Vec<T>>,
+        callback: FN
+    ) -> OptimizationResult<Vec<T>, (), T>
+    where
+        C: ConstrainedFitness<Data=Vec<T>, Value=T>,
+        TT: Termination<Vec<T>, T> + 'static,
+        FN: FnMut(Self::Stats, Option<usize>) -> R,
+        R: Into<Control>
+    {
+        match self.update {
+            UpdateMode::Generational => constraints::run(self, fit_fn, termination, seed, x_in, callback),
+            UpdateMode::SteadyState => steady::run_constrained(self, fit_fn, termination, seed, x_in, callback)
+        }
+    }
+}
 
-            // Callback
-            callback({
-                let best_idx = (0..self.lambda).max_by_key(|i| FloatOrd(fits[*i]))
-                    .expect("Should never be empty!");
-                fits[best_idx]
-            }, if fns < total_fns {total_fns - fns} else {0});
+impl <D, T> Optimizer for DePlus<D, T>
+where
+    D: Distribution<f64> + Sync + Send + Clone + std::fmt::Debug,
+    T: Real
+{
+
+    type Stats = GenerationStats<Vec<T>, T>;
+    type Data = Vec<T>;
+    type Value = T;
+
+    fn fit<F, TT, FN, R>(
+        &self,
+        fit_fn: &F,
+        termination: TT,
+        seed: u64,
+        x_in: Option<&Vec<T>>,
+        callback: FN
+    ) -> OptimizationResult<Vec<T>, (), T>
+    where
+        F: Fitness<Data=Self::Data, Value=T>,
+        TT: Termination<Vec<T>, T> + 'static,
+        FN: FnMut(Self::Stats, Option<usize>) -> R,
+        R: Into<Control>
+    {
+        match self.update {
+            UpdateMode::Generational => crate::run(self, fit_fn, termination, seed, x_in, callback),
+            UpdateMode::SteadyState => steady::run(self, fit_fn, termination, seed, x_in, callback)
         }
+    }
 
-        // Get the best candidate!
-        let best_idx = (0..self.lambda).max_by_key(|i| FloatOrd(fits[*i]))
-            .expect("Should never be empty!");
+}
 
-        assert!(fits[best_idx].is_finite(), 
-                format!("IDX: {}, vec: {:?}", best_idx, pop[best_idx]));
+impl <D, T> SteadyState for DePlus<D, T>
+where
+    D: Distribution<f64> + Sync + Send + Clone + std::fmt::Debug,
+    T: Real
+{
 
-        (fns, fits[best_idx], pop.swap_remove(best_idx))
+    fn begin(&self, state: &mut DeState<T>) {
+        seat(state);
     }
-}
 
-impl <D: Distribution<f64> + Sync + Send + Clone + std::fmt::Debug> Optimizer for DePlus<D> {
-
-    type Stats = f32;
-    type Data = Vec<f32>;
-
-    fn fit<F: Fitness<Data=Self::Data>, FN: FnMut(f32, usize) -> ()>(
-        &self, 
-        fit_fn: &F, 
-        total_fns: usize, 
-        seed: u64, 
-        x_in: Option<&Vec<f32>>,
-        mut callback: FN
-    ) -> (f32, Vec<f32>) {
-
-        let mut fits = 0;
-        let mut best_fit = std::f32::NEG_INFINITY;
-        let mut best_cand = vec![0.; self.dims];
-        while fits < total_fns {
-            let (fn_rem, fit, cand) = self.run_pass(fit_fn, total_fns, 
-                          seed + fits as u64, x_in, 
-                          &mut callback);
-
-            fits += fn_rem;
-            if fit > best_fit {
-                best_fit = fit;
-                best_cand = cand;
+    fn propose(&self, state: &mut DeState<T>) -> Option<(Ticket, Vec<T>)> {
+        let pass = state.ledger.restarts();
+        let slots = state.steady.as_mut().expect("Steady-state run without its slots");
+
+        // Members waiting on their scores go out first
+        if let Some(slot) = slots.queue.pop_front() {
+            return Some((Ticket { pass, slot, kind: Kind::Member }, state.pop[slot].clone()))
+        }
+
+        // Extras drawn by the initializer are only cut once they're all in
+        if state.pop.len() > self.lambda {
+            return None
+        }
+
+        // A generation's worth have come in since the last check
+        if state.phase == Phase::Ready {
+            state.phase = Phase::Trial;
+            // A stale pass starts over, with a fresh population to hand out
+            let best_idx = match self.settle(state) {
+                Some(best_idx) => best_idx,
+                None if self.is_done(state) => return None,
+                None => return self.propose(state)
+            };
+            if self.scatter(state, best_idx) {
+                let slots = state.steady.as_mut().expect("Steady-state run without its slots");
+                slots.rescore_all_but(best_idx);
+                return self.propose(state)
             }
         }
 
-        (best_fit, best_cand)
+        let slots = state.steady.as_mut().expect("Steady-state run without its slots");
+        let target = slots.next_target()?;
+        let mut rng = state.pass_seeds.spawn(slots.bred).rng();
+        slots.bred += 1;
+
+        // Each trial dithers its own F, or draws F and CR from the history
+        let f = T::from_f32(Uniform::new(self.f.0, self.f.1).sample(&mut rng));
+        let params = if self.adaptation.is_some() {
+            Some(state.history.sample(&mut rng))
+        } else {
+            None
+        };
+        let best_idx = self.constraints.best_index(
+            &state.fits, &state.viols, state.ledger.generation());
+        let breeding = Breeding {
+            pop: &state.pop,
+            archive: &state.archive,
+            best_idx,
+            elite: self.elite(&state.fits, &state.viols, best_idx),
+            f
+        };
+        let mut trial = state.pop[target].clone();
+        let forced = self.breed(&breeding, target, params, &mut rng, &mut trial);
+        Some((Ticket { pass, slot: target, kind: Kind::Trial { forced, params } }, trial))
+    }
+
+    fn redraw(&self, state: &mut DeState<T>, ticket: Ticket) -> Option<Vec<T>> {
+        if ticket.kind != Kind::Member {
+            return None
+        }
+
+        // Draw a fresh vector from the initial distribution
+        let rng = &mut state.rng;
+        let mut member: Vec<T> = state.pop[ticket.slot].iter()
+            .map(|_| T::from_f64(self.init_dist.sample(rng)))
+            .collect();
+        if let Some(bounds) = &self.bounds {
+            bounds.repair(&mut member, None, rng);
+        }
+        Some(member)
+    }
+
+    fn accept(&self, state: &mut DeState<T>, ticket: Ticket, candidate: Vec<T>, fitness: T, violation: T) -> bool {
+        // Results from before a restart only count against the budget
+        if ticket.pass != state.ledger.restarts() {
+            return false
+        }
+
+        let idx = ticket.slot;
+        let slots = state.steady.as_mut().expect("Steady-state run without its slots");
+        match ticket.kind {
+            // A trial which took the slot while it was out keeps it
+            Kind::Member if slots.scored[idx] => (),
+            Kind::Member => {
+                state.pop[idx] = candidate;
+                state.fits[idx] = fitness;
+                state.estimates[idx] = Estimate::new(fitness);
+                state.viols[idx] = violation;
+                slots.scored[idx] = true;
+
+                // Once the whole starting population is in, only the fittest carry on
+                if state.pop.len() > self.lambda && slots.scored.iter().all(|s| *s) {
+                    let keep = fittest(&state.fits, &state.viols, self.lambda);
+                    state.pop = select(&state.pop, &keep);
+                    state.fits = select(&state.fits, &keep);
+                    state.estimates = select(&state.estimates, &keep);
+                    state.viols = select(&state.viols, &keep);
+                    state.trials = state.pop.clone();
+                    state.forced.truncate(self.lambda);
+                    slots.scored.truncate(self.lambda);
+                }
+            },
+            Kind::Trial { forced, params } => {
+                let target = (state.fits[idx], state.viols[idx]);
+                let generation = state.ledger.generation();
+                let accept = fitness.is_finite() && (forced ||
+                    self.constraints.better((fitness, violation), target, generation, &mut state.rng));
+
+                if accept && !forced {
+                    if let Some((f, cr)) = params {
+                        let gain = (fitness - target.0).abs() + (target.1 - violation).abs();
+                        if gain > T::ZERO {
+                            slots.successes.push((f, cr, gain.to_f64()));
+                        }
+                        state.archive.push(state.pop[idx].clone());
+                    }
+                }
+
+                if accept {
+                    state.pop[idx] = candidate;
+                    state.fits[idx] = fitness;
+                    state.estimates[idx] = Estimate::new(fitness);
+                    state.viols[idx] = violation;
+                    slots.scored[idx] = true;
+                }
+            },
+            Kind::Child => unreachable!("DE only hands out members and trials")
+        }
+        self.track_best(state);
+
+        let slots = state.steady.as_mut().expect("Steady-state run without its slots");
+        if !slots.tally(state.pop.len()) {
+            return false
+        }
+
+        // Close out the generation: adapt, record and check the criteria.
+        // Staleness is checked before the next trial is bred.
+        let successes = std::mem::take(&mut slots.successes);
+        if let Some(adaptation) = &self.adaptation {
+            state.history.update(adaptation, &successes);
+        }
+        self.reduce(state);
+        self.record(state, 0);
+        if slots_ready(state) {
+            state.phase = Phase::Ready;
+        }
+
+        let stats = self.stats(state);
+        state.ledger.check(&stats);
+        true
     }
+}
 
+/// Gives every member of a fresh population, extras included, a slot to be
+/// scored into one by one
+fn seat<T: Real>(state: &mut DeState<T>) {
+    let n = state.pop.len();
+    state.fits.resize(n, T::NEG_INFINITY);
+    state.estimates.resize(n, Estimate::default());
+    state.viols.resize(n, T::ZERO);
+    state.forced.resize(n, false);
+    state.steady = Some(Slots::new(n));
 }
 
-fn l2norm(v: &[f32]) -> f32 {
+/// Whether every member of a steady-state population has its score in, so
+/// the pass can be checked for staleness
+fn slots_ready<T>(state: &DeState<T>) -> bool {
+    state.steady.as_ref().map(|s| s.queue.is_empty() && s.scored.iter().all(|s| *s)).unwrap_or(false)
+}
+
+fn l2norm<T: Real>(v: &[T]) -> T {
     v.iter()
         .map(|vi| vi.powi(2))
-        .sum::<f32>()
-        .powf(0.5)
+        .sum::<T>()
+        .powf(T::from_f32(0.5))
 }
 
 #[cfg(test)]
@@ -311,27 +1662,363 @@ mod test_de {
     use super::*;
     use rand_distr::StandardNormal;
     use crate::exp::*;
+    use crate::bounds::Repair;
+    use crate::TerminationReason;
+    use crate::constraints::ConstrainedFitness;
+    use crate::polish::LocalMethod;
+    use crate::init::{Opposition,UniformInit};
 
     #[test]
     fn test_matyas() {
-        let de = DePlus {
-            dims: 2,
-            lambda: 30,
-            f: (0.1, 1.),
-            cr: CrossoverType::Uniform(0.9),
-            m: 0.1,
-            exp: 3.,
-            polish_on_stale: None,
-            restart_on_stale: None,
-            init_dist: StandardNormal
-        };
+        let de = DePlus::builder(2, StandardNormal).lambda(30).f(0.1, 1.).build().unwrap();
+
+        let fit_fn = MatyasEnv(-10., 10.);
+        let result = de.fit(&fit_fn, 10000, 2020, None, |_stats, _fns_remaining| {});
+        assert_eq!(result.fitness, 0.);
+        assert_eq!(result.candidate[0], 10.);
+        assert_eq!(result.candidate[1], -10.);
+        assert_eq!(result.termination, TerminationReason::BudgetExhausted);
+        assert_eq!(result.evaluations, 9990);
+        assert_eq!(result.history.len(), result.generations + 1);
+        assert_eq!(result.population.len(), 30);
+        assert_eq!(result.population_fitness.len(), 30);
+        assert_eq!(result.restarts, 0);
+    }
+
+    #[test]
+    fn test_bounds() {
+        let bounds = Bounds::uniform(2, -5., 5., Repair::Reflect);
+        let de = DePlus::builder(2, StandardNormal)
+            .lambda(30)
+            .f(0.1, 1.)
+            .polish_on_stale(10)
+            .bounds(bounds.clone())
+            .build().unwrap();
+
+        // The optimum at (10, -10) lies outside the box
+        let fit_fn = MatyasEnv(-10., 10.);
+        let result = de.fit(&fit_fn, 5000, 2020, None, |stats, _fns_remaining| {
+            assert!(bounds.contains(&stats.best_candidate));
+        });
+        assert!(result.population.iter().all(|p| bounds.contains(p)));
+        assert!((result.candidate[0] - 5.).abs() < 1e-3);
+        assert!((result.candidate[1] + 5.).abs() < 1e-3);
+    }
+
+    #[test]
+    fn test_constrained() {
+        let modes = [
+            ConstraintHandling::Feasibility,
+            ConstraintHandling::Epsilon { level: 1., generations: 100, cp: 2. },
+            ConstraintHandling::StochasticRanking { pf: 0.45 }
+        ];
+
+        for constraints in modes.iter() {
+            for update in &[UpdateMode::Generational, UpdateMode::SteadyState] {
+                let de = DePlus::builder(2, StandardNormal)
+                    .lambda(30)
+                    .f(0.1, 1.)
+                    .constraints(*constraints)
+                    .update(*update)
+                    .build().unwrap();
+
+                let result = de.fit_constrained(&LinearDiskEnv, 10000, 2020, None, |_stats, _fns_remaining| {});
+                assert_eq!(LinearDiskEnv.violation(&result.candidate), 0.);
+                assert!((result.fitness - 2f32.sqrt()).abs() < 1e-3,
+                        "{:?} {:?}: {}", constraints, update, result.fitness);
+            }
+        }
+    }
+
+    #[test]
+    fn test_restarts_reported() {
+        let de = DePlus::builder(2, StandardNormal)
+            .lambda(30)
+            .f(0.1, 1.)
+            .restart_on_stale(5)
+            .build().unwrap();
+
+        let fit_fn = RastriginEnv { dims: 2 };
+        let result = de.fit(&fit_fn, 10000, 2020, None, |_stats, _fns_remaining| {});
+        assert!(result.restarts > 0);
+        assert!(result.fitness >= result.population_fitness.iter().cloned().fold(f32::MIN, f32::max));
+    }
+
+    #[test]
+    fn test_restart_budget() {
+        let region = Bounds::uniform(2, -5., 5., Repair::Clip);
+        let builders = vec![
+            DePlus::builder(2, StandardNormal).lambda(30).restart_on_stale(5),
+            DePlus::builder(2, StandardNormal).lambda(20).restart_on_stale(3)
+                .initializer(Opposition(UniformInit(region))),
+            DePlus::builder(2, StandardNormal).lambda(20).restart_on_stale(3)
+                .noise(Noise::default())
+        ];
+        for (i, builder) in builders.into_iter().enumerate() {
+            let de = builder.build().unwrap();
+            for budget in (1000..1100).step_by(17) {
+                let mut restarts = Vec::new();
+                let result = de.fit(&RastriginEnv { dims: 2 }, budget, 2020, None, |stats, _fns_remaining| {
+                    if let Some(cause) = stats.restarted {
+                        restarts.push(cause);
+                    }
+                });
+
+                // Every pass gets what the ones before it left, no more
+                assert!(result.evaluations <= budget, "{}: {} > {}", i, result.evaluations, budget);
+                assert!(result.restarts > 0);
+                assert_eq!(result.passes.len(), result.restarts + 1);
+                assert_eq!(result.passes.iter().map(|p| p.evaluations).sum::<usize>(), result.evaluations);
+                assert_eq!(result.passes.iter().map(|p| p.batches).sum::<usize>(), result.history.len());
+                assert_eq!(result.passes[0].cause, None);
+                assert!(result.passes[1..].iter().all(|p| p.cause == Some(RestartCause::Stale)));
+                assert_eq!(restarts.len(), result.restarts);
+
+                // Without noise the run's best is the best of its passes
+                if i != 2 {
+                    let best = result.passes.iter().map(|p| p.best).fold(f32::MIN, f32::max);
+                    assert_eq!(best, result.fitness);
+                }
+            }
+        }
+
+        // Restarts asked for by the callback are reported as such
+        let de = DePlus::builder(2, StandardNormal).lambda(20).build().unwrap();
+        let result = de.fit(&RastriginEnv { dims: 2 }, 1000, 2020, None, |stats, _fns_remaining| {
+            if stats.generation % 10 == 9 { Control::Restart } else { Control::Continue }
+        });
+        assert!(result.evaluations <= 1000);
+        assert_eq!(result.passes.len(), result.restarts + 1);
+        assert!(result.passes[1..].iter().all(|p| p.cause == Some(RestartCause::Requested)));
+        assert!(result.history.iter().filter(|s| s.restarted.is_some()).count() == result.restarts);
+    }
+
+    #[test]
+    fn test_ask_tell_matches_fit() {
+        let de = DePlus::builder(2, StandardNormal)
+            .lambda(30)
+            .f(0.1, 1.)
+            .polish_on_stale(10)
+            .build().unwrap();
+
+        let fit_fn = MatyasEnv(-10., 10.);
+        let mut state = de.init(3000, 2020, None);
+        while !de.is_done(&state) {
+            let candidates = de.ask(&mut state);
+            let scores = candidates.iter().map(|c| fit_fn.score(c)).collect();
+            de.tell(&mut state, scores);
+        }
+
+        let result = de.fit(&fit_fn, 3000, 2020, None, |_stats, _fns_remaining| {});
+        assert_eq!(de.best(&state), (result.fitness, &result.candidate));
+    }
+
+    #[test]
+    fn test_execution_backends() {
+        let mut de = DePlus::builder(2, StandardNormal)
+            .lambda(30)
+            .f(0.1, 1.)
+            .polish_on_stale(5)
+            .build().unwrap();
 
         let fit_fn = MatyasEnv(-10., 10.);
-        let (fit, results) = de.fit(&fit_fn, 10000, 2020, None, |_best_fit, _fns_remaining| {});
-        assert_eq!(fit, 0.);
-        assert_eq!(results[0], 10.);
-        assert_eq!(results[1], -10.);
+        let expected = de.fit(&fit_fn, 3000, 2020, None, |_stats, _fns_remaining| {});
+        for execution in [Execution::threads(3), Execution::Sequential] {
+            de.execution = execution;
+            let result = de.fit(&fit_fn, 3000, 2020, None, |_stats, _fns_remaining| {});
+            assert_eq!(result.population, expected.population);
+            assert_eq!(result.candidate, expected.candidate);
+        }
+    }
+
+
+    #[test]
+    fn test_f64() {
+        let de = DePlus::builder(2, StandardNormal).lambda(20).build().unwrap();
+
+        // The nearest f32 is over 1e-7 away from the centre
+        let result = de.fit(&FineSphereEnv(3.000_000_123), 10000, 2020, None, |_stats, _fns_remaining| {});
+        assert!(result.candidate.iter().all(|xi| (xi - 3.000_000_123).abs() < 1e-9), "{:?}", result.candidate);
+    }
+
+    #[test]
+    fn test_noise() {
+        let builder = || DePlus::builder(2, StandardNormal)
+            .lambda(20)
+            .execution(Execution::Sequential);
+        let env = MatyasEnv(-1., 1.);
+        let fit = |de: &DePlus<StandardNormal>| {
+            let noisy = NoisyEnv::new(MatyasEnv(-1., 1.), 1., 2020);
+            let result = de.fit(&noisy, 6000, 2020, None, |_stats, _fns_remaining| {});
+            (result.fitness, env.score(&result.candidate))
+        };
+
+        // Greedy selection locks in lucky draws, so the reported best is
+        // wildly optimistic.  Rescoring and testing keeps it honest.
+        let (reported, actual) = fit(&builder().build().unwrap());
+        assert!(reported - actual > 1.);
+        let (reported, actual) = fit(&builder().noise(Noise::default()).build().unwrap());
+        assert!((reported - actual).abs() < 1.);
+        assert!(actual > -0.5);
+    }
+    #[test]
+    fn test_mutation_strategies() {
+        let mut rng = SeedSequence::new(2020).rng();
+        for target in 0..5 {
+            let mut picked = donors(&mut rng, 5, target, 4);
+            assert!(!picked.contains(&target));
+            picked.sort_unstable();
+            picked.dedup();
+            assert_eq!(picked.len(), 4);
+        }
+
+        let strategies = [
+            MutationStrategy::Rand1,
+            MutationStrategy::Rand2,
+            MutationStrategy::Best1,
+            MutationStrategy::Best2,
+            MutationStrategy::CurrentToBest1,
+            MutationStrategy::CurrentToRand1,
+            MutationStrategy::CurrentToPBest1 { p: 0.1 }
+        ];
+        for mutation in strategies.iter() {
+            let de = DePlus::builder(2, StandardNormal).lambda(20).mutation(*mutation).build().unwrap();
+            let result = de.fit(&MatyasEnv(-1., 1.), 6000, 2020, None, |_stats, _fns_remaining| {});
+            assert!(result.fitness > -1e-3, "{:?}: {}", mutation, result.fitness);
+        }
+
+        // rand/2 needs five donors besides the target
+        let de = DePlus::<_>::builder(2, StandardNormal).lambda(5).mutation(MutationStrategy::Rand2).build();
+        assert!(de.is_err());
+        let de = DePlus::<_>::builder(2, StandardNormal).mutation(MutationStrategy::CurrentToPBest1 { p: 0. }).build();
+        assert!(de.is_err());
+    }
+
+    #[test]
+    fn test_adaptation() {
+        // Lehmer means lean towards the larger F, while CR takes the plain
+        // mean; SHADE weighs both by gain
+        let mut history = History::new(Some(&Adaptation::Shade { memory: 2 }));
+        history.update(&Adaptation::Shade { memory: 2 }, &[(0.2, 0.2, 1.), (0.8, 0.8, 1.)]);
+        assert!((history.f[0] - 0.68).abs() < 1e-6);
+        assert!((history.cr[0] - 0.5).abs() < 1e-6);
+        assert_eq!(history.k, 1);
+        history.update(&Adaptation::Shade { memory: 2 }, &[(0.2, 0.1, 3.), (0.8, 0.9, 1.)]);
+        assert!((history.f[1] - 0.19 / 0.35).abs() < 1e-6);
+        assert!((history.cr[1] - 0.3).abs() < 1e-6);
+
+        let mut jade = History::new(Some(&Adaptation::Jade { c: 1. }));
+        jade.update(&Adaptation::Jade { c: 1. }, &[(0.2, 0.1, 3.), (0.8, 0.9, 1.)]);
+        assert!((jade.f[0] - 0.68).abs() < 1e-6);
+        assert!((jade.cr[0] - 0.5).abs() < 1e-6);
+
+        // Memory left at zero by skipping the builder still gets a slot
+        let empty = History::new(Some(&Adaptation::Shade { memory: 0 }));
+        assert_eq!(empty.f.len(), 1);
+
+        let mut rng = SeedSequence::new(2020).rng();
+        assert!((0..100).map(|_| history.sample(&mut rng)).all(|(f, cr)| {
+            f > 0. && f <= 1. && (0. ..=1.).contains(&cr)
+        }));
+
+        let adaptations = [
+            Adaptation::Jade { c: 0.1 },
+            Adaptation::Shade { memory: 5 },
+            Adaptation::LShade { memory: 5, min_lambda: 4 }
+        ];
+        for adaptation in adaptations.iter() {
+            let de = DePlus::builder(2, StandardNormal)
+                .lambda(40)
+                .mutation(MutationStrategy::CurrentToPBest1 { p: 0.1 })
+                .adaptation(*adaptation)
+                .m(0.)
+                .build()
+                .unwrap();
+            let result = de.fit(&RastriginEnv { dims: 2 }, 8000, 2020, None, |_stats, _fns_remaining| {});
+            assert!(result.fitness > -1e-3, "{:?}: {}", adaptation, result.fitness);
+
+            // L-SHADE ends the budget at its smallest population
+            let expected = if let Adaptation::LShade { .. } = adaptation { 4 } else { 40 };
+            assert_eq!(result.population.len(), expected, "{:?}", adaptation);
+        }
+
+        let de = DePlus::<_>::builder(2, StandardNormal)
+            .cr(CrossoverType::TwoPoint)
+            .adaptation(Adaptation::Jade { c: 0.1 })
+            .build();
+        assert!(de.is_err());
     }
-    
 
+    #[test]
+    fn test_steady_state() {
+        let builder = || DePlus::builder(2, StandardNormal).lambda(20).update(UpdateMode::SteadyState);
+        let plain = builder().execution(Execution::Sequential).build().unwrap();
+        let adaptive = builder().execution(Execution::Sequential)
+            .mutation(MutationStrategy::CurrentToPBest1 { p: 0.2 })
+            .adaptation(Adaptation::Shade { memory: 5 })
+            .m(0.)
+            .build().unwrap();
+        let restarting = builder().execution(Execution::Sequential)
+            .polish_on_stale(5)
+            .restart_on_stale(10)
+            .initializer(Opposition(UniformInit(Bounds::uniform(2, -20., 20., Repair::Clip))))
+            .build().unwrap();
+
+        for (i, de) in [plain, adaptive, restarting].iter().enumerate() {
+            let fit = || de.fit(&MatyasEnv(-10., 10.), 4000, 2020, None, |_stats, _fns_remaining| {});
+            let result = fit();
+            assert!(result.fitness > -1e-4, "{}: {}", i, result.fitness);
+            assert_eq!(result.evaluations, 4000);
+            assert_eq!(result.population.len(), 20);
+            assert_eq!(result.passes.iter().map(|p| p.evaluations).sum::<usize>(), 4000);
+
+            // Sequential runs finish in order, so they repeat exactly
+            assert_eq!(fit().population, result.population);
+
+            // Threads only change the order evaluations finish in
+            let de = DePlus { execution: Execution::threads(3), ..de.clone() };
+            let result = de.fit(&MatyasEnv(-10., 10.), 4000, 2020, None, |_stats, _fns_remaining| {});
+            assert_eq!(result.evaluations, 4000);
+            assert!(result.fitness > -1e-3, "{}: {}", i, result.fitness);
+        }
+
+        // Stale passes start over and carry on through the budget
+        for execution in [Execution::Sequential, Execution::threads(3)] {
+            let de = builder().restart_on_stale(3).execution(execution).build().unwrap();
+            let result = de.fit(&RastriginEnv { dims: 2 }, 4000, 2020, None, |_stats, _fns_remaining| {});
+            assert!(result.restarts > 0);
+            assert_eq!(result.evaluations, 4000);
+            assert_eq!(result.termination, TerminationReason::BudgetExhausted);
+        }
+
+        // Settings which need whole generations at once are turned down
+        assert!(builder().noise(Noise::default()).build().is_err());
+        assert!(builder().polisher(Polisher::default()).build().is_err());
+        assert!(builder().adaptation(Adaptation::LShade { memory: 5, min_lambda: 4 }).build().is_err());
+    }
+
+    #[test]
+    fn test_polisher() {
+        let centre = 3.000_000_123;
+        let error = |de: &DePlus<StandardNormal, f64>| {
+            let result = de.fit(&FineSphereEnv(centre), 500, 2020, None, |_stats, _fns_remaining| {});
+            assert!(result.evaluations <= 500, "{}", result.evaluations);
+            result.candidate.iter().map(|xi| (xi - centre).abs()).fold(0., f64::max)
+        };
+        let builder = || DePlus::builder(2, StandardNormal).lambda(20);
+        let plain = error(&builder().build().unwrap());
+
+        // Polishing at the end closes in on the last few digits.  Polishing
+        // whenever the best stalls splits its share over many short searches,
+        // so it gets less far, but still well past DE alone.
+        for method in [LocalMethod::NelderMead, LocalMethod::HookeJeeves].iter() {
+            let at_end = Polisher { method: *method, share: 0.5, tolerance: 1e-12, ..Polisher::default() };
+            let polished = error(&builder().polisher(at_end).build().unwrap());
+            assert!(polished < 1e-6 && polished < plain / 100., "{:?}: {} vs {}", at_end, polished, plain);
+
+            let on_stale = Polisher { on_stale: Some(1), at_end: false, ..at_end };
+            let polished = error(&builder().polisher(on_stale).build().unwrap());
+            assert!(polished < plain / 10., "{:?}: {} vs {}", on_stale, polished, plain);
+        }
+    }
 }
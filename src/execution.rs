@@ -0,0 +1,266 @@
+use std::cell::Cell;
+use std::sync::Arc;
+
+use rayon::prelude::*;
+use rayon::{Scope,ThreadPool,ThreadPoolBuilder};
+
+#[cfg(feature = "serde")]
+use serde::{Deserialize,Serialize};
+
+/// Where an optimizer does its parallel work: breeding candidates and scoring
+/// batches.  Every backend produces the same results for the same seed.
+/// Configs name a pool by its number of threads.
+#[derive(Clone,Debug,Default)]
+#[cfg_attr(feature = "serde", derive(Serialize,Deserialize),
+           serde(from = "ExecutionDef", into = "ExecutionDef"))]
+pub enum Execution {
+    /// rayon's global pool
+    #[default]
+    Global,
+
+    /// A caller supplied pool
+    Pool(Arc<ThreadPool>),
+
+    /// Everything runs on the calling thread, batch scoring included.  The
+    /// default `score_batch` scores candidates one at a time rather than
+    /// reaching for rayon's global pool.
+    Sequential
+}
+
+impl Execution {
+
+    /// A dedicated pool with a fixed number of threads
+    pub fn threads(num_threads: usize) -> Self {
+        let pool = ThreadPoolBuilder::new()
+            .num_threads(num_threads)
+            .build()
+            .expect("Failed to build thread pool");
+        Execution::Pool(Arc::new(pool))
+    }
+
+    /// Runs `op` within the backend's pool.  Sequential ops run on the calling
+    /// thread, and the crate's default batch scoring stays on it.
+    pub fn install<R: Send, OP: FnOnce() -> R + Send>(&self, op: OP) -> R {
+        match self {
+            Execution::Global => op(),
+            Execution::Pool(pool) => pool.install(op),
+            Execution::Sequential => {
+                let _sequential = SequentialGuard::enter();
+                op()
+            }
+        }
+    }
+
+    /// Candidates a steady-state run keeps out for scoring at once, one per
+    /// thread
+    pub(crate) fn workers(&self) -> usize {
+        match self {
+            Execution::Global => rayon::current_num_threads(),
+            Execution::Pool(pool) => pool.current_num_threads(),
+            Execution::Sequential => 1
+        }
+    }
+
+    /// Runs `op` on the calling thread with a scope whose jobs run in the
+    /// backend's pool, so the caller can hand out more work as jobs finish.
+    /// Sequential jobs run as soon as they're spawned.
+    pub(crate) fn scope<'s, R, OP>(&self, op: OP) -> R
+    where
+        OP: FnOnce(&Spawner<'_, 's>) -> R
+    {
+        match self {
+            Execution::Global => rayon::in_place_scope(|scope| op(&Spawner::Pool(scope))),
+            Execution::Pool(pool) => pool.in_place_scope(|scope| op(&Spawner::Pool(scope))),
+            Execution::Sequential => op(&Spawner::Inline)
+        }
+    }
+
+    /// Scores a batch with `all`, within the backend's pool
+    pub(crate) fn batch<D, T, ALL>(&self, candidates: &[D], all: ALL) -> Vec<T>
+    where
+        D: Sync,
+        T: Send,
+        ALL: FnOnce(&[D]) -> Vec<T> + Send
+    {
+        self.install(|| all(candidates))
+    }
+
+    /// Maps each index in `0..n`
+    pub(crate) fn map<T, F>(&self, n: usize, f: F) -> Vec<T>
+    where
+        T: Send,
+        F: Fn(usize) -> T + Sync + Send
+    {
+        match self {
+            Execution::Sequential => (0..n).map(f).collect(),
+            _ => self.install(|| (0..n).into_par_iter().map(f).collect())
+        }
+    }
+
+    /// Visits each index along with its items from two equally sized slices
+    pub(crate) fn for_each_mut<A, B, F>(&self, a: &mut [A], b: &mut [B], f: F)
+    where
+        A: Send,
+        B: Send,
+        F: Fn(usize, &mut A, &mut B) + Sync + Send
+    {
+        assert_eq!(a.len(), b.len(), "Slices need to be the same length");
+        match self {
+            Execution::Sequential => {
+                a.iter_mut().zip(b.iter_mut()).enumerate()
+                    .for_each(|(i, (ai, bi))| f(i, ai, bi))
+            },
+            _ => self.install(|| {
+                a.par_iter_mut().zip(b.par_iter_mut()).enumerate()
+                    .for_each(|(i, (ai, bi))| f(i, ai, bi))
+            })
+        }
+    }
+}
+
+thread_local! {
+    /// Whether a sequential backend is running work on this thread
+    static SEQUENTIAL: Cell<bool> = const { Cell::new(false) };
+}
+
+/// Marks the current thread as sequential until dropped
+struct SequentialGuard(bool);
+
+impl SequentialGuard {
+
+    fn enter() -> Self {
+        SequentialGuard(SEQUENTIAL.with(|s| s.replace(true)))
+    }
+}
+
+impl Drop for SequentialGuard {
+    fn drop(&mut self) {
+        let outer = self.0;
+        SEQUENTIAL.with(|s| s.set(outer));
+    }
+}
+
+/// Whether a sequential backend is running work on this thread, in which case
+/// batches shouldn't be spread over a pool
+pub(crate) fn is_sequential() -> bool {
+    SEQUENTIAL.with(Cell::get)
+}
+
+/// Maps `f` over a batch, in parallel unless a sequential backend is running
+/// it.  Default batch scoring goes through here.
+pub(crate) fn map_batch<D, T, F>(items: &[D], f: F) -> Vec<T>
+where
+    D: Sync,
+    T: Send,
+    F: Fn(&D) -> T + Sync + Send
+{
+    if is_sequential() {
+        items.iter().map(f).collect()
+    } else {
+        items.par_iter().map(f).collect()
+    }
+}
+
+/// Hands jobs to an `Execution::scope`
+pub(crate) enum Spawner<'a, 's> {
+    Pool(&'a Scope<'s>),
+    Inline
+}
+
+impl <'a, 's> Spawner<'a, 's> {
+
+    pub(crate) fn spawn<F: FnOnce() + Send + 's>(&self, job: F) {
+        match self {
+            Spawner::Pool(scope) => scope.spawn(move |_| job()),
+            Spawner::Inline => job()
+        }
+    }
+}
+
+/// How `Execution` is written in a config
+#[cfg(feature = "serde")]
+#[derive(Serialize,Deserialize)]
+#[serde(rename = "Execution")]
+enum ExecutionDef {
+    Global,
+    Threads(usize),
+    Sequential
+}
+
+#[cfg(feature = "serde")]
+impl From<ExecutionDef> for Execution {
+    fn from(def: ExecutionDef) -> Self {
+        match def {
+            ExecutionDef::Global => Execution::Global,
+            ExecutionDef::Threads(n) => Execution::threads(n),
+            ExecutionDef::Sequential => Execution::Sequential
+        }
+    }
+}
+
+#[cfg(feature = "serde")]
+impl From<Execution> for ExecutionDef {
+    fn from(execution: Execution) -> Self {
+        match execution {
+            Execution::Global => ExecutionDef::Global,
+            Execution::Pool(pool) => ExecutionDef::Threads(pool.current_num_threads()),
+            Execution::Sequential => ExecutionDef::Sequential
+        }
+    }
+}
+
+#[cfg(test)]
+mod test_execution {
+    use std::sync::Mutex;
+    use std::thread::{self,ThreadId};
+
+    use super::*;
+    use crate::Fitness;
+    use crate::exp::MatyasEnv;
+
+    #[test]
+    fn test_backends_agree() {
+        for execution in &[Execution::Global, Execution::threads(3), Execution::Sequential] {
+            assert_eq!(execution.map(5, |i| i * 2), vec![0, 2, 4, 6, 8]);
+
+            let mut a = vec![0; 4];
+            let mut b = vec![1; 4];
+            execution.for_each_mut(&mut a, &mut b, |i, ai, bi| {
+                *ai = i;
+                *bi += i;
+            });
+            assert_eq!(a, vec![0, 1, 2, 3]);
+            assert_eq!(b, vec![1, 2, 3, 4]);
+
+            let scores = execution.batch(&[1., 2.], |xs| xs.iter().map(|x| x * 2.).collect());
+            assert_eq!(scores, vec![2., 4.]);
+        }
+    }
+
+    /// Matyas which notes the thread each candidate was scored on
+    struct Threads(Mutex<Vec<ThreadId>>);
+
+    impl Fitness for Threads {
+        type Data = Vec<f32>;
+        type Value = f32;
+
+        fn score(&self, candidate: &Vec<f32>) -> f32 {
+            self.0.lock().unwrap().push(thread::current().id());
+            MatyasEnv(-10., 10.).score(candidate)
+        }
+    }
+
+    #[test]
+    fn test_sequential_batches() {
+        let fit_fn = Threads(Mutex::new(Vec::new()));
+        let candidates = vec![vec![0., 0.]; 64];
+        let scores = Execution::Sequential.batch(&candidates, |cs| fit_fn.score_batch(cs));
+        assert_eq!(scores.len(), 64);
+
+        // The default batch scoring stays on the calling thread
+        let threads = fit_fn.0.lock().unwrap();
+        assert_eq!(threads.len(), 64);
+        assert!(threads.iter().all(|t| *t == thread::current().id()));
+        assert!(!is_sequential());
+    }
+}
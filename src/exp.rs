@@ -1,9 +1,15 @@
-use crate::Fitness;
+use std::sync::Mutex;
+
+use rand::prelude::*;
+use rand_distr::Normal;
+
+use crate::{ConstrainedFitness,Fitness,Real};
 
 pub struct MatyasEnv(pub f32, pub f32);
 
 impl Fitness for MatyasEnv {
     type Data = Vec<f32>;
+    type Value = f32;
 
     fn score(&self, candidate: &Vec<f32>) -> f32 {
         let mut x = candidate[0];
@@ -19,6 +25,7 @@ pub struct RastriginEnv { pub dims: usize }
 
 impl Fitness for RastriginEnv {
     type Data = Vec<f32>;
+    type Value = f32;
 
     fn score(&self, candidate: &Vec<f32>) -> f32 {
         let f: f32 = 10. * (self.dims as f32) + 
@@ -29,3 +36,63 @@ impl Fitness for RastriginEnv {
     }
 
 }
+
+/// Sphere centred on a point finer than f32 can resolve, scored in f64
+pub struct FineSphereEnv(pub f64);
+
+impl Fitness for FineSphereEnv {
+    type Data = Vec<f64>;
+    type Value = f64;
+
+    fn score(&self, candidate: &Vec<f64>) -> f64 {
+        -candidate.iter().map(|xi| (xi - self.0).powi(2)).sum::<f64>()
+    }
+
+}
+
+/// Maximizes the sum of the coordinates within the unit disk.  The optimum
+/// sits on the boundary at 1/sqrt(dims) in every dimension.
+pub struct LinearDiskEnv;
+
+impl ConstrainedFitness for LinearDiskEnv {
+    type Data = Vec<f32>;
+    type Value = f32;
+
+    fn score(&self, candidate: &Vec<f32>) -> f32 {
+        candidate.iter().sum()
+    }
+
+    fn inequalities(&self, candidate: &Vec<f32>) -> Vec<f32> {
+        vec![candidate.iter().map(|xi| xi.powi(2)).sum::<f32>() - 1.]
+    }
+
+}
+
+/// Adds gaussian noise to another env's fitness.  Draws are only repeatable
+/// when candidates are scored in a fixed order.
+pub struct NoisyEnv<F> {
+    pub env: F,
+    noise: Normal<f32>,
+    rng: Mutex<StdRng>
+}
+
+impl <F> NoisyEnv<F> {
+    pub fn new(env: F, sd: f32, seed: u64) -> Self {
+        NoisyEnv {
+            env,
+            noise: Normal::new(0., sd).unwrap(),
+            rng: Mutex::new(StdRng::seed_from_u64(seed))
+        }
+    }
+}
+
+impl <F: Fitness> Fitness for NoisyEnv<F> {
+    type Data = F::Data;
+    type Value = F::Value;
+
+    fn score(&self, candidate: &F::Data) -> F::Value {
+        let noise = self.noise.sample(&mut *self.rng.lock().unwrap());
+        self.env.score(candidate) + F::Value::from_f32(noise)
+    }
+
+}
@@ -0,0 +1,162 @@
+use std::sync::Mutex;
+
+use rayon::prelude::*;
+
+use crate::{Direction,Fitness,Real};
+
+/// A stateful scorer, such as a simulator with scratch buffers or foreign
+/// handles.  Only ever used by one thread at a time, so it needn't be Sync.
+pub trait Evaluator {
+    type Data;
+    type Value: Real;
+    fn score(&mut self, candidate: &Self::Data) -> Self::Value;
+}
+
+/// Builds evaluators for fitness functions which can't be shared between
+/// threads.  Wrap it in `PerWorker` to fit it like any other `Fitness`.
+pub trait FitnessFactory: Send + Sync {
+    type Data: Send + Sync;
+    type Evaluator: Evaluator<Data=Self::Data> + Send;
+
+    /// Creates a fresh evaluator for a worker
+    fn build(&self) -> Self::Evaluator;
+
+    /// Direction of the user's objective
+    fn direction(&self) -> Direction {
+        Direction::Maximize
+    }
+}
+
+/// Adapts a `FitnessFactory` into a `Fitness`.  Each worker checks out its
+/// own evaluator for the candidates it scores and hands it back afterwards,
+/// so evaluators are built once per concurrently running worker and reused
+/// across generations.
+#[derive(Debug)]
+pub struct PerWorker<FF: FitnessFactory> {
+    factory: FF,
+    idle: Mutex<Vec<FF::Evaluator>>
+}
+
+impl <FF: FitnessFactory> PerWorker<FF> {
+
+    pub fn new(factory: FF) -> Self {
+        PerWorker { factory, idle: Mutex::new(Vec::new()) }
+    }
+
+    pub fn factory(&self) -> &FF {
+        &self.factory
+    }
+
+    /// Takes an idle evaluator, building one if there are none
+    fn checkout(&self) -> Checkout<'_, FF> {
+        let evaluator = self.idle.lock().expect("Evaluator pool poisoned").pop()
+            .unwrap_or_else(|| self.factory.build());
+        Checkout { pool: self, evaluator: Some(evaluator) }
+    }
+}
+
+/// Precision of a factory's scores
+type Score<FF> = <<FF as FitnessFactory>::Evaluator as Evaluator>::Value;
+
+/// An evaluator on loan to a worker, returned to the pool on drop
+struct Checkout<'a, FF: FitnessFactory> {
+    pool: &'a PerWorker<FF>,
+    evaluator: Option<FF::Evaluator>
+}
+
+impl <'a, FF: FitnessFactory> Checkout<'a, FF> {
+    fn score(&mut self, candidate: &FF::Data) -> Score<FF> {
+        self.evaluator.as_mut().expect("Evaluator already returned").score(candidate)
+    }
+}
+
+impl <'a, FF: FitnessFactory> Drop for Checkout<'a, FF> {
+    fn drop(&mut self) {
+        if let (Some(evaluator), Ok(mut idle)) = (self.evaluator.take(), self.pool.idle.lock()) {
+            idle.push(evaluator);
+        }
+    }
+}
+
+impl <FF: FitnessFactory> Fitness for PerWorker<FF> {
+    type Data = FF::Data;
+    type Value = Score<FF>;
+
+    fn score(&self, candidate: &FF::Data) -> Score<FF> {
+        self.checkout().score(candidate)
+    }
+
+    fn score_batch(&self, candidates: &[FF::Data]) -> Vec<Score<FF>> {
+        if crate::execution::is_sequential() {
+            let mut evaluator = self.checkout();
+            return candidates.iter().map(|c| evaluator.score(c)).collect()
+        }
+        candidates.par_iter()
+            .map_init(|| self.checkout(), |evaluator, c| evaluator.score(c))
+            .collect()
+    }
+
+    fn direction(&self) -> Direction {
+        self.factory.direction()
+    }
+}
+
+#[cfg(test)]
+mod test_factory {
+    use std::cell::Cell;
+    use std::sync::atomic::{AtomicUsize,Ordering};
+
+    use rand_distr::StandardNormal;
+
+    use super::*;
+    use crate::{Execution,Optimizer};
+    use crate::de::*;
+    use crate::exp::*;
+
+    /// Matyas with a scratch counter, which makes it Send but not Sync
+    struct Simulator {
+        calls: Cell<usize>
+    }
+
+    impl Evaluator for Simulator {
+        type Data = Vec<f32>;
+        type Value = f32;
+
+        fn score(&mut self, candidate: &Vec<f32>) -> f32 {
+            self.calls.set(self.calls.get() + 1);
+            MatyasEnv(-10., 10.).score(candidate)
+        }
+    }
+
+    struct SimulatorFactory(AtomicUsize);
+
+    impl FitnessFactory for SimulatorFactory {
+        type Data = Vec<f32>;
+        type Evaluator = Simulator;
+
+        fn build(&self) -> Simulator {
+            self.0.fetch_add(1, Ordering::SeqCst);
+            Simulator { calls: Cell::new(0) }
+        }
+    }
+
+    #[test]
+    fn test_per_worker() {
+        let de = DePlus::builder(2, StandardNormal)
+            .lambda(30)
+            .f(0.1, 1.)
+            .execution(Execution::threads(2))
+            .build().unwrap();
+
+        let fit_fn = PerWorker::new(SimulatorFactory(AtomicUsize::new(0)));
+        let result = de.fit(&fit_fn, 3000, 2020, None, |_stats, _fns_remaining| {});
+        let expected = de.fit(&MatyasEnv(-10., 10.), 3000, 2020, None, |_stats, _fns_remaining| {});
+        assert_eq!(result.candidate, expected.candidate);
+
+        // Evaluators are reused across generations, one per busy worker
+        let built = fit_fn.factory().0.load(Ordering::SeqCst);
+        assert!((1..=2).contains(&built), "Built {} evaluators", built);
+        let calls: usize = fit_fn.idle.lock().unwrap().iter().map(|e| e.calls.get()).sum();
+        assert_eq!(calls, result.evaluations);
+    }
+}
@@ -0,0 +1,305 @@
+use std::any::Any;
+use std::borrow::Cow;
+use std::fmt::Debug;
+use std::panic::{AssertUnwindSafe,catch_unwind};
+
+use crate::{AskTell,Control,Direction,Fitness,Minimize,OptimizationResult,Real,Termination};
+
+/// A fitness function which can fail, such as one backed by a simulation or
+/// an external service.  Returning NaN also counts as a failure.
+pub trait FallibleFitness: Send + Sync {
+    type Data: Send + Sync;
+    type Value: Real;
+    type Error: Send + Debug;
+
+    fn try_score(&self, candidate: &Self::Data) -> Result<Self::Value, Self::Error>;
+
+    /// Scores a whole batch of candidates, returning a result per candidate in
+    /// the same order.  Defaults to scoring each candidate in parallel, or in
+    /// turn when the run is sequential.
+    fn try_score_batch(&self, candidates: &[Self::Data]) -> Vec<Result<Self::Value, Self::Error>> {
+        crate::execution::map_batch(candidates, |c| self.try_score(c))
+    }
+
+    /// Direction of the user's objective
+    fn direction(&self) -> Direction {
+        Direction::Maximize
+    }
+}
+
+impl <F: FallibleFitness> FallibleFitness for Minimize<F> {
+    type Data = F::Data;
+    type Value = F::Value;
+    type Error = F::Error;
+
+    fn try_score(&self, candidate: &F::Data) -> Result<F::Value, F::Error> {
+        self.0.try_score(candidate).map(|s| -s)
+    }
+
+    fn try_score_batch(&self, candidates: &[F::Data]) -> Vec<Result<F::Value, F::Error>> {
+        self.0.try_score_batch(candidates).into_iter()
+            .map(|r| r.map(|s| -s))
+            .collect()
+    }
+
+    fn direction(&self) -> Direction {
+        self.0.direction().flip()
+    }
+}
+
+/// Payload of a panic caught while scoring a candidate
+#[derive(Clone,Debug,PartialEq,Eq)]
+pub struct Panic(pub String);
+
+impl Panic {
+    fn from_payload(payload: Box<dyn Any + Send>) -> Self {
+        let msg = match payload.downcast::<String>() {
+            Ok(msg) => *msg,
+            Err(payload) => match payload.downcast::<&'static str>() {
+                Ok(msg) => msg.to_string(),
+                Err(_) => "Box<dyn Any>".to_string()
+            }
+        };
+        Panic(msg)
+    }
+}
+
+/// Opts a fitness function into panic isolation: a panic while scoring a
+/// candidate becomes a failed evaluation instead of tearing down the run.
+/// Fit it with `AskTell::fit_fallible`; the panic payloads come back in the
+/// result's errors.  Candidates are scored one at a time, so a custom
+/// `score_batch` is bypassed.
+#[derive(Clone,Copy,Debug)]
+pub struct Isolated<F>(pub F);
+
+impl <F: Fitness> FallibleFitness for Isolated<F> {
+    type Data = F::Data;
+    type Value = F::Value;
+    type Error = Panic;
+
+    fn try_score(&self, candidate: &F::Data) -> Result<F::Value, Panic> {
+        catch_unwind(AssertUnwindSafe(|| self.0.score(candidate)))
+            .map_err(Panic::from_payload)
+    }
+
+    fn direction(&self) -> Direction {
+        self.0.direction()
+    }
+}
+
+/// What to do when an evaluation fails
+#[derive(Clone,Copy,Debug,PartialEq,Eq)]
+pub enum FailurePolicy {
+    /// Replace the candidate with a freshly sampled one and score that,
+    /// up to `attempts` times before giving it the worst fitness
+    Resample { attempts: usize },
+
+    /// Give the candidate the worst possible fitness
+    Worst,
+
+    /// Score the same candidate again, up to N times before giving it the
+    /// worst fitness.  Suits transient failures.
+    Retry(usize),
+
+    /// Stop the run and return the error
+    Abort
+}
+
+impl FailurePolicy {
+
+    /// Extra evaluations allowed per failed candidate
+    pub(crate) fn attempts(&self) -> usize {
+        match *self {
+            FailurePolicy::Resample { attempts } => attempts,
+            FailurePolicy::Retry(n) => n,
+            FailurePolicy::Worst | FailurePolicy::Abort => 0
+        }
+    }
+}
+
+/// Replaces NaN fitnesses with the worst fitness, returning how many there were
+pub(crate) fn scrub<V: Real>(fitnesses: &mut [V]) -> usize {
+    let mut failures = 0;
+    for f in fitnesses.iter_mut().filter(|f| f.is_nan()) {
+        *f = V::NEG_INFINITY;
+        failures += 1;
+    }
+    failures
+}
+
+/// Runs an ask/tell optimizer to completion with a fitness function which can
+/// fail, scoring each batch at once
+#[allow(clippy::type_complexity)]
+pub(crate) fn run<O, F, T, FN, R>(
+    opt: &O,
+    fit_fn: &F,
+    policy: FailurePolicy,
+    termination: T,
+    seed: u64,
+    x_in: Option<&O::Data>,
+    callback: FN
+) -> Result<OptimizationResult<O::Data, F::Error, O::Value>, F::Error>
+where
+    O: AskTell,
+    F: FallibleFitness<Data=O::Data, Value=O::Value>,
+    T: Termination<O::Data, O::Value> + 'static,
+    FN: FnMut(O::Stats, Option<usize>) -> R,
+    R: Into<Control>
+{
+    crate::drive(opt, fit_fn.direction(), termination, seed, x_in, callback,
+                 |opt, state, candidates, errors| {
+        let fitnesses = evaluate(opt, state, fit_fn, policy, candidates, errors)?;
+        opt.tell(state, fitnesses);
+        Ok(())
+    })
+}
+
+/// Scores a batch, applying the failure policy to every failed candidate.
+/// Extra evaluations are charged to the run, out of whatever budget is left
+/// once the batch itself is paid for.
+pub(crate) fn evaluate<O, F>(
+    opt: &O,
+    state: &mut O::State,
+    fit_fn: &F,
+    policy: FailurePolicy,
+    candidates: &[O::Data],
+    errors: &mut Vec<F::Error>
+) -> Result<Vec<O::Value>, F::Error>
+where
+    O: AskTell,
+    F: FallibleFitness<Data=O::Data, Value=O::Value>
+{
+    let mut fitnesses = vec![O::Value::NEG_INFINITY; candidates.len()];
+    let mut pending: Vec<_> = (0..candidates.len()).collect();
+    let mut batch = Cow::Borrowed(candidates);
+    let mut extra = 0;
+    for attempt in 0.. {
+        let results = opt.execution().batch(&batch, |cs| fit_fn.try_score_batch(cs));
+        assert_eq!(results.len(), batch.len(), "Expected a result per candidate");
+
+        let mut failed = Vec::new();
+        for (idx, result) in pending.into_iter().zip(results) {
+            match result {
+                Ok(f) if !f.is_nan() => fitnesses[idx] = f,
+                Ok(_) => failed.push(idx),
+                Err(e) if policy == FailurePolicy::Abort => return Err(e),
+                Err(e) => {
+                    errors.push(e);
+                    failed.push(idx);
+                }
+            }
+        }
+        opt.ledger_mut(state).fail(failed.len());
+
+        // Only spend what the budget has left after this batch
+        let spare = opt.remaining(state)
+            .map(|r| r.saturating_sub(candidates.len() + extra))
+            .unwrap_or(usize::MAX);
+        failed.truncate(spare);
+        if failed.is_empty() || attempt >= policy.attempts() {
+            break
+        }
+
+        batch = Cow::Owned(failed.iter().map(|idx| match policy {
+            FailurePolicy::Resample { .. } => opt.resample(state, *idx),
+            _ => candidates[*idx].clone()
+        }).collect());
+        extra += batch.len();
+        pending = failed;
+    }
+
+    opt.ledger_mut(state).charge(extra);
+    Ok(fitnesses)
+}
+
+#[cfg(test)]
+mod test_fallible {
+    use rand_distr::StandardNormal;
+
+    use super::*;
+    use crate::{Fitness,TerminationReason};
+    use crate::de::*;
+    use crate::exp::*;
+
+    /// Matyas which fails for any candidate far from the origin
+    struct Flaky;
+
+    impl FallibleFitness for Flaky {
+        type Data = Vec<f32>;
+        type Value = f32;
+        type Error = String;
+
+        fn try_score(&self, candidate: &Vec<f32>) -> Result<f32, String> {
+            if candidate.iter().any(|xi| xi.abs() > 1.5) {
+                Err(format!("Diverged at {:?}", candidate))
+            } else {
+                Ok(MatyasEnv(-1., 1.).score(candidate))
+            }
+        }
+    }
+
+    fn de() -> DePlus<StandardNormal> {
+        DePlus::builder(2, StandardNormal).lambda(30).f(0.1, 1.).build().unwrap()
+    }
+
+    #[test]
+    fn test_policies() {
+        let de = de();
+        let result = de.fit_fallible(&Flaky, FailurePolicy::Worst, 3000, 2020, None, |_, _| {})
+            .expect("Worst should never abort");
+        assert!(result.failures > 0);
+        assert_eq!(result.failures, result.errors.len());
+        assert_eq!(result.history.last().unwrap().failures, result.failures);
+        assert!(result.fitness.is_finite());
+        assert!(result.candidate.iter().all(|xi| xi.abs() <= 1.5));
+
+        for policy in &[FailurePolicy::Retry(2), FailurePolicy::Resample { attempts: 3 }] {
+            let result = de.fit_fallible(&Flaky, *policy, 3000, 2020, None, |_, _| {}).unwrap();
+            assert!(result.failures > 0);
+            // Retries only come out of what's left once the batch is scored
+            assert!(result.evaluations < 3000 + de.lambda);
+            assert_eq!(result.termination, TerminationReason::BudgetExhausted);
+        }
+
+        let result = de.fit_fallible(&Flaky, FailurePolicy::Abort, 3000, 2020, None, |_, _| {});
+        assert!(result.unwrap_err().starts_with("Diverged"));
+    }
+
+    #[test]
+    fn test_retry_passes() {
+        let de = DePlus { restart_on_stale: Some(5), ..de() };
+        let result = de.fit_fallible(&Flaky, FailurePolicy::Retry(2), 3000, 2020, None, |_, _| {}).unwrap();
+        assert!(result.restarts > 0);
+        assert!(result.failures > 0);
+
+        // Retries count against the pass they were made in
+        let passes: usize = result.passes.iter().map(|p| p.evaluations).sum();
+        assert_eq!(passes, result.evaluations);
+    }
+
+    /// Matyas which panics for any candidate far from the origin
+    struct Explosive;
+
+    impl Fitness for Explosive {
+        type Data = Vec<f32>;
+        type Value = f32;
+
+        fn score(&self, candidate: &Vec<f32>) -> f32 {
+            if candidate[0].abs() > 1.5 {
+                panic!("Blew up at {}", candidate[0]);
+            }
+            MatyasEnv(-1., 1.).score(candidate)
+        }
+    }
+
+    #[test]
+    fn test_isolated() {
+        let fit_fn = Isolated(Explosive);
+        let result = de().fit_fallible(&fit_fn, FailurePolicy::Worst, 3000, 2020, None, |_, _| {})
+            .expect("Panics should be caught");
+        assert_eq!(result.termination, TerminationReason::BudgetExhausted);
+        assert!(result.failures > 0);
+        assert!(result.errors.iter().all(|e| e.0.starts_with("Blew up at")));
+        assert!(result.candidate[0].abs() <= 1.5);
+    }
+}
@@ -2,9 +2,13 @@ use std::marker::PhantomData;
 use rand::prelude::*;
 use rand_distr::{Distribution,Binomial,Uniform};
 
+#[cfg(feature = "serde")]
+use serde::{Deserialize,Deserializer,Serialize,Serializer};
+
 use super::Crossover;
 
 #[derive(Copy,Clone,Debug)]
+#[cfg_attr(feature = "serde", derive(Serialize,Deserialize))]
 pub enum CrossoverType {
     /// Uniform over the entire genome
     Binomial,
@@ -25,6 +29,21 @@ impl <A> Linear<A> {
     }
 }
 
+/// Written in configs as just its crossover type
+#[cfg(feature = "serde")]
+impl <A> Serialize for Linear<A> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        self.0.serialize(serializer)
+    }
+}
+
+#[cfg(feature = "serde")]
+impl <'de, A> Deserialize<'de> for Linear<A> {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        CrossoverType::deserialize(deserializer).map(Linear::new)
+    }
+}
+
 impl <A: Clone + Send + Sync + std::fmt::Debug> Crossover for Linear<A> {
     type Encoded = Vec<A>;
 
@@ -53,9 +72,7 @@ impl <A: Clone + Send + Sync + std::fmt::Debug> Crossover for Linear<A> {
             CrossoverType::TwoPoint => {
                 let start = Uniform::new(0, p1.len() - 1).sample(rng);
                 let stop = Uniform::new(start + 1, p1.len()).sample(rng);
-                for idx in start..stop {
-                    offspring[idx] = p2[idx].clone();
-                }
+                offspring[start..stop].clone_from_slice(&p2[start..stop]);
             }
         }
         offspring
@@ -2,19 +2,41 @@ extern crate rand;
 extern crate rayon;
 extern crate float_ord;
 
+#[cfg(feature = "serde")]
+use std::convert::TryFrom;
 use std::fmt::Debug;
 use std::marker::PhantomData;
-use float_ord::FloatOrd;
 
 use rand::prelude::*;
 use rand_distr::Uniform;
-use rayon::prelude::*;
+
+#[cfg(feature = "serde")]
+use serde::{Deserialize,Serialize};
 
 use super::*;
-use crate::{Fitness,Optimizer};
+use crate::{AskTell,Control,Fitness,Optimizer,OptimizationResult,Real,RestartCause,Termination,TerminationReason};
+use crate::ledger::Ledger;
+use crate::stats::GenerationStats;
+use crate::execution::Execution;
+use crate::seed::{SeedSequence,Stream};
+use crate::config::{ConfigError,check};
+use crate::noise::{Estimate,Noise,Sampling,pooled_variance};
+use crate::constraints::{self,ConstrainedAskTell,ConstrainedFitness,ConstraintHandling,feasibility_better,fittest};
+use crate::fallible::{self,FailurePolicy,FallibleFitness};
+use crate::init;
+use crate::steady::{self,Displacement,Kind,Slots,SteadyState,Ticket,UpdateMode};
 
-#[derive(Clone,Copy,Debug)]
-pub struct FastGA<G,M,CO,S,E> {
+#[derive(Clone,Debug)]
+#[cfg_attr(feature = "serde", derive(Serialize,Deserialize), serde(
+    try_from = "FastGABuilder<G,M,CO,S>",
+    bound(serialize = "G: Serialize, M: Serialize, CO: Serialize, S: Serialize",
+          deserialize = "E: Send + Sync + Clone + Debug,
+                         V: Real,
+                         G: Genome<Encoded=E> + Deserialize<'de>,
+                         M: Mutator<Encoded=E> + Deserialize<'de>,
+                         CO: Crossover<Encoded=E> + Deserialize<'de>,
+                         S: Selector + Deserialize<'de>")))]
+pub struct FastGA<G,M,CO,S,E,V = f32> {
     /// Population size.
     pub lambda: usize,
 
@@ -31,14 +53,35 @@ pub struct FastGA<G,M,CO,S,E> {
 
     pub selector: S,
 
-    encoded: PhantomData<E>
+    /// How candidates with constraint violations are ranked for selection and
+    /// elitism.  Only matters when fit with a `ConstrainedFitness`.
+    pub constraints: ConstraintHandling,
+
+    /// Where children are bred and scored
+    pub execution: Execution,
+
+    /// If provided, averages repeated samples of each child and rescores the
+    /// elite, so a lucky draw isn't carried forward forever.  Nothing is
+    /// compared pairwise, so the samples per child stay fixed.
+    pub noise: Option<Noise>,
+
+    /// Whether children replace the parents a generation at a time or take a
+    /// member's place as soon as each is scored
+    pub update: UpdateMode,
+
+    /// Which member a steady-state child takes the place of
+    pub displacement: Displacement,
+
+    #[cfg_attr(feature = "serde", serde(skip))]
+    encoded: PhantomData<(E, V)>
 }
 
 impl <E: Send + Sync + Clone + Debug,
       G: Genome<Encoded=E>, 
       M: Mutator<Encoded=E>, 
       CO: Crossover<Encoded=E>,
-      S: Selector> FastGA<G,M,CO,S,E> {
+      S: Selector,
+      V: Real> FastGA<G,M,CO,S,E,V> {
 
 //impl <G,M,CO,S,E> FastGA<G,M,CO,S,E> {
 
@@ -59,120 +102,813 @@ impl <E: Send + Sync + Clone + Debug,
             mutator,
             cross_over,
             selector,
+            constraints: ConstraintHandling::Feasibility,
+            execution: Execution::Global,
+            noise: None,
+            update: UpdateMode::Generational,
+            displacement: Displacement::Worst,
             encoded: PhantomData
         }
     }
+}
+
+impl <E: Send + Sync + Clone + Debug,
+      G: Genome<Encoded=E>,
+      M: Mutator<Encoded=E>,
+      CO: Crossover<Encoded=E>,
+      S: Selector> FastGA<G,M,CO,S,E> {
+
+    /// Starts a validated configuration from the operators, which have no
+    /// defaults.  The fitness precision is picked when it's built.
+    pub fn builder(genome: G, mutator: M, cross_over: CO, selector: S) -> FastGABuilder<G,M,CO,S> {
+        FastGABuilder {
+            lambda: default_lambda(),
+            elitism: default_elitism(),
+            max_mutate: default_max_mutate(),
+            genome,
+            mutator,
+            cross_over,
+            selector,
+            constraints: ConstraintHandling::default(),
+            execution: Execution::default(),
+            noise: None,
+            update: UpdateMode::default(),
+            displacement: Displacement::default()
+        }
+    }
     
 }
-   
+
+/// Builds a `FastGA`, checking its settings and operators hang together.
+/// Configs are read through here too, so any setting left out takes the
+/// builder's default.
+#[derive(Clone,Debug)]
+#[cfg_attr(feature = "serde", derive(Deserialize))]
+pub struct FastGABuilder<G,M,CO,S> {
+    #[cfg_attr(feature = "serde", serde(default = "default_lambda"))]
+    lambda: usize,
+    #[cfg_attr(feature = "serde", serde(default = "default_elitism"))]
+    elitism: bool,
+    #[cfg_attr(feature = "serde", serde(default = "default_max_mutate"))]
+    max_mutate: f32,
+    genome: G,
+    mutator: M,
+    cross_over: CO,
+    selector: S,
+    #[cfg_attr(feature = "serde", serde(default))]
+    constraints: ConstraintHandling,
+    #[cfg_attr(feature = "serde", serde(default))]
+    execution: Execution,
+    #[cfg_attr(feature = "serde", serde(default))]
+    noise: Option<Noise>,
+    #[cfg_attr(feature = "serde", serde(default))]
+    update: UpdateMode,
+    #[cfg_attr(feature = "serde", serde(default))]
+    displacement: Displacement
+}
+
+fn default_lambda() -> usize { 100 }
+fn default_elitism() -> bool { true }
+fn default_max_mutate() -> f32 { 1. }
+
+impl <G,M,CO,S> FastGABuilder<G,M,CO,S> {
+
+    /// Population size.  Defaults to 100.
+    pub fn lambda(mut self, lambda: usize) -> Self {
+        self.lambda = lambda;
+        self
+    }
+
+    /// Defaults to keeping the best candidates
+    pub fn elitism(mut self, elitism: bool) -> Self {
+        self.elitism = elitism;
+        self
+    }
+
+    /// Largest fraction of genes mutated at once.  Defaults to 1.
+    pub fn max_mutate(mut self, max_mutate: f32) -> Self {
+        self.max_mutate = max_mutate;
+        self
+    }
+
+    pub fn constraints(mut self, constraints: ConstraintHandling) -> Self {
+        self.constraints = constraints;
+        self
+    }
+
+    pub fn execution(mut self, execution: Execution) -> Self {
+        self.execution = execution;
+        self
+    }
+
+    pub fn noise(mut self, noise: Noise) -> Self {
+        self.noise = Some(noise);
+        self
+    }
+
+    /// Defaults to generational
+    pub fn update(mut self, update: UpdateMode) -> Self {
+        self.update = update;
+        self
+    }
+
+    /// Defaults to displacing the worst member
+    pub fn displacement(mut self, displacement: Displacement) -> Self {
+        self.displacement = displacement;
+        self
+    }
+
+    pub fn build<E, V>(self) -> Result<FastGA<G,M,CO,S,E,V>, ConfigError>
+    where
+        E: Send + Sync + Clone + Debug,
+        V: Real,
+        G: Genome<Encoded=E>,
+        M: Mutator<Encoded=E>,
+        CO: Crossover<Encoded=E>,
+        S: Selector
+    {
+        check(self.lambda > 0, "lambda", "needs at least one member")?;
+        check(self.max_mutate > 0. && self.max_mutate <= 1., "max_mutate",
+              "needs to be within (0, 1]")?;
+        self.genome.validate()?;
+        self.mutator.validate()?;
+        if let (Some(genes), Some(dims)) = (self.genome.dims(), self.mutator.dims()) {
+            check(genes == dims, "mutator", "needs to cover every gene")?;
+        }
+        self.cross_over.validate()?;
+        self.selector.validate()?;
+        self.constraints.validate()?;
+        if let Some(noise) = &self.noise {
+            noise.validate()?;
+        }
+        self.displacement.validate()?;
+        if self.update == UpdateMode::SteadyState {
+            check(self.noise.is_none(), "update", "steady-state updates don't support noise")?;
+        }
+
+        let mut ga = FastGA::new(self.lambda, self.elitism, self.max_mutate,
+                                 self.genome, self.mutator, self.cross_over, self.selector);
+        ga.constraints = self.constraints;
+        ga.execution = self.execution;
+        ga.noise = self.noise;
+        ga.update = self.update;
+        ga.displacement = self.displacement;
+        Ok(ga)
+    }
+}
+
+#[cfg(feature = "serde")]
 impl <E: Send + Sync + Clone + Debug,
-      G: Genome<Encoded=E>, 
-      M: Mutator<Encoded=E>, 
+      G: Genome<Encoded=E>,
+      M: Mutator<Encoded=E>,
       CO: Crossover<Encoded=E>,
-      S: Selector> Optimizer for FastGA<G,M,CO,S,E> {
-    type Stats = f32;
-    type Data = E;
+      S: Selector,
+      V: Real> TryFrom<FastGABuilder<G,M,CO,S>> for FastGA<G,M,CO,S,E,V> {
+    type Error = ConfigError;
 
-    fn fit<F: Fitness<Data=E>, FN: FnMut(f32, usize) -> ()>(
-        &self, 
-        fit_fn: &F, 
-        total_fns: usize, 
-        seed: u64, 
-        x_in: Option<&E>,
-        mut callback: FN
-    ) -> (f32, E) {
+    fn try_from(builder: FastGABuilder<G,M,CO,S>) -> Result<Self, ConfigError> {
+        builder.build()
+    }
+}
+   
+/// Ask/tell state for a FastGA run
+#[derive(Debug)]
+#[cfg_attr(feature = "serde", derive(Serialize,Deserialize))]
+pub struct GaState<E, V = f32> {
+    /// Streams for the run, one child per generation
+    seeds: SeedSequence,
+
+    rng: Stream,
+
+    x_in: Option<E>,
+
+    parents: Vec<E>,
+    fitness: Vec<V>,
+
+    /// Fitness samples behind each parent's fitness
+    estimates: Vec<Estimate<V>>,
+
+    /// Samples per child and the elite being rescored
+    sampling: Sampling,
+
+    /// Parent which has held its place as the best under noise.  Only a
+    /// child which passes the replacement test takes over.
+    elite: Option<usize>,
+
+    /// Constraint violation of each parent.  Always zero when unconstrained.
+    violations: Vec<V>,
+
+    /// Children handed out by the last ask
+    children: Vec<E>,
+
+    /// Bookkeeping for steady-state runs, which update the population as
+    /// each evaluation finishes
+    #[cfg_attr(feature = "serde", serde(skip))]
+    steady: Option<Slots>,
 
-        let mut rng = rand::rngs::StdRng::seed_from_u64(seed);
+    /// Steady-state parents' ranks, which children select on while there
+    /// are violations.  Taken afresh once per generation's worth.
+    #[cfg_attr(feature = "serde", serde(skip))]
+    ranks: Vec<V>,
 
+    /// Whether the parents still need their initial scores
+    initialized: bool,
+
+    /// Whether the children handed out by the last ask are still unscored
+    pending: bool,
+
+    ledger: Ledger<E, V>,
+
+    best_fit: V,
+    best_viol: V,
+    best: Option<E>
+}
+
+impl <E: Send + Sync + Clone + Debug,
+      G: Genome<Encoded=E>,
+      M: Mutator<Encoded=E>,
+      CO: Crossover<Encoded=E>,
+      S: Selector,
+      V: Real> FastGA<G,M,CO,S,E,V> {
+
+    /// Samples a fresh set of parents.  A genome with an initializer draws
+    /// them from the pass's own stream, and may draw extras.
+    fn init_parents(&self, rng: &mut Stream, pass_seeds: &SeedSequence, x_in: Option<&E>) -> Vec<E> {
         // Create initial genome set
-        let mut parents: Vec<_> = (0..self.lambda).map(|_| {
-            self.genome.new(&mut rng)
-        }).collect();
+        let drawn = self.genome.initialize(self.lambda, &mut init::stream(pass_seeds));
+        let mut parents: Vec<_> = drawn.unwrap_or_else(|| (0..self.lambda).map(|_| {
+            self.genome.new(rng)
+        }).collect());
 
         if let Some(x_in) = x_in {
             parents[0] = x_in.clone();
         }
+        parents
+    }
+
+    /// Whether the next batch rescores the elite
+    fn rescores(&self, state: &GaState<E, V>) -> bool {
+        self.elitism && self.noise.as_ref()
+            .map(|n| n.reevaluates(state.ledger.generation()))
+            .unwrap_or(false)
+    }
+
+    /// Parent carried over by elitism
+    fn elite_index(&self, state: &GaState<E, V>) -> usize {
+        state.elite.unwrap_or_else(|| self.constraints.best_index(
+            &state.fitness, &state.violations, state.ledger.generation()))
+    }
+
+    /// Settles the elite under noise: the carried elite, which sits at the
+    /// end of the parents, keeps its place unless `challenger` beats it on
+    /// violation or passes the replacement test
+    fn contest_elite(&self, noise: &Noise, state: &mut GaState<E, V>, challenger: usize) -> usize {
+        let elite = match state.elite {
+            Some(_) if self.elitism => state.parents.len() - 1,
+            _ => challenger
+        };
+        let winner = if elite == challenger || state.violations[challenger] < state.violations[elite] {
+            challenger
+        } else if state.violations[challenger] > state.violations[elite] {
+            elite
+        } else {
+            let pooled = pooled_variance(state.estimates.iter());
+            let (c, e) = (state.estimates[challenger], state.estimates[elite]);
+            if state.sampling.replaces(noise, &c, &e, pooled) { challenger } else { elite }
+        };
+        state.sampling.adapt(noise);
+        state.elite = Some(winner);
+        winner
+    }
 
-        let mut fns = self.lambda;
+    /// Breeds a full set of children from the current parents
+    fn breed(&self, state: &mut GaState<E, V>) {
+        // Streams for this generation's children
+        let gen_seeds = state.seeds.spawn(state.ledger.generation() as u64);
 
-        let mut fitness: Vec<_> = parents.iter().map(|p| {
-            fit_fn.score(p)
+        // With violations present, select on each parent's rank instead
+        let ranks;
+        let fitness = if state.violations.iter().any(|v| *v > V::ZERO) {
+            ranks = self.constraints.rank(
+                &state.fitness, &state.violations, state.ledger.generation(), &mut state.rng);
+            &ranks
+        } else {
+            &state.fitness
+        };
+        let parents = &state.parents;
+        state.children = self.execution.map(self.lambda, |i| {
+            // Each child gets its own stream
+            let mut local_rng = gen_seeds.spawn(i as u64).rng();
+            self.child(parents, fitness, &mut local_rng)
+        });
+    }
+
+    /// Breeds a single child, selecting its parents on `fitness`
+    fn child(&self, parents: &[E], fitness: &[V], rng: &mut Stream) -> E {
+        // Grab the number of parents of interest
+        let parents: Vec<_> = (0..self.cross_over.parents_to_select()).map(|_| {
+            &parents[self.selector.choose(fitness, rng)]
         }).collect();
 
-        // Create copy spot
-        let mut children = parents.clone();
-        while fns < total_fns {
-            let best_idx = (0..parents.len())
-                .max_by_key(|idx| FloatOrd(fitness[*idx]))
-                .expect("lambda needs to be greater than zero!");
-
-            callback(fitness[best_idx], total_fns - fns);
-
-            // Generate new seed
-            let new_seed: u64 = rng.sample(Uniform::new(0, 1<<63));
-            children.par_iter_mut().enumerate().for_each(|(i, c)| {
-                // Initialize new thread seed
-                let mut local_rng = rand::rngs::StdRng::seed_from_u64(new_seed + i as u64);
-
-                // Grab the number of parents of interest
-                let parents: Vec<_> = (0..self.cross_over.parents_to_select()).map(|_| {
-                    &parents[self.selector.choose(&fitness, &mut local_rng)]
-                }).collect();
-
-                // Breed new child
-                let mut child = self.cross_over.cross(&parents, &mut local_rng);
-
-                // Figure out the mutation points
-                let num_genes = self.genome.size(&child);
-                let max_dim = ((self.max_mutate * num_genes as f32) as usize).max(2);
-                let mut p: Vec<_> = (1..max_dim)
-                    .map(|m| 1. / (m as f32).powf(1.5))
-                    .collect();
-                let total = p.iter().sum::<f32>();
-                p.iter_mut().for_each(|mi| *mi /= total);
-                let choices: Vec<_> = (1..max_dim).collect();
-                let to_mutate = choices
-                    .choose_weighted(&mut local_rng, |i| p[*i - 1])
-                    .expect("Have an empty genome!  Unsupported");
-
-                let indices: Vec<_> = Uniform::new(0, num_genes)
-                    .sample_iter(&mut local_rng)
-                    .take(*to_mutate)
-                    .collect();
-
-                // Mutate the child
-                self.mutator.mutate(&indices, &mut child, &mut local_rng);
-                std::mem::swap(c, &mut child);
-            });
-
-            // Compute new fitness for the children
-            let mut children_fit: Vec<_> = children.par_iter()
-                .map(|c| fit_fn.score(c))
-                .collect();
+        // Breed new child
+        let mut child = self.cross_over.cross(&parents, rng);
+
+        // Figure out the mutation points
+        let num_genes = self.genome.size(&child);
+        let max_dim = ((self.max_mutate * num_genes as f32) as usize).max(2);
+        let mut p: Vec<_> = (1..max_dim)
+            .map(|m| 1. / (m as f32).powf(1.5))
+            .collect();
+        let total = p.iter().sum::<f32>();
+        p.iter_mut().for_each(|mi| *mi /= total);
+        let choices: Vec<_> = (1..max_dim).collect();
+        let to_mutate = choices
+            .choose_weighted(rng, |i| p[*i - 1])
+            .expect("Have an empty genome!  Unsupported");
+
+        let indices: Vec<_> = Uniform::new(0, num_genes)
+            .sample_iter(&mut *rng)
+            .take(*to_mutate)
+            .collect();
+
+        // Mutate the child
+        self.mutator.mutate(&indices, &mut child, rng);
+        child
+    }
+}
+
+impl <E: Send + Sync + Clone + Debug,
+      G: Genome<Encoded=E>,
+      M: Mutator<Encoded=E>,
+      CO: Crossover<Encoded=E>,
+      S: Selector,
+      V: Real> AskTell for FastGA<G,M,CO,S,E,V> {
+    type State = GaState<E, V>;
+
+    fn init<T: Termination<E, V> + 'static>(
+        &self,
+        termination: T,
+        seed: u64,
+        x_in: Option<&E>
+    ) -> GaState<E, V> {
+        let seeds = SeedSequence::new(seed);
+        let mut rng = seeds.rng();
+        let parents = self.init_parents(&mut rng, &seeds.spawn(0), x_in);
+        GaState {
+            seeds,
+            rng,
+            x_in: x_in.cloned(),
+            parents,
+            fitness: Vec::new(),
+            estimates: Vec::new(),
+            sampling: Sampling::new(self.noise.as_ref()),
+            elite: None,
+            violations: Vec::new(),
+            children: Vec::new(),
+            steady: None,
+            ranks: Vec::new(),
+            initialized: false,
+            pending: false,
+            ledger: Ledger::new(termination),
+            best_fit: V::NEG_INFINITY,
+            best_viol: V::INFINITY,
+            best: None
+        }
+    }
+
+    fn ask(&self, state: &mut GaState<E, V>) -> Vec<E> {
+        if self.is_done(state) {
+            return Vec::new()
+        }
+
+        state.pending = true;
+        if !state.initialized {
+            return state.sampling.expand(state.parents.clone(), Vec::new())
+        }
+
+        if state.children.is_empty() {
+            self.breed(state);
+        }
+
+        // The elite is rescored alongside the children
+        let incumbents = if self.rescores(state) {
+            let best_idx = self.elite_index(state);
+            vec![(best_idx, state.parents[best_idx].clone())]
+        } else {
+            Vec::new()
+        };
+        state.sampling.expand(state.children.clone(), incumbents)
+    }
+
+    fn tell(&self, state: &mut GaState<E, V>, fitnesses: Vec<V>) {
+        let violations = vec![V::ZERO; fitnesses.len()];
+        self.tell_constrained(state, fitnesses, violations);
+    }
+
+    fn termination(&self, state: &GaState<E, V>) -> Option<TerminationReason> {
+        // Never exceed the budget.  The initial parents are always scored,
+        // and steady-state runs hold back what they can't afford themselves.
+        let stopped = state.ledger.stopped();
+        if stopped.is_some() || !state.initialized || state.steady.is_some() {
+            return stopped
+        }
+
+        let elite = if self.rescores(state) { 1 } else { 0 };
+        let cost = state.sampling.cost(self.lambda) + elite;
+        match state.ledger.remaining() {
+            Some(remaining) if remaining < cost => Some(TerminationReason::BudgetExhausted),
+            _ => None
+        }
+    }
+
+    fn restart(&self, state: &mut GaState<E, V>) {
+        let pass_seeds = state.seeds.spawn(state.ledger.restarts() as u64 + 1);
+        state.parents = self.init_parents(&mut state.rng, &pass_seeds, state.x_in.as_ref());
+        state.fitness.clear();
+        state.estimates.clear();
+        state.sampling = Sampling::new(self.noise.as_ref());
+        state.elite = None;
+        state.violations.clear();
+        state.children.clear();
+        state.initialized = false;
+        state.pending = false;
+        if state.steady.is_some() {
+            self.begin(state);
+        }
+        state.ledger.restart(RestartCause::Requested);
+    }
+
+    fn resample(&self, state: &mut GaState<E, V>, idx: usize) -> E {
+        // The elite is rescored as it is
+        let idx = match state.sampling.slot(idx) {
+            Some(slot) => slot,
+            None => return state.parents[state.sampling.incumbent(idx)].clone()
+        };
+
+        let slot = if state.initialized {
+            &mut state.children[idx]
+        } else {
+            &mut state.parents[idx]
+        };
+
+        // So are children with other samples already in
+        if state.sampling.samples() == 1 {
+            *slot = self.genome.new(&mut state.rng);
+        }
+        slot.clone()
+    }
+
+    fn ledger<'a>(&self, state: &'a GaState<E, V>) -> &'a Ledger<E, V> {
+        &state.ledger
+    }
+
+    fn ledger_mut<'a>(&self, state: &'a mut GaState<E, V>) -> &'a mut Ledger<E, V> {
+        &mut state.ledger
+    }
+
+    fn execution(&self) -> &Execution {
+        &self.execution
+    }
+
+    fn best<'a>(&self, state: &'a GaState<E, V>) -> (V, &'a E) {
+        let best = state.best.as_ref()
+            .expect("Population needs to be scored before it has a best!");
+        (state.best_fit, best)
+    }
+
+    fn stats(&self, state: &GaState<E, V>) -> GenerationStats<E, V> {
+        let (best_fit, best) = self.best(state);
+        state.ledger.stats(
+            &state.fitness,
+            self.genome.diversity(&state.parents),
+            (best_fit, best.clone()))
+    }
+
+    fn population(&self, state: &GaState<E, V>) -> (Vec<E>, Vec<V>) {
+        (state.parents.clone(), state.fitness.clone())
+    }
+
+    fn update_mode(&self) -> UpdateMode {
+        self.update
+    }
+
+    fn fit_fallible<F, T, FN, R>(
+        &self,
+        fit_fn: &F,
+        policy: FailurePolicy,
+        termination: T,
+        seed: u64,
+        x_in: Option<&E>,
+        callback: FN
+    ) -> Result<OptimizationResult<E, F::Error, V>, F::Error>
+    where
+        F: FallibleFitness<Data=E, Value=V>,
+        T: Termination<E, V> + 'static,
+        FN: FnMut(Self::Stats, Option<usize>) -> R,
+        R: Into<Control>
+    {
+        match self.update {
+            UpdateMode::Generational => fallible::run(self, fit_fn, policy, termination, seed, x_in, callback),
+            UpdateMode::SteadyState => steady::run_fallible(self, fit_fn, policy, termination, seed, x_in, callback)
+        }
+    }
+}
+
+impl <E: Send + Sync + Clone + Debug,
+      G: Genome<Encoded=E>,
+      M: Mutator<Encoded=E>,
+      CO: Crossover<Encoded=E>,
+      S: Selector,
+      V: Real> ConstrainedAskTell for FastGA<G,M,CO,S,E,V> {
+
+    fn tell_constrained(&self, state: &mut GaState<E, V>, fitnesses: Vec<V>, violations: Vec<V>) {
+        assert!(state.pending, "tell called without a pending ask!");
+        assert_eq!(fitnesses.len(), violations.len(), "Expected a violation per score");
+        let evaluations = fitnesses.len();
+        let (estimates, rescored) = state.sampling.collapse(&fitnesses);
+        let (viols, _) = state.sampling.collapse(&violations);
+        let expected = if state.initialized { self.lambda } else { state.parents.len() };
+        assert_eq!(estimates.len(), expected, "Expected a score per member");
+        let fitnesses: Vec<_> = estimates.iter().map(|e| e.mean()).collect();
+        let violations: Vec<_> = viols.iter().map(|v| v.mean()).collect();
+
+        // Fold the elite's new samples into its estimate before it's carried over
+        for (i, f) in rescored {
+            state.estimates[i].push(f);
+            state.fitness[i] = state.estimates[i].mean();
+        }
+
+        if !state.initialized {
+            state.fitness = fitnesses;
+            state.estimates = estimates;
+            state.violations = violations;
+            state.initialized = true;
+
+            // Initializers may draw extras, leaving only the fittest to carry on
+            if state.parents.len() > self.lambda {
+                let keep = fittest(&state.fitness, &state.violations, self.lambda);
+                state.parents = init::select(&state.parents, &keep);
+                state.fitness = init::select(&state.fitness, &keep);
+                state.estimates = init::select(&state.estimates, &keep);
+                state.violations = init::select(&state.violations, &keep);
+            }
+        } else {
+            let best_idx = self.elite_index(state);
+
+            let mut children = std::mem::take(&mut state.children);
+            let mut children_fit = fitnesses;
+            let mut children_est = estimates;
+            let mut children_viol = violations;
 
             // If elitism is on, copy the best parent over from the previous
             // generation
             if self.elitism {
-                children.push(parents[best_idx].clone());
-                children_fit.push(fitness[best_idx]);
+                children.push(state.parents[best_idx].clone());
+                children_fit.push(state.fitness[best_idx]);
+                children_est.push(state.estimates[best_idx]);
+                children_viol.push(state.violations[best_idx]);
             }
 
             // Swap children and parents
-            std::mem::swap(&mut children, &mut parents);
-            fitness = children_fit;
-            while children.len() > self.lambda {
-                children.pop();
+            state.parents = children;
+            state.fitness = children_fit;
+            state.estimates = children_est;
+            state.violations = children_viol;
+        }
+
+        state.pending = false;
+        state.ledger.record(evaluations);
+        self.track_best(state);
+
+        let stats = self.stats(state);
+        state.ledger.check(&stats);
+    }
+
+    fn fit_constrained<C, T, FN, R>(
+        &self,
+        fit_fn: &C,
+        termination: T,
+        seed: u64,
+        x_in: Option<&E>,
+        callback: FN
+    ) -> OptimizationResult<E, (), V>
+    where
+        C: ConstrainedFitness<Data=E, Value=V>,
+        T: Termination<E, V> + 'static,
+        FN: FnMut(Self::Stats, Option<usize>) -> R,
+        R: Into<Control>
+    {
+        match self.update {
+            UpdateMode::Generational => constraints::run(self, fit_fn, termination, seed, x_in, callback),
+            UpdateMode::SteadyState => steady::run_constrained(self, fit_fn, termination, seed, x_in, callback)
+        }
+    }
+}
+
+impl <E: Send + Sync + Clone + Debug,
+      G: Genome<Encoded=E>,
+      M: Mutator<Encoded=E>,
+      CO: Crossover<Encoded=E>,
+      S: Selector,
+      V: Real> FastGA<G,M,CO,S,E,V> {
+
+    /// Updates the best candidate seen so far from the current parents
+    fn track_best(&self, state: &mut GaState<E, V>) {
+        let mut best_idx = ConstraintHandling::Feasibility.best_index(
+            &state.fitness, &state.violations, 0);
+        if let Some(noise) = &self.noise {
+            best_idx = self.contest_elite(noise, state, best_idx);
+        }
+        let best = (state.fitness[best_idx], state.violations[best_idx]);
+        if best.1 <= V::ZERO {
+            state.ledger.observe(best.0, self.noise.is_some());
+        }
+
+        // With noise, the best is always the current elite
+        if state.best.is_none() || self.noise.is_some() ||
+            feasibility_better(best, (state.best_fit, state.best_viol)) {
+            state.best_fit = best.0;
+            state.best_viol = best.1;
+            state.best = Some(state.parents[best_idx].clone());
+        }
+    }
+
+    /// Ranks the steady-state parents if there are violations and they
+    /// aren't ranked already
+    fn rank_parents(&self, state: &mut GaState<E, V>) {
+        if !state.violations.iter().any(|v| *v > V::ZERO) {
+            state.ranks.clear();
+        } else if state.ranks.len() != state.parents.len() {
+            state.ranks = self.constraints.rank(
+                &state.fitness, &state.violations, state.ledger.generation(), &mut state.rng);
+        }
+    }
+
+    /// Member a steady-state child would take the place of, if any can go.
+    /// With elitism the best stays put.
+    fn victim(&self, state: &mut GaState<E, V>) -> Option<usize> {
+        let slots = state.steady.as_ref().expect("Steady-state run without its slots");
+        let best = ConstraintHandling::Feasibility.best_index(&state.fitness, &state.violations, 0);
+        let eligible: Vec<_> = (0..state.parents.len())
+            .filter(|i| slots.scored[*i] && !(self.elitism && *i == best))
+            .collect();
+        if eligible.is_empty() {
+            return None
+        }
+
+        let entrants = match self.displacement {
+            Displacement::Worst => eligible,
+            Displacement::Tournament(k) => {
+                let rng = &mut state.rng;
+                (0..k).map(|_| eligible[rng.gen_range(0, eligible.len())]).collect()
             }
-            fns += self.lambda;
+        };
+
+        // Each entrant is compared with the one to beat, keeping the first
+        // of equals
+        let generation = state.ledger.generation();
+        let (fitness, violations, rng) = (&state.fitness, &state.violations, &mut state.rng);
+        entrants.into_iter().reduce(|least, i| {
+            let (a, b) = ((fitness[least], violations[least]), (fitness[i], violations[i]));
+            if self.constraints.better(a, b, generation, rng) { i } else { least }
+        })
+    }
+}
+
+impl <E: Send + Sync + Clone + Debug,
+      G: Genome<Encoded=E>,
+      M: Mutator<Encoded=E>,
+      CO: Crossover<Encoded=E>,
+      S: Selector,
+      V: Real> Optimizer for FastGA<G,M,CO,S,E,V> {
+    type Stats = GenerationStats<E, V>;
+    type Data = E;
+    type Value = V;
+
+    fn fit<F, T, FN, R>(
+        &self,
+        fit_fn: &F,
+        termination: T,
+        seed: u64,
+        x_in: Option<&E>,
+        callback: FN
+    ) -> OptimizationResult<E, (), V>
+    where
+        F: Fitness<Data=E, Value=V>,
+        T: Termination<E, V> + 'static,
+        FN: FnMut(Self::Stats, Option<usize>) -> R,
+        R: Into<Control>
+    {
+        match self.update {
+            UpdateMode::Generational => crate::run(self, fit_fn, termination, seed, x_in, callback),
+            UpdateMode::SteadyState => steady::run(self, fit_fn, termination, seed, x_in, callback)
+        }
+    }
+
+}
+
+impl <E: Send + Sync + Clone + Debug,
+      G: Genome<Encoded=E>,
+      M: Mutator<Encoded=E>,
+      CO: Crossover<Encoded=E>,
+      S: Selector,
+      V: Real> SteadyState for FastGA<G,M,CO,S,E,V> {
+
+    fn begin(&self, state: &mut GaState<E, V>) {
+        let n = state.parents.len();
+        state.fitness = vec![V::NEG_INFINITY; n];
+        state.estimates = vec![Estimate::default(); n];
+        state.violations = vec![V::ZERO; n];
+        state.initialized = true;
+        state.steady = Some(Slots::new(n));
+        state.ranks.clear();
+    }
+
+    fn propose(&self, state: &mut GaState<E, V>) -> Option<(Ticket, E)> {
+        let pass = state.ledger.restarts();
+        let slots = state.steady.as_mut().expect("Steady-state run without its slots");
+
+        // Parents waiting on their scores go out first
+        if let Some(slot) = slots.queue.pop_front() {
+            return Some((Ticket { pass, slot, kind: Kind::Member }, state.parents[slot].clone()))
+        }
+
+        // Extras drawn by the initializer are only cut once they're all in,
+        // and children need a scored parent to come from
+        if state.parents.len() > self.lambda || !slots.scored.iter().any(|s| *s) {
+            return None
         }
 
-        let best_idx = (0..parents.len())
-                .max_by_key(|idx| FloatOrd(fitness[*idx]))
-                .expect("lambda needs to be greater than zero!");
+        // Each child gets its own stream, apart from the passes' own
+        let mut rng = state.seeds.spawn(u64::MAX).spawn(pass as u64).spawn(slots.bred).rng();
+        slots.bred += 1;
+        self.rank_parents(state);
+        let standing = if state.ranks.is_empty() { &state.fitness } else { &state.ranks };
+        let child = self.child(&state.parents, standing, &mut rng);
+        Some((Ticket { pass, slot: 0, kind: Kind::Child }, child))
+    }
 
-        (fitness[best_idx], parents.swap_remove(best_idx))
+    fn redraw(&self, state: &mut GaState<E, V>, ticket: Ticket) -> Option<E> {
+        match ticket.kind {
+            Kind::Member => Some(self.genome.new(&mut state.rng)),
+            _ => None
+        }
     }
 
+    fn accept(&self, state: &mut GaState<E, V>, ticket: Ticket, candidate: E, fitness: V, violation: V) -> bool {
+        // Results from before a restart only count against the budget
+        if ticket.pass != state.ledger.restarts() {
+            return false
+        }
+
+        match ticket.kind {
+            Kind::Member => {
+                let idx = ticket.slot;
+                let slots = state.steady.as_mut().expect("Steady-state run without its slots");
+                state.parents[idx] = candidate;
+                state.fitness[idx] = fitness;
+                state.estimates[idx] = Estimate::new(fitness);
+                state.violations[idx] = violation;
+                state.ranks.clear();
+                slots.scored[idx] = true;
+
+                // Once the whole starting population is in, only the fittest carry on
+                if state.parents.len() > self.lambda && slots.scored.iter().all(|s| *s) {
+                    let keep = fittest(&state.fitness, &state.violations, self.lambda);
+                    state.parents = init::select(&state.parents, &keep);
+                    state.fitness = init::select(&state.fitness, &keep);
+                    state.estimates = init::select(&state.estimates, &keep);
+                    state.violations = init::select(&state.violations, &keep);
+                    slots.scored.truncate(self.lambda);
+                }
+            },
+            Kind::Child => {
+                if let Some(idx) = self.victim(state) {
+                    let incumbent = (state.fitness[idx], state.violations[idx]);
+                    let generation = state.ledger.generation();
+                    if fitness.is_finite() &&
+                        !self.constraints.better(incumbent, (fitness, violation), generation, &mut state.rng) {
+                        state.parents[idx] = candidate;
+                        state.fitness[idx] = fitness;
+                        state.estimates[idx] = Estimate::new(fitness);
+                        state.violations[idx] = violation;
+                    }
+                }
+            },
+            Kind::Trial { .. } => unreachable!("GAs only hand out members and children")
+        }
+        self.track_best(state);
+
+        let slots = state.steady.as_mut().expect("Steady-state run without its slots");
+        if !slots.tally(state.parents.len()) {
+            return false
+        }
+        state.ranks.clear();
+        state.ledger.record(0);
+        let stats = self.stats(state);
+        state.ledger.check(&stats);
+        true
+    }
 }
 
 #[cfg(test)]
@@ -184,6 +920,7 @@ mod test_ga {
     use crate::ga::mutator::*;
     use crate::ga::genome::*;
     use crate::ga::crossover::*;
+    use crate::constraints::ConstrainedFitness;
 
     #[test]
     fn test_matyas() {
@@ -191,18 +928,166 @@ mod test_ga {
             100,
             true,
             1.,
-            Continuous { dims: 2, dist: Normal::new(0., 1f32).unwrap() },
-            ContinuousMutator(Normal::new(0., 1f32).unwrap()),           
+            Continuous::new(2, Normal::new(0., 1f32).unwrap()),
+            ContinuousMutator::new(Normal::new(0., 1f32).unwrap()),           
             Linear::new(CrossoverType::TwoPoint),
             Tournament(4));
 
         let fit_fn = MatyasEnv(-10., 10.);
-        let (fit, results) = opt.fit(&fit_fn, 10000, 2020, None, 
-                                     |_best_fit, _fns_remaining| {println!("best_fit: {}", _best_fit);});
-        assert!(fit.abs() < 1e-5);
-        assert!((results[0] - 10.).abs() < 1e-2);
-        assert!((results[1] + 10.).abs() < 1e-2);
+        let result = opt.fit(&fit_fn, 10000, 2020, None,
+                             |stats, _fns_remaining| {println!("best_fit: {}", stats.best);});
+        assert!(result.fitness.abs() < 1e-5);
+        assert!((result.candidate[0] - 10.).abs() < 1e-2);
+        assert!((result.candidate[1] + 10.).abs() < 1e-2);
+    }
+
+    #[test]
+    fn test_constrained() {
+        let mut opt: FastGA<_,_,_,_,Vec<f32>> = FastGA::new(
+            100,
+            true,
+            1.,
+            Continuous::new(2, Normal::new(0., 1f32).unwrap()),
+            ContinuousMutator::new(Normal::new(0., 0.1f32).unwrap()),
+            Linear::new(CrossoverType::TwoPoint),
+            Tournament(4));
+        opt.constraints = ConstraintHandling::StochasticRanking { pf: 0.45 };
+
+        for update in &[UpdateMode::Generational, UpdateMode::SteadyState] {
+            opt.update = *update;
+            let result = opt.fit_constrained(&LinearDiskEnv, 10000, 2020, None, |_stats, _fns_remaining| {});
+            assert_eq!(LinearDiskEnv.violation(&result.candidate), 0.);
+            assert!((result.fitness - 2f32.sqrt()).abs() < 1e-2, "{:?}: {}", update, result.fitness);
+        }
+    }
+
+    #[test]
+    fn test_execution_backends() {
+        let mut opt: FastGA<_,_,_,_,Vec<f32>> = FastGA::new(
+            50,
+            true,
+            1.,
+            Continuous::new(2, Normal::new(0., 1f32).unwrap()),
+            ContinuousMutator::new(Normal::new(0., 1f32).unwrap()),
+            Linear::new(CrossoverType::TwoPoint),
+            Tournament(4));
+
+        let fit_fn = MatyasEnv(-10., 10.);
+        let expected = opt.fit(&fit_fn, 3000, 2020, None, |_stats, _fns_remaining| {});
+        for execution in [Execution::threads(3), Execution::Sequential] {
+            opt.execution = execution;
+            let result = opt.fit(&fit_fn, 3000, 2020, None, |_stats, _fns_remaining| {});
+            assert_eq!(result.population, expected.population);
+            assert_eq!(result.candidate, expected.candidate);
+        }
     }
-    
 
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_resume() {
+        use crate::Control;
+        use crate::checkpoint::*;
+
+        let opt: FastGA<_,_,_,_,Vec<f32>> = FastGA::new(
+            50,
+            true,
+            1.,
+            Continuous::new(2, Normal::new(0., 1f32).unwrap()),
+            ContinuousMutator::new(Normal::new(0., 1f32).unwrap()),
+            Linear::new(CrossoverType::TwoPoint),
+            Tournament(4));
+
+        let fit_fn = MatyasEnv(-10., 10.);
+        let path = std::env::temp_dir().join("darkopt_test_resume_ga.ckpt");
+        let policy = CheckpointPolicy::new(&path, Period::Generations(7));
+        let expected = opt.fit(&fit_fn, 3000, 2020, None, |_stats, _fns_remaining| {});
+
+        opt.fit_checkpointed(&fit_fn, 3000, 2020, None, &policy, |stats, _| {
+            if stats.generation == 30 { Control::Stop } else { Control::Continue }
+        }).unwrap();
+        let result = opt.resume(&fit_fn, 3000, &policy, |_stats, _fns_remaining| {}).unwrap();
+        assert_eq!(result.population, expected.population);
+        assert_eq!(result.population_fitness, expected.population_fitness);
+        assert_eq!(result.candidate, expected.candidate);
+        assert_eq!(result.evaluations, expected.evaluations);
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_f64() {
+        let opt = FastGA::builder(
+            Continuous::new(2, Normal::new(3., 1f64).unwrap()),
+            ContinuousMutator::new(Normal::new(0., 1e-3f64).unwrap()),
+            Linear::new(CrossoverType::TwoPoint),
+            Tournament(4)).lambda(50).build().unwrap();
+
+        let result = opt.fit(&FineSphereEnv(3.000_000_123), 10000, 2020, None, |_stats, _fns_remaining| {});
+        assert!(result.fitness > -1e-10, "{}", result.fitness);
+    }
+
+    #[test]
+    fn test_noise() {
+        let builder = || FastGA::builder(
+            Continuous::new(2, Normal::new(0., 1f32).unwrap()),
+            ContinuousMutator::new(Normal::new(0., 0.3f32).unwrap()),
+            Linear::new(CrossoverType::TwoPoint),
+            Tournament(4)).lambda(50).execution(Execution::Sequential);
+        let env = MatyasEnv(-1., 1.);
+        let fit = |opt: &FastGA<_,_,_,_,Vec<f32>>| {
+            let noisy = NoisyEnv::new(MatyasEnv(-1., 1.), 1., 2020);
+            let result = opt.fit(&noisy, 6000, 2020, None, |_stats, _fns_remaining| {});
+            (result.fitness, env.score(&result.candidate))
+        };
+
+        // An elite carried on its luckiest draw is never displaced
+        let (reported, actual) = fit(&builder().build().unwrap());
+        assert!(reported - actual > 1.);
+        let (reported, actual) = fit(&builder().noise(Noise::default()).build().unwrap());
+        assert!((reported - actual).abs() < 1.);
+        assert!(actual > -0.5);
+
+        // The rescored elite comes out of the budget too
+        let noise = Noise { samples: 2, max_samples: 2, reevaluate: 1, ..Noise::default() };
+        let opt = builder().noise(noise).build().unwrap();
+        for budget in [1000, 1007, 1070] {
+            let result = opt.fit(&env, budget, 2020, None, |_stats, _fns_remaining| {});
+            assert!(result.evaluations <= budget, "{}: {}", budget, result.evaluations);
+        }
+    }
+
+    #[test]
+    fn test_steady_state() {
+        let builder = || FastGA::builder(
+            Continuous::new(2, Normal::new(0., 1f32).unwrap()),
+            ContinuousMutator::new(Normal::new(0., 1f32).unwrap()),
+            Linear::new(CrossoverType::TwoPoint),
+            Tournament(4)).lambda(50).update(UpdateMode::SteadyState);
+
+        for displacement in [Displacement::Worst, Displacement::Tournament(3)].iter() {
+            let opt: FastGA<_,_,_,_,Vec<f32>> = builder()
+                .displacement(*displacement)
+                .execution(Execution::Sequential)
+                .build().unwrap();
+            let fit = || opt.fit(&MatyasEnv(-10., 10.), 5000, 2020, None, |_stats, _fns_remaining| {});
+            let result = fit();
+            assert!(result.fitness.abs() < 1e-3, "{:?}: {}", displacement, result.fitness);
+            assert_eq!(result.evaluations, 5000);
+            assert_eq!(result.history.len(), 100);
+            assert_eq!(result.population.len(), 50);
+
+            // Elitism keeps the best found in the population.  Sequential
+            // runs finish in order, so they repeat exactly.
+            assert!(result.population_fitness.contains(&result.fitness));
+            assert_eq!(fit().population, result.population);
+
+            // Threads change the order evaluations finish in, but not the budget
+            let opt = FastGA { execution: Execution::threads(3), ..opt };
+            let result = opt.fit(&MatyasEnv(-10., 10.), 5000, 2020, None, |_stats, _fns_remaining| {});
+            assert_eq!(result.evaluations, 5000);
+            assert!(result.fitness.abs() < 1e-2, "{:?}: {}", displacement, result.fitness);
+        }
+
+        assert!(builder().noise(Noise::default()).build::<Vec<f32>, f32>().is_err());
+        assert!(builder().displacement(Displacement::Tournament(0)).build::<Vec<f32>, f32>().is_err());
+    }
 }
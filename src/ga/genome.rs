@@ -1,19 +1,49 @@
 extern crate rand;
 
 use std::fmt::Debug;
+use std::marker::PhantomData;
+use std::sync::Arc;
 use rand::prelude::*;
 use rand_distr::Distribution;
 
+#[cfg(feature = "serde")]
+use serde::{Deserialize,Serialize};
+
 use super::Genome;
+use crate::Real;
+use crate::config::{ConfigError,check};
+use crate::init::{self,Initializer};
+use crate::seed::Stream;
 
+/// Fixed length genomes of real genes, each drawn from `dist`.  Build it
+/// with `Continuous::new(dims, dist)`.
 #[derive(Debug,Clone)]
-pub struct Continuous<D> {
+#[cfg_attr(feature = "serde", derive(Serialize,Deserialize))]
+pub struct Continuous<D, T = f32> {
     pub dims: usize,
-    pub dist: D
+    pub dist: D,
+
+    #[cfg_attr(feature = "serde", serde(skip))]
+    initializer: Option<Arc<dyn Initializer<T>>>,
+
+    #[cfg_attr(feature = "serde", serde(skip))]
+    precision: PhantomData<T>
+}
+
+impl <D, T> Continuous<D, T> {
+    pub fn new(dims: usize, dist: D) -> Self {
+        Continuous { dims, dist, initializer: None, precision: PhantomData }
+    }
+
+    /// Draws starting populations from `initializer` rather than `dist`
+    pub fn initializer<I: Initializer<T> + 'static>(mut self, initializer: I) -> Self {
+        self.initializer = Some(Arc::new(initializer));
+        self
+    }
 }
 
-impl <D: Distribution<f32> + Send + Sync + Debug + Clone> Genome for Continuous<D> {
-    type Encoded = Vec<f32>;
+impl <D: Distribution<T> + Send + Sync + Debug + Clone, T: Real> Genome for Continuous<D, T> {
+    type Encoded = Vec<T>;
 
     fn size(&self, encode: &Self::Encoded) -> usize {
         encode.len()
@@ -22,4 +52,24 @@ impl <D: Distribution<f32> + Send + Sync + Debug + Clone> Genome for Continuous<
     fn new<R: Rng>(&self, rng: &mut R) -> Self::Encoded {
         (0..self.dims).map(|_| self.dist.sample(rng) ).collect()
     }
+
+    fn initialize(&self, n: usize, rng: &mut Stream) -> Option<Vec<Self::Encoded>> {
+        self.initializer.as_ref().map(|i| i.initialize(n, rng))
+    }
+
+    fn diversity(&self, population: &[Self::Encoded]) -> Option<f32> {
+        Some(crate::stats::diversity(population))
+    }
+
+    fn dims(&self) -> Option<usize> {
+        Some(self.dims)
+    }
+
+    fn validate(&self) -> Result<(), ConfigError> {
+        check(self.dims > 1, "dims", "crossover needs at least two genes")?;
+        match &self.initializer {
+            Some(initializer) => init::check_init(initializer.as_ref(), self.dims),
+            None => Ok(())
+        }
+    }
 }
@@ -8,13 +8,40 @@ pub mod fastga;
 use std::fmt::Debug;
 use rand::prelude::*;
 
+use crate::Real;
+use crate::config::ConfigError;
+use crate::seed::Stream;
+
 /// Defines genomes
 pub trait Genome: Send + Sync + Clone + Debug {
     type Encoded: Send + Sync;
 
     fn size(&self, encode: &Self::Encoded) -> usize;
 
+    #[allow(clippy::wrong_self_convention)]
     fn new<R: Rng>(&self, rng: &mut R) -> Self::Encoded;
+
+    /// Draws a whole starting population of at least `n`, if the genome has
+    /// an initializer.  Any beyond `n` are cut down once scored.  Defaults to
+    /// sampling each member with `new`.
+    fn initialize(&self, _n: usize, _rng: &mut Stream) -> Option<Vec<Self::Encoded>> {
+        None
+    }
+
+    /// Spread of a population, if the encoding has a notion of distance
+    fn diversity(&self, _population: &[Self::Encoded]) -> Option<f32> {
+        None
+    }
+
+    /// Number of genes, if every genome has the same number
+    fn dims(&self) -> Option<usize> {
+        None
+    }
+
+    /// Checks the genome's settings when an optimizer is built
+    fn validate(&self) -> Result<(), ConfigError> {
+        Ok(())
+    }
 }
 
 /// Defines a mutator which can pointwise mutate a genome
@@ -22,6 +49,16 @@ pub trait Mutator: Send + Sync + Clone + Debug {
     type Encoded: Send + Sync;
 
     fn mutate<R: Rng>(&self, idxs: &[usize], genome: &mut Self::Encoded, rng: &mut R);
+
+    /// Number of genes, if the mutator only fits genomes of one size
+    fn dims(&self) -> Option<usize> {
+        None
+    }
+
+    /// Checks the mutator's settings when an optimizer is built
+    fn validate(&self) -> Result<(), ConfigError> {
+        Ok(())
+    }
 }
 
 /// Defines a crossover method between a set of selected parents
@@ -33,13 +70,23 @@ pub trait Crossover: Send + Sync + Clone + Debug {
 
     /// Creates a new offspring given a set of parents
     fn cross<R: Rng>(&self, parents: &[&Self::Encoded], rng: &mut R) -> Self::Encoded;
+
+    /// Checks the crossover's settings when an optimizer is built
+    fn validate(&self) -> Result<(), ConfigError> {
+        Ok(())
+    }
 }
 
 /// Selection for parents
 pub trait Selector: Send + Sync + Clone + Debug {
 
     /// Selects a parent from the given set of indices
-    fn choose<R: Rng>(&self, fitnesses: &[f32], rng: &mut R) -> usize;
+    fn choose<V: Real, R: Rng>(&self, fitnesses: &[V], rng: &mut R) -> usize;
+
+    /// Checks the selector's settings when an optimizer is built
+    fn validate(&self) -> Result<(), ConfigError> {
+        Ok(())
+    }
 }
 
 
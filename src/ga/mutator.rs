@@ -1,15 +1,35 @@
 use std::fmt::Debug;
+use std::marker::PhantomData;
 
 use rand::prelude::*;
 use rand_distr::Distribution;
 
+#[cfg(feature = "serde")]
+use serde::{Deserialize,Serialize};
+
 use super::Mutator;
+use crate::Real;
+use crate::bounds::Bounds;
+use crate::config::ConfigError;
 
+/// Adds a draw from the distribution to each mutated gene.  Build it with
+/// `ContinuousMutator::new(dist)`.
 #[derive(Debug,Clone)]
-pub struct ContinuousMutator<D>(pub D);
+#[cfg_attr(feature = "serde", derive(Serialize,Deserialize), serde(transparent))]
+pub struct ContinuousMutator<D, T = f32>(
+    pub D,
+    #[cfg_attr(feature = "serde", serde(skip))]
+    PhantomData<T>
+);
+
+impl <D, T> ContinuousMutator<D, T> {
+    pub fn new(dist: D) -> Self {
+        ContinuousMutator(dist, PhantomData)
+    }
+}
 
-impl <D: Distribution<f32> + Send + Sync + Clone + std::fmt::Debug> Mutator for ContinuousMutator<D> {
-    type Encoded = Vec<f32>;
+impl <D: Distribution<T> + Send + Sync + Clone + Debug, T: Real> Mutator for ContinuousMutator<D, T> {
+    type Encoded = Vec<T>;
 
     fn mutate<R: Rng>(&self, idxs: &[usize], genome: &mut Self::Encoded, rng: &mut R) {
         for idx in idxs {
@@ -18,3 +38,29 @@ impl <D: Distribution<f32> + Send + Sync + Clone + std::fmt::Debug> Mutator for
     }
 
 }
+
+/// Repairs any genes the wrapped mutator pushes outside the bounds
+#[derive(Debug,Clone)]
+#[cfg_attr(feature = "serde", derive(Serialize,Deserialize),
+           serde(bound(deserialize = "M: Deserialize<'de>, T: Real")))]
+pub struct Bounded<M, T = f32>(pub M, pub Bounds<T>);
+
+impl <M: Mutator<Encoded=Vec<T>>, T: Real> Mutator for Bounded<M, T> {
+    type Encoded = Vec<T>;
+
+    fn mutate<R: Rng>(&self, idxs: &[usize], genome: &mut Self::Encoded, rng: &mut R) {
+        let parent = genome.clone();
+        self.0.mutate(idxs, genome, rng);
+        self.1.repair(genome, Some(&parent), rng);
+    }
+
+    fn dims(&self) -> Option<usize> {
+        Some(self.1.dims())
+    }
+
+    fn validate(&self) -> Result<(), ConfigError> {
+        self.0.validate()?;
+        self.1.validate()
+    }
+
+}
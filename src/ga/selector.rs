@@ -2,19 +2,28 @@ use std::fmt::Debug;
 
 use rand::prelude::*;
 use rand_distr::{Distribution,Uniform};
-use float_ord::FloatOrd;
+
+#[cfg(feature = "serde")]
+use serde::{Deserialize,Serialize};
 
 use super::Selector;
+use crate::Real;
+use crate::config::{ConfigError,check};
 
 #[derive(Clone,Debug)]
+#[cfg_attr(feature = "serde", derive(Serialize,Deserialize))]
 pub struct Tournament(pub usize);
 
 impl Selector for Tournament {
-    fn choose<R: Rng>(&self, fitnesses: &[f32], rng: &mut R) -> usize {
+    fn choose<V: Real, R: Rng>(&self, fitnesses: &[V], rng: &mut R) -> usize {
         Uniform::new(0, fitnesses.len())
             .sample_iter(rng)
             .take(self.0)
-            .max_by_key(|idx| FloatOrd(fitnesses[*idx]))
+            .max_by(|a, b| fitnesses[*a].cmp_float(&fitnesses[*b]))
             .expect("K == 0 for tournament selection!")
     }
+
+    fn validate(&self) -> Result<(), ConfigError> {
+        check(self.0 > 0, "tournament", "needs at least one entrant")
+    }
 }
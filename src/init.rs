@@ -0,0 +1,413 @@
+use std::fmt::Debug;
+
+use rand::prelude::*;
+
+use crate::Real;
+use crate::bounds::Bounds;
+use crate::config::{ConfigError,check};
+use crate::seed::{SeedSequence,Stream};
+
+/// Spawn index of a pass's initialisation stream, clear of the indices
+/// optimizers use for generations and individuals
+const INIT_KEY: u64 = u64::MAX;
+
+/// Draws an optimizer's starting population.  DE, PSO and continuous GA
+/// genomes all take one, and draw from the same stream for a given seed, so
+/// a shared initializer starts them all from the same population.
+pub trait Initializer<T = f32>: Send + Sync + Debug {
+
+    /// Dimensions of the candidates drawn
+    fn dims(&self) -> usize;
+
+    /// Draws a starting population of `n` candidates.  Any drawn beyond `n`
+    /// are scored along with the rest, and only the fittest `n` are kept.
+    fn initialize(&self, n: usize, rng: &mut Stream) -> Vec<Vec<T>>;
+
+    /// Checks the initializer's settings when an optimizer is built
+    fn validate(&self) -> Result<(), ConfigError> {
+        Ok(())
+    }
+}
+
+/// Stream the starting population of the pass spawned from `pass_seeds` is
+/// drawn from
+pub(crate) fn stream(pass_seeds: &SeedSequence) -> Stream {
+    pass_seeds.spawn(INIT_KEY).rng()
+}
+
+/// Checks an initializer fits an optimizer's search space
+pub(crate) fn check_init<T: Real>(init: &dyn Initializer<T>, dims: usize) -> Result<(), ConfigError> {
+    check(init.dims() == dims, "initializer", "needs to draw candidates with a value per dimension")?;
+    init.validate()
+}
+
+/// Picks out the members at `idxs`
+pub(crate) fn select<X: Clone>(xs: &[X], idxs: &[usize]) -> Vec<X> {
+    idxs.iter().map(|i| xs[*i].clone()).collect()
+}
+
+/// Checks the region is finite, so points can be spread over it
+fn check_region<T: Real>(bounds: &Bounds<T>) -> Result<(), ConfigError> {
+    check(bounds.lower.iter().chain(bounds.upper.iter()).all(|x| x.is_finite()),
+          "initializer", "needs finite bounds to sample within")
+}
+
+/// Maps points in the unit cube onto the bounds
+fn scale<T: Real>(bounds: &Bounds<T>, unit: Vec<Vec<f64>>) -> Vec<Vec<T>> {
+    unit.into_iter().map(|u| {
+        u.iter().zip(bounds.lower.iter().zip(bounds.upper.iter()))
+            .map(|(ui, (l, h))| {
+                let (l, h) = (l.to_f64(), h.to_f64());
+                T::from_f64(l + (h - l) * ui).max(T::from_f64(l)).min(T::from_f64(h))
+            })
+            .collect()
+    }).collect()
+}
+
+/// Uniform within the bounds.  Their repair is ignored.
+#[derive(Clone,Debug)]
+pub struct UniformInit<T = f32>(pub Bounds<T>);
+
+impl <T: Real> Initializer<T> for UniformInit<T> {
+    fn dims(&self) -> usize {
+        self.0.dims()
+    }
+
+    fn initialize(&self, n: usize, rng: &mut Stream) -> Vec<Vec<T>> {
+        let unit = (0..n).map(|_| (0..self.dims()).map(|_| rng.gen()).collect()).collect();
+        scale(&self.0, unit)
+    }
+
+    fn validate(&self) -> Result<(), ConfigError> {
+        check_region(&self.0)
+    }
+}
+
+/// Latin hypercube within the bounds: every dimension is cut into `n` equal
+/// strata, each holding exactly one candidate
+#[derive(Clone,Debug)]
+pub struct LatinHypercube<T = f32>(pub Bounds<T>);
+
+impl <T: Real> Initializer<T> for LatinHypercube<T> {
+    fn dims(&self) -> usize {
+        self.0.dims()
+    }
+
+    fn initialize(&self, n: usize, rng: &mut Stream) -> Vec<Vec<T>> {
+        let mut unit = vec![vec![0.; self.dims()]; n];
+        for d in 0..self.dims() {
+            let mut strata: Vec<_> = (0..n).collect();
+            strata.shuffle(rng);
+            unit.iter_mut().zip(strata).for_each(|(u, s)| {
+                u[d] = (s as f64 + rng.gen::<f64>()) / n as f64;
+            });
+        }
+        scale(&self.0, unit)
+    }
+
+    fn validate(&self) -> Result<(), ConfigError> {
+        check_region(&self.0)
+    }
+}
+
+/// Degree, coefficients and initial direction numbers of the primitive
+/// polynomials behind each Sobol dimension after the first, from Joe and Kuo
+const SOBOL: [(usize, u32, &[u32]); 20] = [
+    (1, 0, &[1]),
+    (2, 1, &[1, 3]),
+    (3, 1, &[1, 3, 1]),
+    (3, 2, &[1, 1, 1]),
+    (4, 1, &[1, 1, 3, 3]),
+    (4, 4, &[1, 3, 5, 13]),
+    (5, 2, &[1, 1, 5, 5, 17]),
+    (5, 4, &[1, 1, 5, 5, 5]),
+    (5, 7, &[1, 1, 7, 11, 19]),
+    (5, 11, &[1, 1, 5, 1, 1]),
+    (5, 13, &[1, 1, 1, 3, 11]),
+    (5, 14, &[1, 3, 5, 5, 31]),
+    (6, 1, &[1, 3, 3, 9, 7, 49]),
+    (6, 13, &[1, 1, 1, 15, 21, 21]),
+    (6, 16, &[1, 3, 1, 13, 27, 49]),
+    (6, 19, &[1, 1, 1, 15, 7, 5]),
+    (6, 22, &[1, 3, 1, 15, 13, 25]),
+    (6, 25, &[1, 1, 5, 5, 19, 61]),
+    (7, 1, &[1, 3, 7, 11, 23, 15, 103]),
+    (7, 4, &[1, 3, 7, 13, 13, 15, 69])
+];
+
+/// Most dimensions `Sobol` supports
+pub const SOBOL_DIMS: usize = SOBOL.len() + 1;
+
+/// Direction numbers for a Sobol dimension, scaled to 32 bits
+fn directions(dim: usize) -> [u32; 32] {
+    let mut v = [0u32; 32];
+    if dim == 0 {
+        v.iter_mut().enumerate().for_each(|(k, vk)| *vk = 1 << (31 - k));
+        return v
+    }
+
+    let (s, a, m) = SOBOL[dim - 1];
+    for k in 0..32 {
+        v[k] = if k < s {
+            m[k] << (31 - k)
+        } else {
+            let mut x = v[k - s] ^ (v[k - s] >> s);
+            for i in 1..s {
+                if (a >> (s - 1 - i)) & 1 == 1 {
+                    x ^= v[k - i];
+                }
+            }
+            x
+        };
+    }
+    v
+}
+
+/// First `n` points of the unscrambled Sobol sequence, as 32 bit fractions
+fn sobol_points(n: usize, dims: usize) -> Vec<Vec<u32>> {
+    let dirs: Vec<_> = (0..dims).map(directions).collect();
+    let mut x = vec![0u32; dims];
+    (0..n).map(|i| {
+        if i > 0 {
+            let c = (!(i - 1)).trailing_zeros() as usize;
+            x.iter_mut().zip(dirs.iter()).for_each(|(xi, v)| *xi ^= v[c]);
+        }
+        x.clone()
+    }).collect()
+}
+
+/// Sobol low discrepancy sequence within the bounds, up to `SOBOL_DIMS`
+/// dimensions.  Each draw applies a fresh random digital shift, which keeps
+/// the sequence's balance while giving every pass different points.
+#[derive(Clone,Debug)]
+pub struct Sobol<T = f32>(pub Bounds<T>);
+
+impl <T: Real> Initializer<T> for Sobol<T> {
+    fn dims(&self) -> usize {
+        self.0.dims()
+    }
+
+    fn initialize(&self, n: usize, rng: &mut Stream) -> Vec<Vec<T>> {
+        let shift: Vec<u32> = (0..self.dims()).map(|_| rng.gen()).collect();
+        let unit = sobol_points(n, self.dims()).into_iter().map(|x| {
+            x.iter().zip(shift.iter())
+                .map(|(xi, si)| (xi ^ si) as f64 / 2f64.powi(32))
+                .collect()
+        }).collect();
+        scale(&self.0, unit)
+    }
+
+    fn validate(&self) -> Result<(), ConfigError> {
+        check(self.dims() <= SOBOL_DIMS, "initializer", "Sobol supports at most 21 dimensions")?;
+        check_region(&self.0)
+    }
+}
+
+/// Van der Corput radical inverse of `i` in `base`
+fn radical_inverse(mut i: u64, base: u64) -> f64 {
+    let (mut f, mut r) = (1., 0.);
+    while i > 0 {
+        f /= base as f64;
+        r += f * (i % base) as f64;
+        i /= base;
+    }
+    r
+}
+
+/// First `n` primes
+fn primes(n: usize) -> Vec<u64> {
+    let mut primes = Vec::with_capacity(n);
+    let mut c = 2;
+    while primes.len() < n {
+        if primes.iter().take_while(|p| *p * *p <= c).all(|p| c % p != 0) {
+            primes.push(c);
+        }
+        c += 1;
+    }
+    primes
+}
+
+/// Halton low discrepancy sequence within the bounds, one prime base per
+/// dimension.  Each draw applies a fresh random rotation.  Best kept to a
+/// few dozen dimensions, beyond which the bases correlate.
+#[derive(Clone,Debug)]
+pub struct Halton<T = f32>(pub Bounds<T>);
+
+impl <T: Real> Initializer<T> for Halton<T> {
+    fn dims(&self) -> usize {
+        self.0.dims()
+    }
+
+    fn initialize(&self, n: usize, rng: &mut Stream) -> Vec<Vec<T>> {
+        let bases = primes(self.dims());
+        let shift: Vec<f64> = (0..self.dims()).map(|_| rng.gen()).collect();
+        let unit = (0..n).map(|i| {
+            bases.iter().zip(shift.iter())
+                .map(|(b, s)| (radical_inverse(i as u64 + 1, *b) + s) % 1.)
+                .collect()
+        }).collect();
+        scale(&self.0, unit)
+    }
+
+    fn validate(&self) -> Result<(), ConfigError> {
+        check_region(&self.0)
+    }
+}
+
+/// Opposition based initialisation: draws `n` candidates from the wrapped
+/// initializer along with their opposites, mirrored across the centre of the
+/// box the draws span.  All 2n are scored and the fittest `n` kept.
+#[derive(Clone,Debug)]
+pub struct Opposition<I>(pub I);
+
+impl <T: Real, I: Initializer<T>> Initializer<T> for Opposition<I> {
+    fn dims(&self) -> usize {
+        self.0.dims()
+    }
+
+    fn initialize(&self, n: usize, rng: &mut Stream) -> Vec<Vec<T>> {
+        let mut pop = self.0.initialize(n, rng);
+        let lower: Vec<_> = (0..self.dims())
+            .map(|d| pop.iter().map(|x| x[d]).fold(T::INFINITY, T::min))
+            .collect();
+        let upper: Vec<_> = (0..self.dims())
+            .map(|d| pop.iter().map(|x| x[d]).fold(T::NEG_INFINITY, T::max))
+            .collect();
+        let opposites: Vec<Vec<T>> = pop.iter().map(|x| {
+            x.iter().enumerate().map(|(d, xi)| lower[d] + upper[d] - *xi).collect()
+        }).collect();
+        pop.extend(opposites);
+        pop
+    }
+
+    fn validate(&self) -> Result<(), ConfigError> {
+        self.0.validate()
+    }
+}
+
+/// Starts from user supplied candidates, filling out the rest of the
+/// population from another initializer.  Seeds beyond the population size
+/// are scored too, and only the fittest kept.
+#[derive(Clone,Debug)]
+pub struct Seeded<T, I> {
+    pub seeds: Vec<Vec<T>>,
+    pub rest: I
+}
+
+impl <T, I> Seeded<T, I> {
+    pub fn new(seeds: Vec<Vec<T>>, rest: I) -> Self {
+        Seeded { seeds, rest }
+    }
+}
+
+impl <T: Real, I: Initializer<T>> Initializer<T> for Seeded<T, I> {
+    fn dims(&self) -> usize {
+        self.rest.dims()
+    }
+
+    fn initialize(&self, n: usize, rng: &mut Stream) -> Vec<Vec<T>> {
+        let mut pop = self.seeds.clone();
+        if pop.len() < n {
+            pop.extend(self.rest.initialize(n - pop.len(), rng));
+        }
+        pop
+    }
+
+    fn validate(&self) -> Result<(), ConfigError> {
+        check(self.seeds.iter().all(|s| s.len() == self.dims()), "initializer",
+              "needs every seed to have a value per dimension")?;
+        check(self.seeds.iter().flatten().all(|x| x.is_finite()), "initializer",
+              "needs finite seeds")?;
+        self.rest.validate()
+    }
+}
+
+#[cfg(test)]
+mod test_init {
+    use rand_distr::{Normal,StandardNormal};
+
+    use super::*;
+    use crate::{AskTell,Optimizer};
+    use crate::bounds::Repair;
+    use crate::de::DePlus;
+    use crate::pso::PSO;
+    use crate::exp::*;
+    use crate::ga::fastga::FastGA;
+    use crate::ga::genome::Continuous;
+    use crate::ga::mutator::ContinuousMutator;
+    use crate::ga::crossover::{CrossoverType,Linear};
+    use crate::ga::selector::Tournament;
+
+    fn region() -> Bounds<f32> {
+        Bounds::uniform(2, -10., 10., Repair::Clip)
+    }
+
+    #[test]
+    fn test_sequences() {
+        let points: Vec<Vec<f64>> = sobol_points(4, 3).into_iter()
+            .map(|x| x.iter().map(|xi| *xi as f64 / 2f64.powi(32)).collect())
+            .collect();
+        assert_eq!(points, vec![
+            vec![0., 0., 0.],
+            vec![0.5, 0.5, 0.5],
+            vec![0.75, 0.25, 0.25],
+            vec![0.25, 0.75, 0.75]]);
+        assert_eq!(primes(5), vec![2, 3, 5, 7, 11]);
+        assert_eq!(radical_inverse(3, 2), 0.75);
+        assert!((radical_inverse(5, 3) - 7. / 9.).abs() < 1e-12);
+
+        // Every stratum of every dimension is hit exactly once
+        let mut rng = SeedSequence::new(2020).rng();
+        let pop = LatinHypercube(region()).initialize(20, &mut rng);
+        for d in 0..2 {
+            let mut strata: Vec<_> = pop.iter().map(|x| (x[d] + 10.) as usize).collect();
+            strata.sort_unstable();
+            assert_eq!(strata, (0..20).collect::<Vec<_>>());
+        }
+
+        let pop = Opposition(Sobol(region())).initialize(8, &mut rng);
+        assert_eq!(pop.len(), 16);
+        assert!(pop.iter().all(|x| region().contains(x)));
+
+        let seeded = Seeded::new(vec![vec![1., 2.]], Halton(region()));
+        let pop = seeded.initialize(5, &mut rng);
+        assert_eq!(pop.len(), 5);
+        assert_eq!(pop[0], vec![1., 2.]);
+        assert!(Seeded::new(vec![vec![1.]], Halton(region())).validate().is_err());
+        assert!(Sobol(Bounds::uniform(22, -1f32, 1., Repair::Clip)).validate().is_err());
+    }
+
+    #[test]
+    fn test_shared_start() {
+        let init = LatinHypercube(region());
+        let de = DePlus::builder(2, StandardNormal).lambda(20).initializer(init.clone()).build().unwrap();
+        let pso = PSO::builder(2).swarm_size(20).initializer(init.clone()).build().unwrap();
+        let ga = FastGA::builder(
+            Continuous::new(2, Normal::new(0., 1f32).unwrap()).initializer(init),
+            ContinuousMutator::new(Normal::new(0., 1f32).unwrap()),
+            Linear::new(CrossoverType::TwoPoint),
+            Tournament(4)).lambda(20).build().unwrap();
+
+        let expected = de.ask(&mut de.init(1000, 2020, None));
+        assert_eq!(pso.ask(&mut pso.init(1000, 2020, None)), expected);
+        assert_eq!(ga.ask(&mut ga.init(1000, 2020, None)), expected);
+
+        let result = ga.fit(&MatyasEnv(-1., 1.), 3000, 2020, None, |_stats, _fns_remaining| {});
+        assert!(result.fitness > -1e-3);
+    }
+
+    #[test]
+    fn test_opposition() {
+        let init = Opposition(UniformInit(region()));
+        let de = DePlus::builder(2, StandardNormal).lambda(20).initializer(init).build().unwrap();
+        let mut evaluations = Vec::new();
+        let result = de.fit(&MatyasEnv(-1., 1.), 3000, 2020, None, |stats, _fns_remaining| {
+            evaluations.push(stats.evaluations);
+        });
+
+        // Both halves are scored, then only the fittest carry on
+        assert_eq!(evaluations[0], 40);
+        assert_eq!(result.population.len(), 20);
+        assert!(result.fitness > -1e-3);
+    }
+}
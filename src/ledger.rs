@@ -0,0 +1,233 @@
+use std::time::{Duration,Instant};
+
+#[cfg(feature = "serde")]
+use serde::{Deserialize,Serialize};
+
+use crate::{GenerationStats,Real,Termination,TerminationReason};
+use crate::result::{Pass,RestartCause};
+
+/// Run bookkeeping shared by every optimizer's ask/tell state: how much of the
+/// budget has been spent, how far along the run is and whether it has stopped.
+///
+/// Restarts share the one budget: each pass gets what the ones before it left,
+/// and keeps its own tally of what it spent and the best it found.
+///
+/// Checkpoints keep the elapsed time but not the termination criteria, which
+/// are supplied again on resume.
+#[derive(Debug)]
+#[cfg_attr(feature = "serde", derive(Serialize,Deserialize), serde(bound(
+    serialize = "V: Serialize", deserialize = "V: Deserialize<'de>")))]
+pub struct Ledger<D, V = f32> {
+    evaluations: usize,
+    batches: usize,
+    restarts: usize,
+    failures: usize,
+
+    /// Every pass so far, the current one last
+    passes: Vec<Pass<V>>,
+
+    /// Evaluations the next batch gets for free
+    waived: usize,
+    free: usize,
+    #[cfg_attr(feature = "serde", serde(with = "elapsed"))]
+    started: Instant,
+    #[cfg_attr(feature = "serde", serde(skip, default = "unset"))]
+    termination: Box<dyn Termination<D, V>>,
+    stopped: Option<TerminationReason>
+}
+
+impl <D, V: Real> Ledger<D, V> {
+
+    pub(crate) fn new<T: Termination<D, V> + 'static>(termination: T) -> Self {
+        Ledger {
+            evaluations: 0,
+            batches: 0,
+            restarts: 0,
+            failures: 0,
+            passes: vec![Pass::new(None)],
+            waived: 0,
+            free: 0,
+            started: Instant::now(),
+            termination: Box::new(termination),
+            stopped: None
+        }
+    }
+
+    /// Function evaluations spent so far
+    pub fn evaluations(&self) -> usize {
+        self.evaluations
+    }
+
+    /// Generations completed after the initial population
+    pub fn generation(&self) -> usize {
+        self.batches.saturating_sub(1)
+    }
+
+    /// Times the population has been restarted
+    pub fn restarts(&self) -> usize {
+        self.restarts
+    }
+
+    /// Evaluations which failed to produce a usable fitness
+    pub fn failures(&self) -> usize {
+        self.failures
+    }
+
+    /// Evaluations which weren't charged to the budget, such as cache hits
+    pub fn free(&self) -> usize {
+        self.free
+    }
+
+    /// Every pass so far, the current one last
+    pub fn passes(&self) -> &[Pass<V>] {
+        &self.passes
+    }
+
+    fn pass(&mut self) -> &mut Pass<V> {
+        self.passes.last_mut().expect("A run always has a pass")
+    }
+
+    /// Wall clock time since the run started
+    pub fn elapsed(&self) -> Duration {
+        self.started.elapsed()
+    }
+
+    /// Why the run stopped, if it has
+    pub fn stopped(&self) -> Option<TerminationReason> {
+        self.stopped
+    }
+
+    /// Function evaluations left, if the termination criteria are budget based
+    pub fn remaining(&self) -> Option<usize> {
+        self.termination.remaining(self.evaluations)
+    }
+
+    /// Records a scored batch.  Every batch after the first is a new generation.
+    pub(crate) fn record(&mut self, evaluations: usize) {
+        let waived = self.waived.min(evaluations);
+        self.waived = 0;
+        self.free += waived;
+        self.batches += 1;
+        self.evaluations += evaluations - waived;
+
+        let pass = self.pass();
+        pass.batches += 1;
+        pass.evaluations += evaluations - waived;
+    }
+
+    /// Notes the current pass's best feasible fitness after a batch.  Noisy
+    /// runs pass `current` to take the latest estimate as it stands.
+    pub(crate) fn observe(&mut self, best: V, current: bool) {
+        let pass = self.pass();
+        if current || best > pass.best {
+            pass.best = best;
+        }
+    }
+
+    /// Records a single evaluation scored on its own, as steady-state runs
+    /// do.  A generation is recorded, with no evaluations of its own, once a
+    /// population's worth are in.
+    pub(crate) fn record_single(&mut self, free: bool) {
+        if free {
+            self.free += 1;
+        } else {
+            self.charge(1);
+        }
+    }
+
+    /// Lets the next recorded batch off `evaluations` of its cost
+    pub(crate) fn waive(&mut self, evaluations: usize) {
+        self.waived += evaluations;
+    }
+
+    /// Charges evaluations made outside of a batch, such as retries
+    pub(crate) fn charge(&mut self, evaluations: usize) {
+        self.evaluations += evaluations;
+        self.pass().evaluations += evaluations;
+    }
+
+    pub(crate) fn fail(&mut self, failures: usize) {
+        self.failures += failures;
+    }
+
+    /// Starts a new pass, which gets whatever budget is left
+    pub(crate) fn restart(&mut self, cause: RestartCause) {
+        self.restarts += 1;
+        self.passes.push(Pass::new(Some(cause)));
+        self.termination.reset();
+    }
+
+    /// Swaps in new termination criteria, such as when resuming a run
+    #[cfg(feature = "serde")]
+    pub(crate) fn set_termination<T: Termination<D, V> + 'static>(&mut self, termination: T) {
+        self.termination = Box::new(termination);
+    }
+
+    pub(crate) fn stop(&mut self, reason: TerminationReason) {
+        if self.stopped.is_none() {
+            self.stopped = Some(reason);
+        }
+    }
+
+    /// Checks the termination criteria against the latest stats
+    pub(crate) fn check(&mut self, stats: &GenerationStats<D, V>) {
+        if self.stopped.is_none() {
+            self.stopped = self.termination.check(stats);
+        }
+    }
+
+    /// Summarizes a population with the ledger's counters
+    pub(crate) fn stats(
+        &self,
+        fitnesses: &[V],
+        diversity: Option<f32>,
+        best: (V, D)
+    ) -> GenerationStats<D, V> {
+        let mut stats = GenerationStats::new(
+            self.generation(), self.evaluations, fitnesses, diversity, best, self.elapsed());
+        stats.failures = self.failures;
+        stats.restarts = self.restarts;
+
+        // The first batch of a new pass reports why it was restarted
+        let pass = &self.passes[self.passes.len() - 1];
+        if pass.batches == 1 {
+            stats.restarted = pass.cause;
+        }
+        stats
+    }
+}
+
+/// Stand in for criteria which have yet to be supplied.  Never stops a run.
+#[cfg(feature = "serde")]
+#[derive(Debug)]
+struct Unset;
+
+#[cfg(feature = "serde")]
+impl <D, V> Termination<D, V> for Unset {
+    fn check(&mut self, _stats: &GenerationStats<D, V>) -> Option<TerminationReason> {
+        None
+    }
+}
+
+#[cfg(feature = "serde")]
+fn unset<D, V>() -> Box<dyn Termination<D, V>> {
+    Box::new(Unset)
+}
+
+/// Stores the start of a run as the time elapsed since
+#[cfg(feature = "serde")]
+mod elapsed {
+    use std::time::{Duration,Instant};
+
+    use serde::{Deserialize,Deserializer,Serialize,Serializer};
+
+    pub fn serialize<S: Serializer>(started: &Instant, serializer: S) -> Result<S::Ok, S::Error> {
+        started.elapsed().serialize(serializer)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Instant, D::Error> {
+        let elapsed = Duration::deserialize(deserializer)?;
+        let now = Instant::now();
+        Ok(now.checked_sub(elapsed).unwrap_or(now))
+    }
+}
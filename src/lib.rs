@@ -3,30 +3,414 @@ pub mod pso;
 pub mod exp;
 
 pub mod ga;
+pub mod stats;
+pub mod result;
+pub mod termination;
+pub mod objective;
+pub mod bounds;
+pub mod constraints;
+pub mod ledger;
+pub mod fallible;
+pub mod execution;
+pub mod factory;
+pub mod seed;
+pub mod config;
+pub mod cache;
+pub mod noise;
+pub mod real;
+pub mod init;
+pub mod polish;
+pub mod steady;
+#[cfg(feature = "serde")]
+pub mod checkpoint;
+
+pub use stats::GenerationStats;
+pub use result::{OptimizationResult,Pass,RestartCause,TerminationReason};
+pub use termination::Termination;
+pub use objective::{Direction,Minimize};
+pub use bounds::{Bounds,Repair};
+pub use constraints::{ConstrainedAskTell,ConstrainedFitness,ConstraintHandling};
+pub use ledger::Ledger;
+pub use fallible::{FailurePolicy,FallibleFitness,Isolated,Panic};
+pub use execution::Execution;
+pub use factory::{Evaluator,FitnessFactory,PerWorker};
+pub use seed::{SeedSequence,Stream};
+pub use config::{ConfigError,Sampler};
+pub use cache::{Bitwise,CacheStats,Cached,Exact,Keying,Quantized};
+pub use noise::{Estimate,Noise,Replacement};
+pub use real::Real;
+pub use polish::{LocalMethod,Polisher};
+pub use steady::{Displacement,UpdateMode};
+pub use init::{Halton,Initializer,LatinHypercube,Opposition,Seeded,Sobol,UniformInit};
+#[cfg(feature = "serde")]
+pub use checkpoint::{Checkpoint,CheckpointPolicy,Checkpointed,Period};
+
+use objective::Oriented;
 
 pub trait Fitness: Send + Sync {
-    type Data;
-    fn score(&self, candidate: &Self::Data) -> f32;
+    type Data: Send + Sync;
+
+    /// Precision of the scores, usually `f32`
+    type Value: Real;
+
+    fn score(&self, candidate: &Self::Data) -> Self::Value;
+
+    /// Scores a whole batch of candidates at once, returning a fitness per
+    /// candidate in the same order.  Optimizers send each generation through
+    /// here, so objectives which are cheaper to evaluate as a matrix should
+    /// override it.  Defaults to scoring each candidate in parallel, or in
+    /// turn when the run is sequential.
+    fn score_batch(&self, candidates: &[Self::Data]) -> Vec<Self::Value> {
+        execution::map_batch(candidates, |c| self.score(c))
+    }
+
+    /// Scores a candidate, also saying whether the score came free, such as
+    /// from a cache, and shouldn't count against the budget.  Defaults to
+    /// charging every score.
+    fn score_metered(&self, candidate: &Self::Data) -> (Self::Value, bool) {
+        (self.score(candidate), false)
+    }
+
+    /// Batch form of `score_metered`
+    fn score_batch_metered(&self, candidates: &[Self::Data]) -> Vec<(Self::Value, bool)> {
+        self.score_batch(candidates).into_iter()
+            .map(|s| (s, false))
+            .collect()
+    }
+
+    /// Direction of the user's objective.  Scores are always maximized; this
+    /// only changes how fitness values are reported back.
+    fn direction(&self) -> Direction {
+        Direction::Maximize
+    }
+}
+
+/// What the fit callback wants to happen after a generation.  Callbacks
+/// returning `()` always continue.
+#[derive(Clone,Copy,Debug,PartialEq,Eq)]
+pub enum Control {
+    /// Keep going
+    Continue,
+
+    /// Stop the run and return the best found so far
+    Stop,
+
+    /// Start over from a fresh population, keeping the best found so far
+    Restart
+}
+
+impl From<()> for Control {
+    fn from(_: ()) -> Self {
+        Control::Continue
+    }
 }
 
 pub trait Optimizer: Clone + std::fmt::Debug + Send + Sync {
     type Stats;
     type Data: Clone + Send + Sync;
 
-    fn fit<F: Fitness<Data=Self::Data>, FN: FnMut(Self::Stats, usize) -> ()>(
+    /// Precision of the fitness values it works with
+    type Value: Real;
+
+    /// Runs until the termination criteria are met.  A bare `usize` is an
+    /// evaluation budget.  The callback receives the stats for each generation
+    /// along with the evaluations left, if the criteria are budget based.
+    fn fit<F, T, FN, R>(
+        &self,
+        fit_fn: &F,
+        termination: T,
+        seed: u64,
+        x_in: Option<&Self::Data>,
+        callback: FN
+    ) -> OptimizationResult<Self::Data, (), Self::Value>
+    where
+        F: Fitness<Data=Self::Data, Value=Self::Value>,
+        T: Termination<Self::Data, Self::Value> + 'static,
+        FN: FnMut(Self::Stats, Option<usize>) -> R,
+        R: Into<Control>;
+}
+
+/// Stepping interface for optimizers.  `ask` hands out a batch of candidates,
+/// which can be scored anywhere, and `tell` feeds their fitnesses back in the
+/// same order.  All population, RNG and budget bookkeeping lives in the state.
+/// Fitnesses are always maximized; use `Direction::orient_stats` to report
+/// stats for a minimized objective.
+pub trait AskTell: Optimizer<Stats=GenerationStats<<Self as Optimizer>::Data, <Self as Optimizer>::Value>> {
+    type State: Send;
+
+    /// Creates a fresh state for a run with the given termination criteria
+    fn init<T: Termination<Self::Data, Self::Value> + 'static>(
+        &self,
+        termination: T,
+        seed: u64,
+        x_in: Option<&Self::Data>
+    ) -> Self::State;
+
+    /// Returns the next batch of candidates to score.  Empty once the run is done.
+    fn ask(&self, state: &mut Self::State) -> Vec<Self::Data>;
+
+    /// Reports the fitnesses for the batch returned by the last `ask`
+    fn tell(&self, state: &mut Self::State, fitnesses: Vec<Self::Value>);
+
+    /// Why the run has stopped, if it has
+    fn termination(&self, state: &Self::State) -> Option<TerminationReason> {
+        self.ledger(state).stopped()
+    }
+
+    /// Stops the run early
+    fn stop(&self, state: &mut Self::State, reason: TerminationReason) {
+        self.ledger_mut(state).stop(reason)
+    }
+
+    /// Discards the population and starts again from a fresh one.  The best
+    /// candidate and the budget carry over.
+    fn restart(&self, state: &mut Self::State);
+
+    /// Whether the run has stopped
+    fn is_done(&self, state: &Self::State) -> bool {
+        self.termination(state).is_some()
+    }
+
+    /// Replaces candidate `idx` of the pending batch with a freshly sampled
+    /// one, returning it.  Its score is then told in place of the original's.
+    fn resample(&self, state: &mut Self::State, idx: usize) -> Self::Data;
+
+    /// Budget, generation and failure counters for the run
+    fn ledger<'a>(&self, state: &'a Self::State) -> &'a Ledger<Self::Data, Self::Value>;
+
+    fn ledger_mut<'a>(&self, state: &'a mut Self::State) -> &'a mut Ledger<Self::Data, Self::Value>;
+
+    /// Backend that batches are bred and scored on
+    fn execution(&self) -> &Execution;
+
+    /// Number of function evaluations left, if the criteria are budget based
+    fn remaining(&self, state: &Self::State) -> Option<usize> {
+        self.ledger(state).remaining()
+    }
+
+    /// Best fitness and candidate seen so far
+    fn best<'a>(&self, state: &'a Self::State) -> (Self::Value, &'a Self::Data);
+
+    /// Summarizes the current population
+    fn stats(&self, state: &Self::State) -> Self::Stats;
+
+    /// Current population and the fitness of each member
+    fn population(&self, state: &Self::State) -> (Vec<Self::Data>, Vec<Self::Value>);
+
+    /// Number of times the population has been restarted
+    fn restarts(&self, state: &Self::State) -> usize {
+        self.ledger(state).restarts()
+    }
+
+    /// Whether `fit` folds in each generation at once or each evaluation as
+    /// it finishes
+    fn update_mode(&self) -> UpdateMode {
+        UpdateMode::Generational
+    }
+
+    /// Runs like `Optimizer::fit` with a fitness function which can fail.
+    /// Failures are handled according to `policy`; errors which don't abort
+    /// the run are collected in the result.
+    #[allow(clippy::type_complexity)]
+    fn fit_fallible<F, T, FN, R>(
         &self,
-        fit_fn: &F, 
-        total_fns: usize,
+        fit_fn: &F,
+        policy: FailurePolicy,
+        termination: T,
         seed: u64,
         x_in: Option<&Self::Data>,
         callback: FN
-    ) -> (f32, Self::Data);
+    ) -> Result<OptimizationResult<Self::Data, F::Error, Self::Value>, F::Error>
+    where
+        F: FallibleFitness<Data=Self::Data, Value=Self::Value>,
+        T: Termination<Self::Data, Self::Value> + 'static,
+        FN: FnMut(Self::Stats, Option<usize>) -> R,
+        R: Into<Control>
+    {
+        fallible::run(self, fit_fn, policy, termination, seed, x_in, callback)
+    }
+}
+
+/// Runs an ask/tell optimizer to completion, scoring each batch at once.
+pub(crate) fn run<O, F, T, FN, R>(
+    opt: &O,
+    fit_fn: &F,
+    termination: T,
+    seed: u64,
+    x_in: Option<&O::Data>,
+    callback: FN
+) -> OptimizationResult<O::Data, (), O::Value>
+where
+    O: AskTell,
+    F: Fitness<Data=O::Data, Value=O::Value>,
+    T: Termination<O::Data, O::Value> + 'static,
+    FN: FnMut(O::Stats, Option<usize>) -> R,
+    R: Into<Control>
+{
+    let result = drive(opt, fit_fn.direction(), termination, seed, x_in, callback,
+                       |opt, state, candidates, _errors| {
+        evaluate(opt, fit_fn, state, candidates);
+        Ok(())
+    });
+    result.unwrap_or_else(|()| unreachable!("Infallible evaluation failed"))
+}
+
+/// Scores a batch and tells the optimizer about it.  NaNs count as failures
+/// and free scores aren't charged to the budget.
+pub(crate) fn evaluate<O, F>(opt: &O, fit_fn: &F, state: &mut O::State, candidates: &[O::Data])
+where
+    O: AskTell,
+    F: Fitness<Data=O::Data, Value=O::Value>
+{
+    let scores = opt.execution().batch(candidates, |cs| fit_fn.score_batch_metered(cs));
+    assert_eq!(scores.len(), candidates.len(), "Expected a score per candidate");
+    let free = scores.iter().filter(|(_, free)| *free).count();
+    let mut fitnesses: Vec<_> = scores.into_iter().map(|(s, _)| s).collect();
+    let failures = fallible::scrub(&mut fitnesses);
+    opt.ledger_mut(state).fail(failures);
+    opt.ledger_mut(state).waive(free);
+    opt.tell(state, fitnesses);
+}
+
+/// Runs an ask/tell optimizer to completion.  `evaluate` scores each batch of
+/// candidates and tells the optimizer about them, collecting any errors which
+/// don't end the run.  An error returned from `evaluate` aborts the run.
+pub(crate) fn drive<O, T, FN, R, E, Er>(
+    opt: &O,
+    direction: Direction,
+    termination: T,
+    seed: u64,
+    x_in: Option<&O::Data>,
+    callback: FN,
+    evaluate: E
+) -> Result<OptimizationResult<O::Data, Er, O::Value>, Er>
+where
+    O: AskTell,
+    T: Termination<O::Data, O::Value> + 'static,
+    FN: FnMut(O::Stats, Option<usize>) -> R,
+    R: Into<Control>,
+    E: FnMut(&O, &mut O::State, &[O::Data], &mut Vec<Er>) -> Result<(), Er>
+{
+    let state = opt.init(Oriented(termination, direction), seed, x_in);
+    drive_from(opt, direction, state, Vec::new(), callback, evaluate, |_, _| Ok(()))
+}
+
+/// Carries a run on from `state` to completion, given the history so far.
+/// `checkpoint` is handed the state after every generation the run continues
+/// past.  Errors collected by `evaluate` end up in the result; errors returned
+/// by either closure abort the run.
+pub(crate) fn drive_from<O, FN, R, E, C, Ec, Er>(
+    opt: &O,
+    direction: Direction,
+    mut state: O::State,
+    mut history: Vec<GenerationStats<(), O::Value>>,
+    mut callback: FN,
+    mut evaluate: E,
+    mut checkpoint: C
+) -> Result<OptimizationResult<O::Data, Ec, O::Value>, Er>
+where
+    O: AskTell,
+    FN: FnMut(O::Stats, Option<usize>) -> R,
+    R: Into<Control>,
+    E: FnMut(&O, &mut O::State, &[O::Data], &mut Vec<Ec>) -> Result<(), Er>,
+    C: FnMut(&O::State, &[GenerationStats<(), O::Value>]) -> Result<(), Er>
+{
+    let mut errors = Vec::new();
+    while !opt.is_done(&state) {
+        let candidates = opt.ask(&mut state);
+
+        // Asking can end a run which can't afford its next batch
+        if opt.is_done(&state) {
+            break
+        }
+        evaluate(opt, &mut state, &candidates, &mut errors)?;
+
+        let stats = direction.orient_stats(opt.stats(&state));
+        history.push(stats.summary());
+        match callback(stats, opt.remaining(&state)).into() {
+            Control::Continue => (),
+            Control::Stop     => opt.stop(&mut state, TerminationReason::Callback),
+            Control::Restart  => opt.restart(&mut state)
+        }
+
+        if !opt.is_done(&state) {
+            checkpoint(&state, &history)?;
+        }
+    }
+
+    Ok(finish(opt, direction, state, errors, history))
+}
+
+/// Wraps up a finished run's state as its result
+pub(crate) fn finish<O: AskTell, E>(
+    opt: &O,
+    direction: Direction,
+    state: O::State,
+    errors: Vec<E>,
+    history: Vec<GenerationStats<(), O::Value>>
+) -> OptimizationResult<O::Data, E, O::Value> {
+    let (fitness, best) = opt.best(&state);
+    let stats = opt.stats(&state);
+    let (population, population_fitness) = opt.population(&state);
+    OptimizationResult {
+        fitness: direction.orient(fitness),
+        candidate: best.clone(),
+        evaluations: stats.evaluations,
+        generations: stats.generation,
+        termination: opt.termination(&state).expect("Run should have stopped!"),
+        restarts: opt.restarts(&state),
+        passes: opt.ledger(&state).passes().iter()
+            .map(|p| Pass { best: direction.orient(p.best), ..p.clone() })
+            .collect(),
+        failures: stats.failures,
+        errors,
+        population,
+        population_fitness: population_fitness.into_iter()
+            .map(|f| direction.orient(f))
+            .collect(),
+        history
+    }
 }
 
 #[cfg(test)]
 mod tests {
+    use std::sync::Mutex;
+
+    use super::*;
+    use crate::exp::*;
+    use crate::de::*;
+    use rand_distr::StandardNormal;
+
     #[test]
     fn it_works() {
         assert_eq!(2 + 2, 4);
     }
+
+    /// Records the size of every batch it is asked to score
+    struct BatchCounter(MatyasEnv, Mutex<Vec<usize>>);
+
+    impl Fitness for BatchCounter {
+        type Data = Vec<f32>;
+        type Value = f32;
+
+        fn score(&self, _candidate: &Vec<f32>) -> f32 {
+            panic!("Candidates should only be scored in batches")
+        }
+
+        fn score_batch(&self, candidates: &[Vec<f32>]) -> Vec<f32> {
+            self.1.lock().unwrap().push(candidates.len());
+            candidates.iter().map(|c| self.0.score(c)).collect()
+        }
+    }
+
+    #[test]
+    fn test_whole_generations_are_batched() {
+        let fit_fn = BatchCounter(MatyasEnv(-10., 10.), Mutex::new(Vec::new()));
+        let de = DePlus::builder(2, StandardNormal).lambda(30).f(0.1, 1.).build().unwrap();
+        de.fit(&fit_fn, 1000, 2020, None, |_best_fit, _fns_remaining| {});
+
+        let batches = fit_fn.1.into_inner().unwrap();
+        assert_eq!(batches.len(), 33);
+        assert!(batches.iter().all(|b| *b == 30));
+    }
 }
@@ -0,0 +1,302 @@
+#[cfg(feature = "serde")]
+use serde::{Deserialize,Serialize};
+
+use crate::Real;
+use crate::config::{ConfigError,check};
+
+/// How a challenger has to beat an incumbent to replace it
+#[derive(Clone,Copy,Debug,PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize,Deserialize))]
+pub enum Replacement {
+    /// Higher mean wins
+    Mean,
+
+    /// One sided Welch test: the challenger's mean has to be higher at
+    /// significance `alpha`.  Members without enough samples of their own
+    /// fall back on the population's pooled variance.
+    Welch { alpha: f32 }
+}
+
+/// Settings for noisy objectives, where scoring the same candidate twice can
+/// give different fitnesses.  Candidates are scored `samples` times and
+/// averaged, incumbents are rescored so lucky draws wash out, and
+/// replacements go through `replacement`.
+#[derive(Clone,Copy,Debug,PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize,Deserialize))]
+pub struct Noise {
+    /// Samples averaged for each new candidate to begin with
+    pub samples: usize,
+
+    /// Most samples per candidate.  Above `samples`, k doubles while most
+    /// comparisons are within a standard error of each other and halves
+    /// back once they're clear cut.
+    pub max_samples: usize,
+
+    /// Rescore the incumbents every N generations.  0 never does.
+    pub reevaluate: usize,
+
+    pub replacement: Replacement
+}
+
+impl Default for Noise {
+    fn default() -> Self {
+        Noise {
+            samples: 2,
+            max_samples: 16,
+            reevaluate: 1,
+            replacement: Replacement::Welch { alpha: 0.05 }
+        }
+    }
+}
+
+impl Noise {
+
+    pub fn validate(&self) -> Result<(), ConfigError> {
+        check(self.samples > 0, "noise", "needs at least one sample per candidate")?;
+        check(self.max_samples >= self.samples, "noise", "max_samples needs to be at least samples")?;
+        if let Replacement::Welch { alpha } = self.replacement {
+            check(alpha > 0. && alpha < 0.5, "noise", "alpha needs to be within (0, 0.5)")?;
+        }
+        Ok(())
+    }
+
+    /// Whether incumbents are rescored in the given generation
+    // `is_multiple_of` would need Rust 1.87
+    #[allow(clippy::manual_is_multiple_of)]
+    pub(crate) fn reevaluates(&self, generation: usize) -> bool {
+        generation > 0 && self.reevaluate > 0 && generation % self.reevaluate == 0
+    }
+}
+
+/// Running mean and variance of a candidate's fitness samples
+#[derive(Clone,Copy,Debug,Default,PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize,Deserialize))]
+pub struct Estimate<V = f32> {
+    n: usize,
+    mean: V,
+    m2: V
+}
+
+impl <V: Real> Estimate<V> {
+
+    pub fn new(sample: V) -> Self {
+        let mut e = Estimate::default();
+        e.push(sample);
+        e
+    }
+
+    /// Adds a sample.  A non-finite sample marks the candidate as failed.
+    pub fn push(&mut self, sample: V) {
+        if !sample.is_finite() || !self.mean.is_finite() {
+            self.n += 1;
+            self.mean = V::NEG_INFINITY;
+            return
+        }
+        self.n += 1;
+        let delta = sample - self.mean;
+        self.mean += delta / V::from_usize(self.n);
+        self.m2 += delta * (sample - self.mean);
+    }
+
+    pub fn samples(&self) -> usize {
+        self.n
+    }
+
+    pub fn mean(&self) -> V {
+        self.mean
+    }
+
+    /// Sample variance, once there are at least two samples
+    pub fn variance(&self) -> Option<V> {
+        if self.n > 1 && self.mean.is_finite() {
+            Some(self.m2 / V::from_usize(self.n - 1))
+        } else {
+            None
+        }
+    }
+}
+
+/// Mean variance of the estimates which have one
+pub(crate) fn pooled_variance<'a, V, I>(estimates: I) -> Option<V>
+where
+    V: Real,
+    I: Iterator<Item=&'a Estimate<V>>
+{
+    let (sum, count) = estimates.filter_map(|e| e.variance())
+        .fold((V::ZERO, 0), |(s, c), v| (s + v, c + 1));
+    if count > 0 { Some(sum / V::from_usize(count)) } else { None }
+}
+
+/// Upper `p` quantile of the standard normal, after Abramowitz and Stegun 26.2.23
+fn normal_quantile(p: f32) -> f32 {
+    let t = (-2. * p.ln()).sqrt();
+    t - (2.515_517 + 0.802_853 * t + 0.010_328 * t * t) /
+        (1. + 1.432_788 * t + 0.189_269 * t * t + 0.001_308 * t * t * t)
+}
+
+/// Noise bookkeeping for a run: the current samples per candidate, which
+/// incumbents the pending batch rescores, and how decisive this
+/// generation's comparisons have been.
+#[derive(Clone,Debug,Default)]
+#[cfg_attr(feature = "serde", derive(Serialize,Deserialize))]
+pub(crate) struct Sampling {
+    k: usize,
+    fresh: usize,
+    rescored: Vec<usize>,
+    ambiguous: usize,
+    compared: usize
+}
+
+impl Sampling {
+
+    pub(crate) fn new(noise: Option<&Noise>) -> Self {
+        Sampling { k: noise.map(|n| n.samples).unwrap_or(1), ..Sampling::default() }
+    }
+
+    /// Evaluations a batch of `fresh` candidates costs, before any rescoring
+    pub(crate) fn cost(&self, fresh: usize) -> usize {
+        fresh * self.k
+    }
+
+    /// Lays out a batch: `k` copies of each fresh candidate followed by the
+    /// incumbents to rescore, given as their index and value
+    pub(crate) fn expand<D: Clone>(&mut self, fresh: Vec<D>, incumbents: Vec<(usize, D)>) -> Vec<D> {
+        self.fresh = fresh.len();
+        self.rescored = incumbents.iter().map(|(i, _)| *i).collect();
+        let mut batch = Vec::with_capacity(self.fresh * self.k + incumbents.len());
+        for c in fresh {
+            (1..self.k).for_each(|_| batch.push(c.clone()));
+            batch.push(c);
+        }
+        batch.extend(incumbents.into_iter().map(|(_, c)| c));
+        batch
+    }
+
+    /// Fresh candidate a batch index samples, if any
+    pub(crate) fn slot(&self, idx: usize) -> Option<usize> {
+        if idx < self.fresh * self.k { Some(idx / self.k) } else { None }
+    }
+
+    /// Incumbent a batch index past the fresh candidates rescores
+    pub(crate) fn incumbent(&self, idx: usize) -> usize {
+        self.rescored[idx - self.fresh * self.k]
+    }
+
+    /// Splits scores for an expanded batch into an estimate per fresh
+    /// candidate and a sample per rescored incumbent
+    pub(crate) fn collapse<V: Real>(&self, scores: &[V]) -> (Vec<Estimate<V>>, Vec<(usize, V)>) {
+        assert_eq!(scores.len(), self.fresh * self.k + self.rescored.len(),
+                   "Expected a score per sample");
+        let (fresh, rescored) = scores.split_at(self.fresh * self.k);
+        let estimates = fresh.chunks(self.k).map(|samples| {
+            let mut e = Estimate::default();
+            samples.iter().for_each(|s| e.push(*s));
+            e
+        }).collect();
+        (estimates, self.rescored.iter().cloned().zip(rescored.iter().cloned()).collect())
+    }
+
+    /// Whether `challenger` should replace `incumbent`, tallying how clear
+    /// cut the comparison was
+    pub(crate) fn replaces<V: Real>(
+        &mut self,
+        noise: &Noise,
+        challenger: &Estimate<V>,
+        incumbent: &Estimate<V>,
+        pooled: Option<V>
+    ) -> bool {
+        // Nothing to defend yet
+        if incumbent.n == 0 {
+            return true
+        }
+
+        let diff = challenger.mean - incumbent.mean;
+        let se = challenger.variance().or(pooled).and_then(|vc| {
+            incumbent.variance().or(pooled).map(|vi| {
+                (vc / V::from_usize(challenger.n) + vi / V::from_usize(incumbent.n)).sqrt()
+            })
+        }).filter(|se| *se > V::ZERO && diff.is_finite());
+
+        self.compared += 1;
+        match se {
+            Some(se) => {
+                if diff.abs() < se {
+                    self.ambiguous += 1;
+                }
+                match noise.replacement {
+                    Replacement::Mean => diff > V::ZERO,
+                    Replacement::Welch { alpha } => diff / se > V::from_f32(normal_quantile(alpha))
+                }
+            },
+            None => diff > V::ZERO
+        }
+    }
+
+    /// Adjusts k at the end of a generation from how ambiguous its
+    /// comparisons were
+    pub(crate) fn adapt(&mut self, noise: &Noise) {
+        if self.compared > 0 {
+            let ambiguous = self.ambiguous as f32 / self.compared as f32;
+            if ambiguous > 0.5 {
+                self.k = (self.k * 2).min(noise.max_samples);
+            } else if ambiguous < 0.1 {
+                self.k = (self.k / 2).max(noise.samples);
+            }
+        }
+        self.ambiguous = 0;
+        self.compared = 0;
+    }
+
+    /// Current samples per fresh candidate
+    pub(crate) fn samples(&self) -> usize {
+        self.k
+    }
+}
+
+#[cfg(test)]
+mod test_noise {
+    use super::*;
+
+    #[test]
+    fn test_estimates() {
+        let mut e = Estimate::new(1.);
+        e.push(3.);
+        assert_eq!(e.mean(), 2.);
+        assert_eq!(e.variance(), Some(2.));
+        e.push(f32::NAN);
+        assert_eq!(e.mean(), f32::NEG_INFINITY);
+        assert_eq!(e.variance(), None);
+
+        assert!((normal_quantile(0.05) - 1.645).abs() < 1e-3);
+        assert!((normal_quantile(0.025) - 1.96).abs() < 1e-3);
+    }
+
+    #[test]
+    fn test_sampling() {
+        let noise = Noise { samples: 2, max_samples: 4, ..Noise::default() };
+        let mut sampling = Sampling::new(Some(&noise));
+        let batch = sampling.expand(vec!['a', 'b'], vec![(7, 'z')]);
+        assert_eq!(batch, vec!['a', 'a', 'b', 'b', 'z']);
+        assert_eq!(sampling.slot(3), Some(1));
+        assert_eq!(sampling.slot(4), None);
+
+        let (estimates, rescored) = sampling.collapse(&[1., 3., 5., 5., 9.]);
+        assert_eq!(estimates[0].mean(), 2.);
+        assert_eq!(estimates[1].variance(), Some(0.));
+        assert_eq!(rescored, vec![(7, 9.)]);
+
+        // A small gain within the noise isn't enough under Welch, but is on the mean
+        let incumbent = estimates[0];
+        let challenger = Estimate { n: 2, mean: 2.5f32, m2: 2. };
+        assert!(!sampling.replaces(&noise, &challenger, &incumbent, None));
+        let mean = Noise { replacement: Replacement::Mean, ..noise };
+        assert!(sampling.replaces(&mean, &challenger, &incumbent, None));
+
+        // Ambiguous comparisons grow k up to the cap, clear ones shrink it
+        sampling.adapt(&noise);
+        assert_eq!(sampling.samples(), 4);
+        sampling.replaces(&noise, &Estimate::new(100.), &incumbent, Some(1.));
+        sampling.adapt(&noise);
+        assert_eq!(sampling.samples(), 2);
+    }
+}
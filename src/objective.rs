@@ -0,0 +1,152 @@
+#[cfg(feature = "serde")]
+use serde::{Deserialize,Serialize};
+
+use crate::{Fitness,GenerationStats,Real,Termination,TerminationReason};
+
+/// Which way the user's objective points.  Optimizers always maximize
+/// internally; the direction maps fitness values back to the user's own
+/// orientation.
+#[derive(Clone,Copy,Debug,PartialEq,Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize,Deserialize))]
+pub enum Direction {
+    Maximize,
+    Minimize
+}
+
+impl Direction {
+
+    /// The opposite direction
+    pub fn flip(self) -> Self {
+        match self {
+            Direction::Maximize => Direction::Minimize,
+            Direction::Minimize => Direction::Maximize
+        }
+    }
+
+    /// Whether fitness `a` is better than `b`, in this direction
+    pub fn better<V: Real>(self, a: V, b: V) -> bool {
+        match self {
+            Direction::Maximize => a > b,
+            Direction::Minimize => a < b
+        }
+    }
+
+    /// Converts a fitness between the internal, maximized value and the
+    /// user's orientation.  Applying it twice is a no-op.
+    pub fn orient<V: Real>(self, fitness: V) -> V {
+        match self {
+            Direction::Maximize => fitness,
+            Direction::Minimize => -fitness
+        }
+    }
+
+    /// Reports generation stats in the user's orientation
+    pub fn orient_stats<D, V: Real>(self, mut stats: GenerationStats<D, V>) -> GenerationStats<D, V> {
+        stats.direction = self;
+        stats.best   = self.orient(stats.best);
+        stats.mean   = self.orient(stats.mean);
+        stats.median = self.orient(stats.median);
+        stats.worst  = self.orient(stats.worst);
+        stats
+    }
+}
+
+/// Turns a loss into a fitness, so that optimizers minimize it.  Stats and
+/// results from `fit` report the loss itself, not the negated value.
+#[derive(Clone,Copy,Debug)]
+pub struct Minimize<F>(pub F);
+
+impl <F: Fitness> Fitness for Minimize<F> {
+    type Data = F::Data;
+    type Value = F::Value;
+
+    fn score(&self, candidate: &F::Data) -> F::Value {
+        -self.0.score(candidate)
+    }
+
+    fn score_batch(&self, candidates: &[F::Data]) -> Vec<F::Value> {
+        let mut scores = self.0.score_batch(candidates);
+        scores.iter_mut().for_each(|s| *s = -*s);
+        scores
+    }
+
+    fn score_metered(&self, candidate: &F::Data) -> (F::Value, bool) {
+        let (s, free) = self.0.score_metered(candidate);
+        (-s, free)
+    }
+
+    fn score_batch_metered(&self, candidates: &[F::Data]) -> Vec<(F::Value, bool)> {
+        let mut scores = self.0.score_batch_metered(candidates);
+        scores.iter_mut().for_each(|(s, _)| *s = -*s);
+        scores
+    }
+
+    fn direction(&self) -> Direction {
+        self.0.direction().flip()
+    }
+}
+
+/// Hands termination criteria stats in the user's orientation, so targets
+/// can be written in terms of the user's objective.
+#[derive(Debug)]
+pub(crate) struct Oriented<T>(pub T, pub Direction);
+
+impl <D: Clone, V: Real, T: Termination<D, V>> Termination<D, V> for Oriented<T> {
+    fn check(&mut self, stats: &GenerationStats<D, V>) -> Option<TerminationReason> {
+        match self.1 {
+            Direction::Maximize => self.0.check(stats),
+            Direction::Minimize => self.0.check(&self.1.orient_stats(stats.clone()))
+        }
+    }
+
+    fn remaining(&self, evaluations: usize) -> Option<usize> {
+        self.0.remaining(evaluations)
+    }
+
+    fn reset(&mut self) {
+        self.0.reset()
+    }
+}
+
+#[cfg(test)]
+mod test_objective {
+    use rand_distr::StandardNormal;
+
+    use super::*;
+    use crate::Optimizer;
+    use crate::de::*;
+    use crate::termination::*;
+
+    /// Squared distance from (1, 2); a loss to be minimized
+    struct Sphere;
+
+    impl Fitness for Sphere {
+        type Data = Vec<f32>;
+        type Value = f32;
+
+        fn score(&self, candidate: &Vec<f32>) -> f32 {
+            (candidate[0] - 1.).powi(2) + (candidate[1] - 2.).powi(2)
+        }
+    }
+
+    #[test]
+    fn test_minimize() {
+        let de = DePlus::builder(2, StandardNormal).lambda(30).f(0.1, 1.).build().unwrap();
+
+        let fit_fn = Minimize(Sphere);
+        assert_eq!(fit_fn.direction(), Direction::Minimize);
+
+        let mut bests = Vec::new();
+        let result = de.fit(&fit_fn, Any(10000, TargetFitness(1e-6)), 2020, None, |stats, _| {
+            bests.push(stats.best);
+        });
+
+        // Losses are reported as is, and shrink over time
+        assert_eq!(result.termination, TerminationReason::TargetReached);
+        assert!(result.fitness >= 0. && result.fitness <= 1e-6);
+        assert!(bests.windows(2).all(|w| w[1] <= w[0]));
+        assert!(result.population_fitness.iter().all(|f| *f >= result.fitness));
+        assert!((result.candidate[0] - 1.).abs() < 1e-2);
+        assert!((result.candidate[1] - 2.).abs() < 1e-2);
+    }
+}
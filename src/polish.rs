@@ -0,0 +1,417 @@
+#[cfg(feature = "serde")]
+use serde::{Deserialize,Serialize};
+
+use crate::Real;
+use crate::bounds::Bounds;
+use crate::config::{ConfigError,check,check_prob};
+
+/// Derivative-free local search a polisher runs
+#[derive(Clone,Copy,Debug,PartialEq,Default)]
+#[cfg_attr(feature = "serde", derive(Serialize,Deserialize))]
+pub enum LocalMethod {
+    /// Nelder-Mead simplex, with the standard reflection, expansion,
+    /// contraction and shrink coefficients
+    #[default]
+    NelderMead,
+
+    /// Hooke-Jeeves pattern search.  Probes every coordinate both ways at
+    /// once, so a probe's evaluations can run in parallel, and halves the step
+    /// whenever none improve.
+    HookeJeeves
+}
+
+/// Refines the incumbent with a local search, spending a share of what's
+/// left of the budget.  Local searches follow fitness alone; their best only
+/// replaces the incumbent if it's better under the feasibility rules.
+/// Steps are clipped to the bounds, whatever their repair.
+#[derive(Clone,Copy,Debug,PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize,Deserialize))]
+pub struct Polisher {
+    pub method: LocalMethod,
+
+    /// Fraction of the remaining budget each stale polish may use, and the
+    /// fraction held back for the polish at the end
+    pub share: f32,
+
+    /// Polish once the best hasn't improved for this many generations
+    pub on_stale: Option<usize>,
+
+    /// Polish with whatever is left once the budget is down to its share.
+    /// Only applies with a budget.
+    pub at_end: bool,
+
+    /// Size of the first steps, relative to the population's spread in each
+    /// dimension.  Dimensions with no spread step this far outright.
+    pub step: f32,
+
+    /// Stops a search once its steps are all within this distance
+    pub tolerance: f32
+}
+
+impl Default for Polisher {
+    fn default() -> Self {
+        Polisher {
+            method: LocalMethod::default(),
+            share: 0.1,
+            on_stale: None,
+            at_end: true,
+            step: 0.5,
+            tolerance: 1e-6
+        }
+    }
+}
+
+impl Polisher {
+
+    pub fn validate(&self) -> Result<(), ConfigError> {
+        check_prob("polisher", self.share)?;
+        check(self.share > 0., "polisher", "share needs to be positive")?;
+        check(self.on_stale != Some(0), "polisher", "on_stale needs to be at least one generation")?;
+        check(self.step > 0. && self.step.is_finite(), "polisher", "step needs to be positive")?;
+        check(self.tolerance >= 0. && self.tolerance.is_finite(), "polisher",
+              "tolerance needs to be non-negative")
+    }
+
+    /// Starts a search from `x0`, which has already been scored, scaling the
+    /// first steps to the population it came from
+    pub(crate) fn start<T: Real>(
+        &self,
+        x0: &[T],
+        f0: T,
+        population: &[Vec<T>],
+        bounds: Option<&Bounds<T>>
+    ) -> LocalSearch<T> {
+        let step = T::from_f32(self.step);
+        let step: Vec<_> = (0..x0.len()).map(|d| {
+            let lower = population.iter().map(|x| x[d]).fold(T::INFINITY, T::min);
+            let upper = population.iter().map(|x| x[d]).fold(T::NEG_INFINITY, T::max);
+            let spread = upper - lower;
+            if spread > T::ZERO && spread.is_finite() { step * spread } else { step }
+        }).collect();
+        match self.method {
+            LocalMethod::NelderMead => LocalSearch::NelderMead(NelderMead::new(x0, f0, &step, bounds)),
+            LocalMethod::HookeJeeves => LocalSearch::HookeJeeves(HookeJeeves::new(x0, f0, step, bounds))
+        }
+    }
+}
+
+/// Keeps a point within the bounds
+fn clip<T: Real>(x: &mut [T], bounds: Option<&Bounds<T>>) {
+    if let Some(bounds) = bounds {
+        x.iter_mut().zip(bounds.lower.iter().zip(bounds.upper.iter()))
+            .for_each(|(xi, (l, u))| *xi = xi.max(*l).min(*u));
+    }
+}
+
+/// Widest gap, in any dimension, between two points
+fn distance<T: Real>(a: &[T], b: &[T]) -> T {
+    a.iter().zip(b.iter()).map(|(ai, bi)| (*ai - *bi).abs()).fold(T::ZERO, T::max)
+}
+
+/// Local search in progress, asked and told like an optimizer
+#[derive(Clone,Debug)]
+#[cfg_attr(feature = "serde", derive(Serialize,Deserialize))]
+pub(crate) enum LocalSearch<T> {
+    NelderMead(NelderMead<T>),
+    HookeJeeves(HookeJeeves<T>)
+}
+
+impl <T: Real> LocalSearch<T> {
+
+    /// Points to score next
+    pub(crate) fn ask(&self) -> &[Vec<T>] {
+        match self {
+            LocalSearch::NelderMead(nm) => &nm.pending,
+            LocalSearch::HookeJeeves(hj) => &hj.pending
+        }
+    }
+
+    pub(crate) fn tell(&mut self, fitnesses: &[T], bounds: Option<&Bounds<T>>) {
+        assert_eq!(fitnesses.len(), self.ask().len(), "Expected a score per point");
+        match self {
+            LocalSearch::NelderMead(nm) => nm.tell(fitnesses, bounds),
+            LocalSearch::HookeJeeves(hj) => hj.tell(fitnesses, bounds)
+        }
+    }
+
+    /// Whether the search has closed in on a point
+    pub(crate) fn converged(&self, tolerance: T) -> bool {
+        match self {
+            LocalSearch::NelderMead(nm) => nm.step != NmStep::Init &&
+                nm.simplex.iter().all(|x| distance(x, &nm.simplex[0]) <= tolerance),
+            LocalSearch::HookeJeeves(hj) => hj.h.iter().all(|h| *h <= tolerance)
+        }
+    }
+}
+
+#[derive(Clone,Copy,Debug,PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize,Deserialize))]
+enum NmStep {
+    Init,
+    Reflect,
+    Expand,
+    Contract { outside: bool },
+    Shrink
+}
+
+/// Nelder-Mead simplex, fittest vertex first once ordered
+#[derive(Clone,Debug)]
+#[cfg_attr(feature = "serde", derive(Serialize,Deserialize))]
+pub(crate) struct NelderMead<T> {
+    simplex: Vec<Vec<T>>,
+    fits: Vec<T>,
+    step: NmStep,
+
+    /// Reflected point and its fitness, while expanding or contracting
+    reflected: (Vec<T>, T),
+
+    pending: Vec<Vec<T>>
+}
+
+impl <T: Real> NelderMead<T> {
+
+    fn new(x0: &[T], f0: T, step: &[T], bounds: Option<&Bounds<T>>) -> Self {
+        let mut simplex = vec![x0.to_vec()];
+        for i in 0..x0.len() {
+            // Step back instead of into a bound
+            let mut x = x0.to_vec();
+            x[i] += step[i];
+            if bounds.map(|b| x[i] > b.upper[i]).unwrap_or(false) {
+                x[i] = x0[i] - step[i];
+            }
+            clip(&mut x, bounds);
+            simplex.push(x);
+        }
+        let pending = simplex[1..].to_vec();
+        let mut fits = vec![T::NEG_INFINITY; simplex.len()];
+        fits[0] = f0;
+        NelderMead { simplex, fits, step: NmStep::Init, reflected: (Vec::new(), T::NEG_INFINITY), pending }
+    }
+
+    /// Centroid of every vertex but the worst
+    fn centroid(&self) -> Vec<T> {
+        let n = self.simplex.len() - 1;
+        (0..self.simplex[0].len())
+            .map(|d| self.simplex[..n].iter().map(|x| x[d]).sum::<T>() / T::from_usize(n))
+            .collect()
+    }
+
+    /// Point along the line from the centroid through the worst vertex
+    fn along(&self, coef: T, bounds: Option<&Bounds<T>>) -> Vec<T> {
+        let c = self.centroid();
+        let worst = &self.simplex[self.simplex.len() - 1];
+        let mut x: Vec<_> = c.iter().zip(worst.iter()).map(|(ci, wi)| *ci + coef * (*ci - *wi)).collect();
+        clip(&mut x, bounds);
+        x
+    }
+
+    /// Orders the simplex and proposes its reflection
+    fn reflect(&mut self, bounds: Option<&Bounds<T>>) {
+        let mut order: Vec<_> = (0..self.simplex.len()).collect();
+        order.sort_by(|i, j| self.fits[*j].cmp_float(&self.fits[*i]));
+        self.simplex = order.iter().map(|i| self.simplex[*i].clone()).collect();
+        self.fits = order.iter().map(|i| self.fits[*i]).collect();
+        self.pending = vec![self.along(T::ONE, bounds)];
+        self.step = NmStep::Reflect;
+    }
+
+    fn replace_worst(&mut self, x: Vec<T>, f: T) {
+        let n = self.simplex.len() - 1;
+        self.simplex[n] = x;
+        self.fits[n] = f;
+    }
+
+    fn tell(&mut self, fitnesses: &[T], bounds: Option<&Bounds<T>>) {
+        let n = self.simplex.len() - 1;
+        let half = T::from_f32(0.5);
+        match self.step {
+            NmStep::Init | NmStep::Shrink => {
+                self.fits[1..].copy_from_slice(fitnesses);
+                self.reflect(bounds);
+            },
+            NmStep::Reflect => {
+                let (xr, fr) = (self.pending.remove(0), fitnesses[0]);
+                if fr > self.fits[0] {
+                    self.pending = vec![self.along(T::from_f32(2.), bounds)];
+                    self.reflected = (xr, fr);
+                    self.step = NmStep::Expand;
+                } else if fr > self.fits[n - 1] {
+                    self.replace_worst(xr, fr);
+                    self.reflect(bounds);
+                } else {
+                    let outside = fr > self.fits[n];
+                    let coef = if outside { half } else { -half };
+                    self.pending = vec![self.along(coef, bounds)];
+                    self.reflected = (xr, fr);
+                    self.step = NmStep::Contract { outside };
+                }
+            },
+            NmStep::Expand => {
+                let (xe, fe) = (self.pending.remove(0), fitnesses[0]);
+                let (xr, fr) = std::mem::take(&mut self.reflected);
+                if fe > fr {
+                    self.replace_worst(xe, fe);
+                } else {
+                    self.replace_worst(xr, fr);
+                }
+                self.reflect(bounds);
+            },
+            NmStep::Contract { outside } => {
+                let (xc, fc) = (self.pending.remove(0), fitnesses[0]);
+                let beaten = if outside { self.reflected.1 } else { self.fits[n] };
+                if fc >= beaten && fc.is_finite() {
+                    self.replace_worst(xc, fc);
+                    self.reflect(bounds);
+                } else {
+                    // Shrink everything towards the best vertex
+                    let best = self.simplex[0].clone();
+                    for x in self.simplex[1..].iter_mut() {
+                        x.iter_mut().zip(best.iter()).for_each(|(xi, bi)| *xi = *bi + half * (*xi - *bi));
+                    }
+                    self.pending = self.simplex[1..].to_vec();
+                    self.step = NmStep::Shrink;
+                }
+            }
+        }
+    }
+}
+
+#[derive(Clone,Copy,Debug,PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize,Deserialize))]
+enum HjStep {
+    Probe,
+    Pattern
+}
+
+/// Hooke-Jeeves pattern search around a base point
+#[derive(Clone,Debug)]
+#[cfg_attr(feature = "serde", derive(Serialize,Deserialize))]
+pub(crate) struct HookeJeeves<T> {
+    base: Vec<T>,
+    base_fit: T,
+
+    /// Step along each coordinate
+    h: Vec<T>,
+    step: HjStep,
+
+    /// Best probe, while its pattern move is tried
+    moved: (Vec<T>, T),
+
+    pending: Vec<Vec<T>>
+}
+
+impl <T: Real> HookeJeeves<T> {
+
+    fn new(x0: &[T], f0: T, h: Vec<T>, bounds: Option<&Bounds<T>>) -> Self {
+        let mut hj = HookeJeeves {
+            base: x0.to_vec(),
+            base_fit: f0,
+            h,
+            step: HjStep::Probe,
+            moved: (Vec::new(), T::NEG_INFINITY),
+            pending: Vec::new()
+        };
+        hj.probe(bounds);
+        hj
+    }
+
+    /// Proposes a step of h either way along every coordinate
+    fn probe(&mut self, bounds: Option<&Bounds<T>>) {
+        self.pending = (0..self.base.len()).flat_map(|d| {
+            let mut up = self.base.clone();
+            let mut down = self.base.clone();
+            up[d] += self.h[d];
+            down[d] -= self.h[d];
+            clip(&mut up, bounds);
+            clip(&mut down, bounds);
+            vec![up, down]
+        }).collect();
+        self.step = HjStep::Probe;
+    }
+
+    fn tell(&mut self, fitnesses: &[T], bounds: Option<&Bounds<T>>) {
+        match self.step {
+            HjStep::Probe => {
+                let best = (0..fitnesses.len())
+                    .max_by(|i, j| fitnesses[*i].cmp_float(&fitnesses[*j]))
+                    .expect("Probes should never be empty");
+                if fitnesses[best] > self.base_fit {
+                    // Carry on in the direction which worked
+                    let moved = self.pending.swap_remove(best);
+                    let mut pattern: Vec<_> = moved.iter().zip(self.base.iter())
+                        .map(|(mi, bi)| *mi + (*mi - *bi))
+                        .collect();
+                    clip(&mut pattern, bounds);
+                    self.moved = (moved, fitnesses[best]);
+                    self.pending = vec![pattern];
+                    self.step = HjStep::Pattern;
+                } else {
+                    self.h.iter_mut().for_each(|h| *h *= T::from_f32(0.5));
+                    self.probe(bounds);
+                }
+            },
+            HjStep::Pattern => {
+                let (moved, moved_fit) = std::mem::take(&mut self.moved);
+                if fitnesses[0] > moved_fit {
+                    self.base = self.pending.remove(0);
+                    self.base_fit = fitnesses[0];
+                } else {
+                    self.base = moved;
+                    self.base_fit = moved_fit;
+                }
+                self.probe(bounds);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test_polish {
+    use super::*;
+
+    /// Drives a search on a shifted sphere until it converges, returning the
+    /// best point it scored and the evaluations it took
+    fn run(polisher: &Polisher) -> (Vec<f64>, usize) {
+        let score = |x: &Vec<f64>| -x.iter().enumerate().map(|(i, xi)| (xi - i as f64).powi(2)).sum::<f64>();
+        let x0 = vec![0.3, 0.6, 2.4];
+        let mut search = polisher.start(&x0, score(&x0), &[], None);
+        let (mut best, mut evaluations) = (x0, 0);
+        while !search.converged(polisher.tolerance as f64) {
+            let points = search.ask().to_vec();
+            let fits: Vec<_> = points.iter().map(score).collect();
+            evaluations += fits.len();
+            for (x, f) in points.iter().zip(fits.iter()) {
+                if *f > score(&best) {
+                    best = x.clone();
+                }
+            }
+            search.tell(&fits, None);
+            assert!(evaluations < 10000, "Search never converged");
+        }
+        (best, evaluations)
+    }
+
+    #[test]
+    fn test_local_search() {
+        for method in [LocalMethod::NelderMead, LocalMethod::HookeJeeves].iter() {
+            let polisher = Polisher { method: *method, tolerance: 1e-9, ..Polisher::default() };
+            let (best, _) = run(&polisher);
+            assert!(best.iter().enumerate().all(|(i, xi)| (xi - i as f64).abs() < 1e-6),
+                    "{:?}: {:?}", method, best);
+        }
+
+        // Steps stay within the bounds
+        let bounds = Bounds::uniform(2, 0., 1., crate::Repair::Clip);
+        for method in [LocalMethod::NelderMead, LocalMethod::HookeJeeves].iter() {
+            let polisher = Polisher { method: *method, ..Polisher::default() };
+            let mut search = polisher.start(&[0.95, 0.5], -1., &[vec![0.9, 0.3], vec![1., 0.5]], Some(&bounds));
+            assert!(search.ask().iter().all(|x| bounds.contains(x)));
+            let fits = vec![-2.; search.ask().len()];
+            search.tell(&fits, Some(&bounds));
+            assert!(search.ask().iter().all(|x| bounds.contains(x)));
+        }
+
+        assert!(Polisher { share: 0., ..Polisher::default() }.validate().is_err());
+        assert!(Polisher { tolerance: -1., ..Polisher::default() }.validate().is_err());
+    }
+}
@@ -2,71 +2,99 @@ extern crate rand;
 extern crate rayon;
 extern crate float_ord;
 
-use float_ord::FloatOrd;
+#[cfg(feature = "serde")]
+use std::convert::TryFrom;
+use std::sync::Arc;
 
 use rand::prelude::*;
 use rand_distr::{Distribution,Uniform};
-use rayon::prelude::*;
 
-use crate::{Fitness,Optimizer};
+#[cfg(feature = "serde")]
+use serde::{Deserialize,Serialize};
+
+use crate::{AskTell,Control,Fitness,Optimizer,OptimizationResult,Real,RestartCause,Termination,TerminationReason};
+use crate::ledger::Ledger;
+use crate::stats::{GenerationStats,diversity};
+use crate::bounds::Bounds;
+use crate::execution::Execution;
+use crate::seed::{SeedSequence,Stream};
+use crate::config::{ConfigError,check,check_range};
+use crate::noise::{Estimate,Noise,Sampling,pooled_variance};
+use crate::init::{self,Initializer};
+use crate::constraints::fittest;
 
 #[derive(Clone,Debug)]
-struct Particle {
-    position: Vec<f32>,
-    velocity: Vec<f32>,
-    best_seen: Vec<f32>,
-    fitness: f32,
-    best_fitness: f32
+#[cfg_attr(feature = "serde", derive(Serialize,Deserialize))]
+struct Particle<T> {
+    position: Vec<T>,
+    velocity: Vec<T>,
+    best_seen: Vec<T>,
+    fitness: T,
+    best_fitness: T,
+
+    /// Fitness samples behind the personal best
+    best_estimate: Estimate<T>
 }
 
-impl Particle {
-    fn new<D: Distribution<f32>, R: Rng>(dims: usize, mut rng: R, d: &D) -> Self {
+impl <T: Real> Particle<T> {
+    fn new(position: Vec<T>) -> Self {
+        let dims = position.len();
         Particle {
-            position: d.sample_iter(&mut rng).take(dims).collect(),
-            velocity: vec![0.; dims],
-            best_seen: vec![0.; dims],
-            fitness: std::f32::MIN,
-            best_fitness: std::f32::MIN
+            position,
+            velocity: vec![T::ZERO; dims],
+            best_seen: vec![T::ZERO; dims],
+            fitness: T::MIN,
+            best_fitness: T::MIN,
+            best_estimate: Estimate::default()
         }
     }
 
-    fn evaluate<F: Fitness<Data=Vec<f32>>>(&mut self, f: &F) -> f32 {
-        self.fitness = f.score(&self.position);
-        if self.fitness > self.best_fitness {
+    /// Scores the current position, making it the personal best if it won
+    fn evaluate(&mut self, estimate: Estimate<T>, replaces: bool) {
+        self.fitness = estimate.mean();
+        if replaces {
             self.best_seen.copy_from_slice(&self.position);
             self.best_fitness = self.fitness;
+            self.best_estimate = estimate;
         }
-        self.fitness
     }
 
     fn update<R: Rng>(
-        &mut self, 
-        global_best: &[f32], 
-        w: f32, 
-        c_1: f32, 
-        c_2: f32, 
-        mut rng: R) 
+        &mut self,
+        global_best: &[T],
+        w: f32,
+        c_1: f32,
+        c_2: f32,
+        bounds: Option<&Bounds<T>>,
+        mut rng: R)
     {
-        let d = Uniform::new(0., 1.);
-        
+        let d = Uniform::new(0f32, 1.);
+        let (w, c_1, c_2) = (T::from_f32(w), T::from_f32(c_1), T::from_f32(c_2));
+
         let p = &self.position;
         let bs = &self.best_seen;
         self.velocity.iter_mut().enumerate().for_each(|(i, vi)| {
-            let r_1 = d.sample(&mut rng);
-            let r_2 = d.sample(&mut rng);
+            let r_1 = T::from_f32(d.sample(&mut rng));
+            let r_2 = T::from_f32(d.sample(&mut rng));
             *vi = w * *vi + 
                 r_1 * c_1 * (global_best[i] - p[i]) +
                 r_2 * c_2 * (bs[i] - p[i]);
         });
 
+        let parent = bounds.map(|_| self.position.clone());
         self.position.iter_mut().zip(self.velocity.iter()).for_each(|(pi, vi)| {
-            *pi += vi;
+            *pi += *vi;
         });
+
+        if let (Some(bounds), Some(parent)) = (bounds, parent) {
+            bounds.repair(&mut self.position, Some(&parent), &mut rng);
+        }
     }
 }
 
 #[derive(Clone,Debug)]
-pub struct PSO {
+#[cfg_attr(feature = "serde", derive(Serialize,Deserialize), serde(try_from = "PsoBuilder<T>", bound(deserialize = "T: Real")))]
+pub struct PSO<T = f32> {
     /// Number of dimensions in the genome
     dims: usize,
 
@@ -83,86 +111,497 @@ pub struct PSO {
     c_2: f32,
 
     /// The min value for X
-    x_range: Option<(f32, f32)>
+    x_range: Option<(T, T)>,
+
+    /// If provided, draws each restart's starting positions in place of x_range
+    #[cfg_attr(feature = "serde", serde(skip))]
+    initializer: Option<Arc<dyn Initializer<T>>>,
+
+    /// If provided, keeps particles within the box, repairing any which leave it
+    bounds: Option<Bounds<T>>,
+
+    /// Where particles are moved and scored
+    execution: Execution,
 
+    /// If provided, averages repeated samples of each position and rescores
+    /// the personal bests, so lucky draws don't get locked in
+    noise: Option<Noise>
+
+}
+
+/// Builds a `PSO`, checking its settings hang together.  Configs are read
+/// through here too, so any setting left out takes the builder's default.
+#[derive(Clone,Debug)]
+#[cfg_attr(feature = "serde", derive(Deserialize), serde(bound(deserialize = "T: Real")))]
+pub struct PsoBuilder<T = f32> {
+    dims: usize,
+    #[cfg_attr(feature = "serde", serde(default = "default_swarm_size"))]
+    swarm_size: usize,
+    #[cfg_attr(feature = "serde", serde(default = "default_w"))]
+    w: f32,
+    #[cfg_attr(feature = "serde", serde(default = "default_c"))]
+    c_1: f32,
+    #[cfg_attr(feature = "serde", serde(default = "default_c"))]
+    c_2: f32,
+    #[cfg_attr(feature = "serde", serde(default))]
+    x_range: Option<(T, T)>,
+    #[cfg_attr(feature = "serde", serde(skip))]
+    initializer: Option<Arc<dyn Initializer<T>>>,
+    #[cfg_attr(feature = "serde", serde(default))]
+    bounds: Option<Bounds<T>>,
+    #[cfg_attr(feature = "serde", serde(default))]
+    execution: Execution,
+    #[cfg_attr(feature = "serde", serde(default))]
+    noise: Option<Noise>
 }
 
-impl PSO {
+fn default_swarm_size() -> usize { 30 }
+
+// Clerc and Kennedy's constriction coefficients
+fn default_w() -> f32 { 0.7298 }
+fn default_c() -> f32 { 1.49618 }
+
+impl <T: Real> PsoBuilder<T> {
+
+    /// Defaults to 30 particles
+    pub fn swarm_size(mut self, swarm_size: usize) -> Self {
+        self.swarm_size = swarm_size;
+        self
+    }
+
+    /// Momentum coefficient.  Defaults to 0.7298.
+    pub fn w(mut self, w: f32) -> Self {
+        self.w = w;
+        self
+    }
+
+    /// Global and local bias coefficients.  Both default to 1.49618.
+    pub fn c(mut self, c_1: f32, c_2: f32) -> Self {
+        self.c_1 = c_1;
+        self.c_2 = c_2;
+        self
+    }
+
+    /// Range particles start in.  Defaults to (-1, 1).
+    pub fn x_range(mut self, x_min: T, x_max: T) -> Self {
+        self.x_range = Some((x_min, x_max));
+        self
+    }
+
+    /// Draws starting positions from `initializer` rather than x_range
+    pub fn initializer<I: Initializer<T> + 'static>(mut self, initializer: I) -> Self {
+        self.initializer = Some(Arc::new(initializer));
+        self
+    }
+
+    pub fn bounds(mut self, bounds: Bounds<T>) -> Self {
+        self.bounds = Some(bounds);
+        self
+    }
+
+    pub fn execution(mut self, execution: Execution) -> Self {
+        self.execution = execution;
+        self
+    }
+
+    pub fn noise(mut self, noise: Noise) -> Self {
+        self.noise = Some(noise);
+        self
+    }
+
+    pub fn build(self) -> Result<PSO<T>, ConfigError> {
+        check(self.dims > 0, "dims", "needs at least one dimension")?;
+        check(self.swarm_size > 0, "swarm_size", "needs at least one particle")?;
+        check(self.w.is_finite(), "w", "needs to be finite")?;
+        check(self.c_1.is_finite() && self.c_1 >= 0., "c_1", "needs to be non-negative")?;
+        check(self.c_2.is_finite() && self.c_2 >= 0., "c_2", "needs to be non-negative")?;
+        if let Some(x_range) = self.x_range {
+            check_range("x_range", x_range)?;
+        }
+        if let Some(bounds) = &self.bounds {
+            bounds.validate()?;
+            check(bounds.dims() == self.dims, "bounds", "need a limit per dimension")?;
+        }
+        if let Some(initializer) = &self.initializer {
+            init::check_init(initializer.as_ref(), self.dims)?;
+        }
+        if let Some(noise) = &self.noise {
+            noise.validate()?;
+        }
+
+        Ok(PSO {
+            dims: self.dims,
+            swarm_size: self.swarm_size,
+            w: self.w,
+            c_1: self.c_1,
+            c_2: self.c_2,
+            x_range: self.x_range,
+            initializer: self.initializer,
+            bounds: self.bounds,
+            execution: self.execution,
+            noise: self.noise
+        })
+    }
+}
+
+#[cfg(feature = "serde")]
+impl <T: Real> TryFrom<PsoBuilder<T>> for PSO<T> {
+    type Error = ConfigError;
+
+    fn try_from(builder: PsoBuilder<T>) -> Result<Self, ConfigError> {
+        builder.build()
+    }
+}
+
+impl <T: Real> PSO<T> {
+
+    /// Starts a validated configuration for a `dims` dimensional search
+    pub fn builder(dims: usize) -> PsoBuilder<T> {
+        PsoBuilder {
+            dims,
+            swarm_size: default_swarm_size(),
+            w: default_w(),
+            c_1: default_c(),
+            c_2: default_c(),
+            x_range: None,
+            initializer: None,
+            bounds: None,
+            execution: Execution::default(),
+            noise: None
+        }
+    }
+
+    pub fn dims(&self) -> usize {
+        self.dims
+    }
+
+    pub fn swarm_size(&self) -> usize {
+        self.swarm_size
+    }
     
-    fn get_best(swarm: &[Particle], cur_best: &mut [f32]) -> f32 {
+    fn get_best(swarm: &[Particle<T>], cur_best: &mut [T]) -> T {
         // Get best candidate
         let best_p = swarm.iter()
-            .max_by_key(|p| FloatOrd(p.best_fitness))
+            .max_by(|a, b| a.best_fitness.cmp_float(&b.best_fitness))
             .expect("Swarm should always be at least a size of one");
         cur_best.copy_from_slice(&best_p.best_seen);
         best_p.best_fitness
     }
 }
 
-impl Optimizer for PSO {
-    type Stats = f32;
-    type Data = Vec<f32>;
+/// Ask/tell state for a PSO run
+#[derive(Debug)]
+#[cfg_attr(feature = "serde", derive(Serialize,Deserialize))]
+pub struct PsoState<T = f32> {
+    /// Streams for the run, one child per restart
+    seeds: SeedSequence,
 
-    fn fit<F: Fitness<Data=Self::Data>, FN: FnMut(f32, usize) -> ()>(
-        &self,
-        fit_fn: &F,
-        total_fns: usize,
-        seed: u64,
-        x_in: Option<&Vec<f32>>,
-        mut callback: FN
-    ) -> (f32, Vec<f32>) {
+    x_in: Option<Vec<T>>,
+
+    swarm: Vec<Particle<T>>,
+
+    /// An rng per particle.  This guarantees reproducibility
+    rngs: Vec<Stream>,
 
-        let mut rng = rand::rngs::StdRng::seed_from_u64(seed);
+    /// Samples per position and personal bests being rescored
+    sampling: Sampling,
+
+    global_fit: T,
+    global_best: Vec<T>,
+
+    /// Whether the swarm still needs its initial scores
+    initialized: bool,
+
+    /// Whether the positions handed out by the last ask are still unscored
+    pending: bool,
+
+    ledger: Ledger<Vec<T>, T>,
+
+    /// Best seen across restarts
+    best_fit: T,
+    best: Vec<T>
+}
+
+impl <T: Real> PSO<T> {
+
+    /// Samples a fresh swarm along with an rng per particle.  An initializer
+    /// may add extra particles, which are cut down once scored.
+    fn init_swarm(&self, seeds: &SeedSequence, x_in: Option<&Vec<T>>) -> (Vec<Particle<T>>, Vec<Stream>) {
+        let rngs = (0..self.swarm_size)
+            .map(|i| seeds.spawn(i as u64).rng())
+            .collect::<Vec<_>>();
+
+        if let Some(initializer) = &self.initializer {
+            let mut rng = init::stream(seeds);
+            let mut positions = initializer.initialize(self.swarm_size, &mut rng);
+            if let Some(v) = x_in {
+                positions[0] = v.clone();
+            }
+            let swarm = positions.into_iter().map(|mut position| {
+                if let Some(bounds) = &self.bounds {
+                    bounds.repair(&mut position, None, &mut rng);
+                }
+                Particle::new(position)
+            }).collect();
+            return (swarm, rngs)
+        }
+
+        let mut rng = seeds.rng();
         let dist = match self.x_range {
-            None => Uniform::new(-1., 1.),
+            None => Uniform::new(-T::ONE, T::ONE),
             Some((x_min, x_max)) => Uniform::new(x_min, x_max)
         };
 
         // Initialize swarm
-        let mut swarm: Vec<_> = (0..self.swarm_size).map(|i| {
-            let mut p = Particle::new(self.dims, &mut rng, &dist);
+        let swarm: Vec<_> = (0..self.swarm_size).map(|i| {
+            let mut p = Particle::new((&dist).sample_iter(&mut rng).take(self.dims).collect());
             // If we have an initial offset, set the best to the best score
             // and offset the rest of the population by x_in
             if let Some(v) = x_in {
                 if i == 0 {
-                    p.position.copy_from_slice(&v);
+                    p.position.copy_from_slice(v);
                 } else {
                     p.position.iter_mut().zip(v.iter()).for_each(|(pi, vi)| {
-                        *pi += vi;
+                        *pi += *vi;
                     });
                 }
             }
 
-            // Get the fitness for the initial position
-            p.evaluate(fit_fn);
+            if let Some(bounds) = &self.bounds {
+                bounds.repair(&mut p.position, None, &mut rng);
+            }
             p
         }).collect();
 
-        let mut fn_count = self.swarm_size;
+        (swarm, rngs)
+    }
 
-        // Generate an rng per swarm.  This guarantees reproducibility
-        let mut rngs = (&mut rng)
-            .sample_iter(Uniform::new(0, std::u64::MAX))
-            .take(self.swarm_size)
-            .map(|seed| rand::rngs::StdRng::seed_from_u64(seed))
-            .collect::<Vec<_>>();
- 
-        let mut global_best = vec![0.; self.dims];
+    /// Draws a single fresh position the way the swarm was laid out: from
+    /// the initializer, or uniformly within `x_range`, the bounds or
+    /// [-1, 1], whichever is set first
+    fn draw_position(&self, rng: &mut Stream) -> Vec<T> {
+        if let Some(initializer) = &self.initializer {
+            return initializer.initialize(1, rng).swap_remove(0)
+        }
 
-        while (fn_count + self.swarm_size) < total_fns {
-            let global_fit = PSO::get_best(&swarm, &mut global_best);
-            callback(global_fit, total_fns - fn_count);
+        let (lower, upper) = match (self.x_range, &self.bounds) {
+            (Some((x_min, x_max)), _) => (vec![x_min; self.dims], vec![x_max; self.dims]),
+            (None, Some(bounds)) => (bounds.lower.clone(), bounds.upper.clone()),
+            (None, None) => (vec![-T::ONE; self.dims], vec![T::ONE; self.dims])
+        };
+        lower.into_iter().zip(upper)
+            .map(|(l, u)| if l < u { Uniform::new(l, u).sample(rng) } else { l })
+            .collect()
+    }
 
-            swarm.par_iter_mut().zip(rngs.par_iter_mut()).for_each(|(p, lrng)| {
-                p.update(&global_best, self.w, self.c_1, self.c_2, lrng);
-                p.evaluate(fit_fn);
+    /// Whether the next batch rescores the personal bests
+    fn rescores(&self, state: &PsoState<T>) -> bool {
+        state.initialized && self.noise.as_ref()
+            .map(|n| n.reevaluates(state.ledger.generation()))
+            .unwrap_or(false)
+    }
+
+    /// Evaluations the next batch will cost, personal bests included
+    fn next_cost(&self, state: &PsoState<T>) -> usize {
+        let incumbents = if self.rescores(state) { state.swarm.len() } else { 0 };
+        state.sampling.cost(self.swarm_size) + incumbents
+    }
+}
+
+impl <T: Real> AskTell for PSO<T> {
+    type State = PsoState<T>;
+
+    fn init<TT: Termination<Vec<T>, T> + 'static>(
+        &self,
+        termination: TT,
+        seed: u64,
+        x_in: Option<&Vec<T>>
+    ) -> PsoState<T> {
+        let seeds = SeedSequence::new(seed);
+        let (swarm, rngs) = self.init_swarm(&seeds.spawn(0), x_in);
+        PsoState {
+            seeds,
+            x_in: x_in.cloned(),
+            swarm,
+            rngs,
+            sampling: Sampling::new(self.noise.as_ref()),
+            global_fit: T::MIN,
+            global_best: vec![T::ZERO; self.dims],
+            initialized: false,
+            pending: false,
+            ledger: Ledger::new(termination),
+            best_fit: T::MIN,
+            best: vec![T::ZERO; self.dims]
+        }
+    }
+
+    fn ask(&self, state: &mut PsoState<T>) -> Vec<Vec<T>> {
+        if self.is_done(state) {
+            return Vec::new()
+        }
+
+        if state.initialized && !state.pending {
+            let global_best = &state.global_best;
+            self.execution.for_each_mut(&mut state.swarm, &mut state.rngs, |_, p, lrng| {
+                p.update(global_best, self.w, self.c_1, self.c_2, self.bounds.as_ref(), lrng);
             });
-            fn_count += swarm.len();
         }
 
-        let global_fit = PSO::get_best(&swarm, &mut global_best);
+        // Personal bests are rescored alongside the new positions
+        let incumbents = if self.rescores(state) {
+            state.swarm.iter().map(|p| p.best_seen.clone()).enumerate().collect()
+        } else {
+            Vec::new()
+        };
 
-        (global_fit, global_best)
+        state.pending = true;
+        let positions = state.swarm.iter().map(|p| p.position.clone()).collect();
+        state.sampling.expand(positions, incumbents)
+    }
+
+    fn tell(&self, state: &mut PsoState<T>, fitnesses: Vec<T>) {
+        let evaluations = fitnesses.len();
+        let (estimates, rescored) = state.sampling.collapse(&fitnesses);
+        assert_eq!(estimates.len(), state.swarm.len(), "Expected a score per particle");
+        for (i, f) in rescored {
+            let p = &mut state.swarm[i];
+            p.best_estimate.push(f);
+            p.best_fitness = p.best_estimate.mean();
+        }
+
+        let pooled = if self.noise.is_some() {
+            pooled_variance(state.swarm.iter().map(|p| &p.best_estimate).chain(estimates.iter()))
+        } else {
+            None
+        };
+        for (p, e) in state.swarm.iter_mut().zip(estimates) {
+            let replaces = match &self.noise {
+                Some(noise) => state.sampling.replaces(noise, &e, &p.best_estimate, pooled),
+                None => e.mean() > p.best_fitness
+            };
+            p.evaluate(e, replaces);
+        }
+        if let Some(noise) = &self.noise {
+            state.sampling.adapt(noise);
+        }
+
+        // Initializers may draw extras, leaving only the fittest to carry on
+        if state.swarm.len() > self.swarm_size {
+            let fits: Vec<_> = state.swarm.iter().map(|p| p.best_fitness).collect();
+            let keep = fittest(&fits, &vec![T::ZERO; fits.len()], self.swarm_size);
+            state.swarm = init::select(&state.swarm, &keep);
+        }
+
+        state.ledger.record(evaluations);
+        state.initialized = true;
+        state.pending = false;
+        state.global_fit = PSO::get_best(&state.swarm, &mut state.global_best);
+        state.ledger.observe(state.global_fit, self.noise.is_some());
+
+        // With noise, the best is always the current best estimate
+        if self.noise.is_some() || state.global_fit > state.best_fit {
+            state.best_fit = state.global_fit;
+            state.best.copy_from_slice(&state.global_best);
+        }
+
+        let stats = self.stats(state);
+        state.ledger.check(&stats);
+    }
+
+    fn termination(&self, state: &PsoState<T>) -> Option<TerminationReason> {
+        // Never exceed the budget.  The initial swarm is always scored.
+        let cost = self.next_cost(state);
+        let exhausted = self.remaining(state).map(|r| r < cost).unwrap_or(false);
+        let stopped = state.ledger.stopped();
+        if stopped.is_none() && state.initialized && exhausted {
+            Some(TerminationReason::BudgetExhausted)
+        } else {
+            stopped
+        }
+    }
+
+    fn restart(&self, state: &mut PsoState<T>) {
+        let seeds = state.seeds.spawn(state.ledger.restarts() as u64 + 1);
+        let (swarm, rngs) = self.init_swarm(&seeds, state.x_in.as_ref());
+        state.swarm = swarm;
+        state.rngs = rngs;
+        state.sampling = Sampling::new(self.noise.as_ref());
+        state.global_fit = T::MIN;
+        state.initialized = false;
+        state.pending = false;
+        state.ledger.restart(RestartCause::Requested);
+    }
+
+    fn resample(&self, state: &mut PsoState<T>, idx: usize) -> Vec<T> {
+        // Personal bests, and positions with other samples already in, are
+        // rescored as they are
+        let idx = match state.sampling.slot(idx) {
+            None => return state.swarm[state.sampling.incumbent(idx)].best_seen.clone(),
+            Some(slot) if state.sampling.samples() > 1 => return state.swarm[slot].position.clone(),
+            Some(slot) => slot
+        };
+
+        // Scatter the particle to a fresh position, at rest
+        let rng = &mut state.rngs[idx];
+        let p = &mut state.swarm[idx];
+        p.position = self.draw_position(rng);
+        p.velocity.iter_mut().for_each(|vi| *vi = T::ZERO);
+        if let Some(bounds) = &self.bounds {
+            bounds.repair(&mut p.position, None, rng);
+        }
+        p.position.clone()
+    }
+
+    fn ledger<'a>(&self, state: &'a PsoState<T>) -> &'a Ledger<Vec<T>, T> {
+        &state.ledger
+    }
+
+    fn ledger_mut<'a>(&self, state: &'a mut PsoState<T>) -> &'a mut Ledger<Vec<T>, T> {
+        &mut state.ledger
+    }
+
+    fn execution(&self) -> &Execution {
+        &self.execution
+    }
+
+    fn best<'a>(&self, state: &'a PsoState<T>) -> (T, &'a Vec<T>) {
+        (state.best_fit, &state.best)
+    }
+
+    fn stats(&self, state: &PsoState<T>) -> GenerationStats<Vec<T>, T> {
+        let fitnesses: Vec<_> = state.swarm.iter().map(|p| p.fitness).collect();
+        let positions: Vec<_> = state.swarm.iter().map(|p| p.position.clone()).collect();
+        state.ledger.stats(
+            &fitnesses,
+            Some(diversity(&positions)),
+            (state.best_fit, state.best.clone()))
+    }
+
+    fn population(&self, state: &PsoState<T>) -> (Vec<Vec<T>>, Vec<T>) {
+        state.swarm.iter().map(|p| (p.position.clone(), p.fitness)).unzip()
+    }
+}
+
+impl <T: Real> Optimizer for PSO<T> {
+    type Stats = GenerationStats<Vec<T>, T>;
+    type Data = Vec<T>;
+    type Value = T;
+
+    fn fit<F, TT, FN, R>(
+        &self,
+        fit_fn: &F,
+        termination: TT,
+        seed: u64,
+        x_in: Option<&Vec<T>>,
+        callback: FN
+    ) -> OptimizationResult<Vec<T>, (), T>
+    where
+        F: Fitness<Data=Self::Data, Value=T>,
+        TT: Termination<Vec<T>, T> + 'static,
+        FN: FnMut(Self::Stats, Option<usize>) -> R,
+        R: Into<Control>
+    {
+        crate::run(self, fit_fn, termination, seed, x_in, callback)
     }
 }
 
@@ -171,6 +610,8 @@ impl Optimizer for PSO {
 mod test_pso {
     use super::*;
     use crate::exp::*;
+    use crate::bounds::Repair;
+    use crate::init::UniformInit;
 
     #[test]
     fn test_matyas() {
@@ -180,15 +621,180 @@ mod test_pso {
             w: 0.8,
             c_1: 0.5,
             c_2: 1.,
-            x_range: None
+            x_range: None,
+            initializer: None,
+            bounds: None,
+            execution: Execution::Global,
+            noise: None
+        };
+
+        let fit_fn = MatyasEnv(-10., 10.);
+        let result = opt.fit(&fit_fn, 10000, 2020, None,
+                             |_stats, _fns_remaining| {});
+        assert_eq!(result.fitness, 0.);
+        assert_eq!(result.candidate[0], 10.);
+        assert_eq!(result.candidate[1], -10.);
+    }
+
+    #[test]
+    fn test_bounds() {
+        let bounds = Bounds::uniform(2, -5., 5., Repair::Clip);
+        let opt = PSO {
+            dims: 2,
+            swarm_size: 30,
+            w: 0.8,
+            c_1: 0.5,
+            c_2: 1.,
+            x_range: Some((-20., 20.)),
+            initializer: None,
+            bounds: Some(bounds.clone()),
+            execution: Execution::Global,
+            noise: None
+        };
+
+        let fit_fn = MatyasEnv(-10., 10.);
+        let result = opt.fit(&fit_fn, 3000, 2020, None, |_stats, _fns_remaining| {});
+        assert!(result.population.iter().all(|p| bounds.contains(p)));
+        assert_eq!(result.candidate, vec![5., -5.]);
+    }
+
+    #[test]
+    fn test_resample() {
+        let resampled = |opt: PSO| {
+            let mut state = opt.init(1000, 2020, None);
+            let batch = opt.ask(&mut state);
+            (0..batch.len()).map(|i| opt.resample(&mut state, i)).collect::<Vec<_>>()
+        };
+
+        // Failed particles are drawn again within the bounds' extent
+        let bounds = Bounds::uniform(2, 5., 6., Repair::Clip);
+        let positions = resampled(PSO::builder(2).bounds(bounds.clone()).build().unwrap());
+        assert!(positions.iter().all(|p| bounds.contains(p)));
+        assert!(positions.iter().any(|p| p.iter().any(|xi| *xi > 5. && *xi < 6.)));
+
+        // Or from the initializer the swarm was laid out with
+        let region = Bounds::uniform(2, 10., 11., Repair::Clip);
+        let positions = resampled(PSO::builder(2).initializer(UniformInit(region.clone())).build().unwrap());
+        assert!(positions.iter().all(|p| region.contains(p)));
+    }
+
+    #[test]
+    fn test_callback_stop() {
+        let opt = PSO {
+            dims: 2,
+            swarm_size: 30,
+            w: 0.8,
+            c_1: 0.5,
+            c_2: 1.,
+            x_range: None,
+            initializer: None,
+            bounds: None,
+            execution: Execution::Global,
+            noise: None
+        };
+
+        let fit_fn = MatyasEnv(-10., 10.);
+        let mut evaluations = 0;
+        let result = opt.fit(&fit_fn, 10000, 2020, None, |stats, _fns_remaining| {
+            evaluations = stats.evaluations;
+            if stats.best > -1e-3 { Control::Stop } else { Control::Continue }
+        });
+        assert_eq!(result.termination, TerminationReason::Callback);
+        assert!(result.fitness > -1e-3);
+        assert!(evaluations < 10000);
+    }
+
+    #[test]
+    fn test_execution_backends() {
+        let mut opt = PSO {
+            dims: 2,
+            swarm_size: 30,
+            w: 0.8,
+            c_1: 0.5,
+            c_2: 1.,
+            x_range: None,
+            initializer: None,
+            bounds: None,
+            execution: Execution::Global,
+            noise: None
+        };
+
+        let fit_fn = MatyasEnv(-10., 10.);
+        let expected = opt.fit(&fit_fn, 3000, 2020, None, |_stats, _fns_remaining| {});
+        for execution in [Execution::threads(1), Execution::threads(3), Execution::Sequential] {
+            opt.execution = execution;
+            let result = opt.fit(&fit_fn, 3000, 2020, None, |_stats, _fns_remaining| {});
+            assert_eq!(result.population, expected.population);
+            assert_eq!(result.candidate, expected.candidate);
+        }
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_resume() {
+        use crate::checkpoint::*;
+
+        let opt = PSO {
+            dims: 2,
+            swarm_size: 30,
+            w: 0.8,
+            c_1: 0.5,
+            c_2: 1.,
+            x_range: None,
+            initializer: None,
+            bounds: None,
+            execution: Execution::Global,
+            noise: None
         };
 
         let fit_fn = MatyasEnv(-10., 10.);
-        let (fit, results) = opt.fit(&fit_fn, 10000, 2020, None, 
-                                    |_best_fit, _fns_remaining| {});
-        assert_eq!(fit, 0.);
-        assert_eq!(results[0], 10.);
-        assert_eq!(results[1], -10.);
+        let path = std::env::temp_dir().join("darkopt_test_resume_pso.ckpt");
+        let policy = CheckpointPolicy::new(&path, Period::Generations(7));
+        let expected = opt.fit(&fit_fn, 3000, 2020, None, |_stats, _fns_remaining| {});
+
+        opt.fit_checkpointed(&fit_fn, 3000, 2020, None, &policy, |stats, _| {
+            if stats.generation == 50 { Control::Stop } else { Control::Continue }
+        }).unwrap();
+        let result = opt.resume(&fit_fn, 3000, &policy, |_stats, _fns_remaining| {}).unwrap();
+        assert_eq!(result.population, expected.population);
+        assert_eq!(result.population_fitness, expected.population_fitness);
+        assert_eq!(result.candidate, expected.candidate);
+        assert_eq!(result.evaluations, expected.evaluations);
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_f64() {
+        let opt = PSO::builder(2).x_range(0., 5.).build().unwrap();
+
+        // The nearest f32 is over 1e-7 away from the centre
+        let result = opt.fit(&FineSphereEnv(3.000_000_123), 10000, 2020, None, |_stats, _fns_remaining| {});
+        assert!(result.candidate.iter().all(|xi| (xi - 3.000_000_123).abs() < 1e-9), "{:?}", result.candidate);
     }
 
+    #[test]
+    fn test_noise() {
+        let builder = || PSO::builder(2).execution(Execution::Sequential);
+        let env = MatyasEnv(-1., 1.);
+        let fit = |opt: &PSO| {
+            let noisy = NoisyEnv::new(MatyasEnv(-1., 1.), 1., 2020);
+            let result = opt.fit(&noisy, 6000, 2020, None, |_stats, _fns_remaining| {});
+            (result.fitness, env.score(&result.candidate))
+        };
+
+        // Personal bests hold on to their luckiest draw unless rescored
+        let (reported, actual) = fit(&builder().build().unwrap());
+        assert!(reported - actual > 1.);
+        let (reported, actual) = fit(&builder().noise(Noise::default()).build().unwrap());
+        assert!((reported - actual).abs() < 1.);
+        assert!(actual > -0.5);
+
+        // Rescored personal bests come out of the budget too
+        let noise = Noise { samples: 2, max_samples: 2, reevaluate: 1, ..Noise::default() };
+        let opt = builder().swarm_size(10).noise(noise).build().unwrap();
+        for budget in [1000, 1050] {
+            let result = opt.fit(&env, budget, 2020, None, |_stats, _fns_remaining| {});
+            assert!(result.evaluations <= budget, "{}: {}", budget, result.evaluations);
+        }
+    }
 }
@@ -0,0 +1,109 @@
+use std::cmp::Ordering;
+use std::fmt::{Debug,Display};
+use std::iter::Sum;
+use std::ops::{Add,AddAssign,Div,DivAssign,Mul,MulAssign,Neg,Sub,SubAssign};
+
+use float_ord::FloatOrd;
+use rand::Rng;
+use rand_distr::{Distribution,StandardNormal};
+use rand_distr::uniform::SampleUniform;
+
+#[cfg(feature = "serde")]
+use serde::Serialize;
+#[cfg(feature = "serde")]
+use serde::de::DeserializeOwned;
+
+/// Floating point precision for fitness values and continuous candidates.
+/// Implemented for `f32`, the default everywhere, and `f64`.
+pub trait Real: Copy + Debug + Display + Default + PartialOrd + Send + Sync + 'static
+    + Add<Output=Self> + Sub<Output=Self> + Mul<Output=Self> + Div<Output=Self> + Neg<Output=Self>
+    + AddAssign + SubAssign + MulAssign + DivAssign + Sum + SampleUniform + Serial
+{
+    const ZERO: Self;
+    const ONE: Self;
+    const INFINITY: Self;
+    const NEG_INFINITY: Self;
+    const MIN: Self;
+    const NAN: Self;
+
+    fn from_f64(x: f64) -> Self;
+
+    fn to_f64(self) -> f64;
+
+    fn from_f32(x: f32) -> Self {
+        Self::from_f64(x as f64)
+    }
+
+    fn from_usize(n: usize) -> Self {
+        Self::from_f64(n as f64)
+    }
+
+    fn abs(self) -> Self;
+    fn sqrt(self) -> Self;
+    fn powi(self, n: i32) -> Self;
+    fn powf(self, n: Self) -> Self;
+    fn max(self, other: Self) -> Self;
+    fn min(self, other: Self) -> Self;
+    fn rem_euclid(self, rhs: Self) -> Self;
+    fn is_finite(self) -> bool;
+    fn is_nan(self) -> bool;
+
+    /// Bit pattern, widened to 64 bits
+    fn to_bits(self) -> u64;
+
+    /// Total order matching `FloatOrd`, for sorting and picking the best
+    fn cmp_float(&self, other: &Self) -> Ordering;
+
+    /// Draws from the standard normal
+    fn standard_normal<R: Rng + ?Sized>(rng: &mut R) -> Self;
+}
+
+/// Serde bounds, which only apply with the serde feature on
+#[cfg(feature = "serde")]
+pub trait Serial: Serialize + DeserializeOwned {}
+
+#[cfg(feature = "serde")]
+impl <T: Serialize + DeserializeOwned> Serial for T {}
+
+#[cfg(not(feature = "serde"))]
+pub trait Serial {}
+
+#[cfg(not(feature = "serde"))]
+impl <T> Serial for T {}
+
+macro_rules! impl_real {
+    ($t:ident) => {
+        impl Real for $t {
+            const ZERO: Self = 0.;
+            const ONE: Self = 1.;
+            const INFINITY: Self = $t::INFINITY;
+            const NEG_INFINITY: Self = $t::NEG_INFINITY;
+            const MIN: Self = $t::MIN;
+            const NAN: Self = $t::NAN;
+
+            fn from_f64(x: f64) -> Self { x as $t }
+            fn to_f64(self) -> f64 { self as f64 }
+            fn abs(self) -> Self { $t::abs(self) }
+            fn sqrt(self) -> Self { $t::sqrt(self) }
+            fn powi(self, n: i32) -> Self { $t::powi(self, n) }
+            fn powf(self, n: Self) -> Self { $t::powf(self, n) }
+            fn max(self, other: Self) -> Self { $t::max(self, other) }
+            fn min(self, other: Self) -> Self { $t::min(self, other) }
+            fn rem_euclid(self, rhs: Self) -> Self { $t::rem_euclid(self, rhs) }
+            fn is_finite(self) -> bool { $t::is_finite(self) }
+            fn is_nan(self) -> bool { $t::is_nan(self) }
+            fn to_bits(self) -> u64 { $t::to_bits(self) as u64 }
+
+            fn cmp_float(&self, other: &Self) -> Ordering {
+                FloatOrd(*self).cmp(&FloatOrd(*other))
+            }
+
+            fn standard_normal<R: Rng + ?Sized>(rng: &mut R) -> Self {
+                StandardNormal.sample(rng)
+            }
+        }
+    }
+}
+
+impl_real!(f32);
+impl_real!(f64);
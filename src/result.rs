@@ -0,0 +1,104 @@
+#[cfg(feature = "serde")]
+use serde::{Deserialize,Serialize};
+
+use crate::{GenerationStats,Real};
+
+/// Why a run stopped
+#[derive(Clone,Copy,Debug,PartialEq,Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize,Deserialize))]
+pub enum TerminationReason {
+    /// The function evaluation budget has been spent
+    BudgetExhausted,
+
+    /// The wall clock time limit has been reached
+    TimeLimit,
+
+    /// The best fitness reached the target
+    TargetReached,
+
+    /// The best fitness stopped improving
+    Stagnation,
+
+    /// The population collapsed onto a single point
+    DiversityCollapse,
+
+    /// The callback asked the run to stop
+    Callback
+}
+
+/// Why a population was restarted
+#[derive(Clone,Copy,Debug,PartialEq,Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize,Deserialize))]
+pub enum RestartCause {
+    /// The best fitness stopped improving, such as with `restart_on_stale`
+    Stale,
+
+    /// The restart was asked for, by the callback or through `AskTell::restart`
+    Requested
+}
+
+/// Summary of a single pass of a run: from a fresh population up to the next
+/// restart or the end of the run
+#[derive(Clone,Debug,PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize,Deserialize))]
+pub struct Pass<V = f32> {
+    /// Why the pass started.  The first pass of a run has no cause.
+    pub cause: Option<RestartCause>,
+
+    /// Best feasible fitness the pass found
+    pub best: V,
+
+    /// Function evaluations the pass spent
+    pub evaluations: usize,
+
+    /// Batches the pass scored, including its initial population
+    pub batches: usize
+}
+
+impl <V: Real> Pass<V> {
+
+    pub(crate) fn new(cause: Option<RestartCause>) -> Self {
+        Pass { cause, best: V::NEG_INFINITY, evaluations: 0, batches: 0 }
+    }
+}
+
+/// Outcome of a call to `Optimizer::fit`.  `E` is the error type of a
+/// `FallibleFitness` and `V` the precision of its fitness values.
+#[derive(Clone,Debug)]
+pub struct OptimizationResult<D, E = (), V = f32> {
+    /// Best fitness found
+    pub fitness: V,
+
+    /// Candidate with the best fitness
+    pub candidate: D,
+
+    /// Function evaluations actually spent
+    pub evaluations: usize,
+
+    /// Generations completed after the initial population
+    pub generations: usize,
+
+    /// Why the run stopped
+    pub termination: TerminationReason,
+
+    /// Number of times the population was restarted
+    pub restarts: usize,
+
+    /// Summary of each pass, in order.  There is one more than there are restarts.
+    pub passes: Vec<Pass<V>>,
+
+    /// Evaluations which failed to produce a usable fitness, including NaNs
+    pub failures: usize,
+
+    /// Errors from failed evaluations which didn't abort the run
+    pub errors: Vec<E>,
+
+    /// Final population
+    pub population: Vec<D>,
+
+    /// Fitness of each member of the final population
+    pub population_fitness: Vec<V>,
+
+    /// Summary of every generation, in order
+    pub history: Vec<GenerationStats<(), V>>
+}
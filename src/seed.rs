@@ -0,0 +1,287 @@
+use rand::{RngCore,SeedableRng};
+use rand_chacha::ChaCha20Rng;
+#[cfg(feature = "serde")]
+use serde::{Deserialize,Deserializer,Serialize,Serializer};
+
+const POOL_SIZE: usize = 4;
+const XSHIFT: u32 = 16;
+const INIT_A: u32 = 0x43b0_d7e5;
+const MULT_A: u32 = 0x931e_8875;
+const INIT_B: u32 = 0x8b51_f9dd;
+const MULT_B: u32 = 0x58f3_8ded;
+const MIX_MULT_L: u32 = 0xca01_f9dd;
+const MIX_MULT_R: u32 = 0x4973_f715;
+
+/// Derives independent RNG streams from a single seed, after NumPy's
+/// SeedSequence.  Each child is addressed by its path of spawn indices, so
+/// streams for a run, a generation or an individual are well mixed and never
+/// overlap, however they're scheduled.
+#[derive(Clone,Debug,PartialEq,Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize,Deserialize))]
+pub struct SeedSequence {
+    entropy: u64,
+    key: Vec<u64>,
+    pool: [u32; POOL_SIZE]
+}
+
+impl SeedSequence {
+
+    pub fn new(entropy: u64) -> Self {
+        SeedSequence::with_key(entropy, Vec::new())
+    }
+
+    fn with_key(entropy: u64, key: Vec<u64>) -> Self {
+        let mut words = uint32_words(entropy);
+        if !key.is_empty() && words.len() < POOL_SIZE {
+            // Keep the key from aliasing with entropy
+            words.resize(POOL_SIZE, 0);
+        }
+        key.iter().for_each(|k| words.extend(uint32_words(*k)));
+        SeedSequence { entropy, key, pool: mix_entropy(&words) }
+    }
+
+    /// The `i`th child sequence
+    pub fn spawn(&self, i: u64) -> Self {
+        let mut key = self.key.clone();
+        key.push(i);
+        SeedSequence::with_key(self.entropy, key)
+    }
+
+    /// Path of spawn indices from the root
+    pub fn key(&self) -> &[u64] {
+        &self.key
+    }
+
+    /// Expands the pool into `n` words of RNG state
+    pub fn generate_state(&self, n: usize) -> Vec<u32> {
+        let mut hash_const = INIT_B;
+        (0..n).map(|i| {
+            let mut value = self.pool[i % POOL_SIZE] ^ hash_const;
+            hash_const = hash_const.wrapping_mul(MULT_B);
+            value = value.wrapping_mul(hash_const);
+            value ^ (value >> XSHIFT)
+        }).collect()
+    }
+
+    /// An RNG seeded from this sequence
+    pub fn rng(&self) -> Stream {
+        let mut seed = [0; 32];
+        let words = self.generate_state(seed.len() / 4);
+        seed.chunks_mut(4).zip(words)
+            .for_each(|(chunk, w)| chunk.copy_from_slice(&w.to_le_bytes()));
+        Stream::from_seed(seed)
+    }
+}
+
+/// Splits an integer into little-endian 32 bit words, as NumPy coerces
+/// entropy and spawn keys: no more words than it needs, and one for zero
+fn uint32_words(mut n: u64) -> Vec<u32> {
+    let mut words = vec![n as u32];
+    n >>= 32;
+    while n > 0 {
+        words.push(n as u32);
+        n >>= 32;
+    }
+    words
+}
+
+/// Mixes entropy words into a pool, following NumPy's
+/// `SeedSequence.mix_entropy`
+fn mix_entropy(words: &[u32]) -> [u32; POOL_SIZE] {
+    let mut hash_const = INIT_A;
+    let mut hashmix = |value: u32| {
+        let mut value = value ^ hash_const;
+        hash_const = hash_const.wrapping_mul(MULT_A);
+        value = value.wrapping_mul(hash_const);
+        value ^ (value >> XSHIFT)
+    };
+    let mix = |x: u32, y: u32| {
+        let result = MIX_MULT_L.wrapping_mul(x).wrapping_sub(MIX_MULT_R.wrapping_mul(y));
+        result ^ (result >> XSHIFT)
+    };
+
+    // Add in the entropy up to the pool size
+    let mut pool = [0; POOL_SIZE];
+    for (i, p) in pool.iter_mut().enumerate() {
+        *p = hashmix(words.get(i).cloned().unwrap_or(0));
+    }
+
+    // Mix all bits together so late bits can affect earlier ones
+    for src in 0..POOL_SIZE {
+        for dst in 0..POOL_SIZE {
+            if src != dst {
+                pool[dst] = mix(pool[dst], hashmix(pool[src]));
+            }
+        }
+    }
+
+    // Mix each remaining word into every word of the pool
+    for w in words.iter().skip(POOL_SIZE) {
+        for p in pool.iter_mut() {
+            *p = mix(*p, hashmix(*w));
+        }
+    }
+    pool
+}
+
+/// ChaCha20, the same generator as `StdRng`, which remembers its seed so its
+/// position in the stream can be checkpointed and restored.
+#[derive(Clone,Debug)]
+pub struct Stream {
+    seed: [u8; 32],
+    rng: ChaCha20Rng
+}
+
+impl Stream {
+
+    /// Seed the stream started from
+    pub fn seed(&self) -> [u8; 32] {
+        self.seed
+    }
+}
+
+impl SeedableRng for Stream {
+    type Seed = [u8; 32];
+
+    fn from_seed(seed: [u8; 32]) -> Self {
+        Stream { seed, rng: ChaCha20Rng::from_seed(seed) }
+    }
+}
+
+impl RngCore for Stream {
+    fn next_u32(&mut self) -> u32 {
+        self.rng.next_u32()
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        self.rng.next_u64()
+    }
+
+    fn fill_bytes(&mut self, dest: &mut [u8]) {
+        self.rng.fill_bytes(dest)
+    }
+
+    fn try_fill_bytes(&mut self, dest: &mut [u8]) -> Result<(), rand::Error> {
+        self.rng.try_fill_bytes(dest)
+    }
+}
+
+#[cfg(feature = "serde")]
+impl Serialize for Stream {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        (self.seed, self.rng.get_word_pos()).serialize(serializer)
+    }
+}
+
+#[cfg(feature = "serde")]
+impl <'de> Deserialize<'de> for Stream {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let (seed, word_pos) = <([u8; 32], u128)>::deserialize(deserializer)?;
+        let mut stream = Stream::from_seed(seed);
+        stream.rng.set_word_pos(word_pos);
+        Ok(stream)
+    }
+}
+
+#[cfg(test)]
+mod test_seed {
+    use rand::Rng;
+    use rand_distr::{Normal,StandardNormal};
+
+    use super::*;
+    use crate::{Execution,Optimizer};
+    use crate::de::*;
+    use crate::exp::*;
+    use crate::ga::fastga::FastGA;
+    use crate::ga::genome::Continuous;
+    use crate::ga::mutator::ContinuousMutator;
+    use crate::ga::crossover::{CrossoverType as GaCrossover,Linear};
+    use crate::ga::selector::Tournament;
+
+    fn thread_counts() -> Vec<Execution> {
+        vec![Execution::threads(1), Execution::threads(2), Execution::threads(5), Execution::Sequential]
+    }
+
+    #[test]
+    fn test_streams() {
+        let root = SeedSequence::new(2020);
+        assert_eq!(root.spawn(3).spawn(1).key(), &[3, 1]);
+        assert_eq!(root.spawn(3).rng().gen::<u64>(), root.spawn(3).rng().gen::<u64>());
+
+        // Streams draw exactly what StdRng would from the same seed
+        let mut seed = [0; 32];
+        seed[0] = 7;
+        let mut std_rng = rand::rngs::StdRng::from_seed(seed);
+        let mut stream = Stream::from_seed(seed);
+        assert!((0..100).all(|_| std_rng.gen::<u64>() == stream.gen::<u64>()));
+
+        // Neighbouring seeds, siblings and the parent all get distinct streams
+        let draws: Vec<u64> = vec![
+            root.rng().gen(),
+            SeedSequence::new(2021).rng().gen(),
+            root.spawn(0).rng().gen(),
+            root.spawn(1).rng().gen(),
+            root.spawn(0).spawn(0).rng().gen(),
+            root.spawn(0).spawn(1).rng().gen()
+        ];
+        for (i, a) in draws.iter().enumerate() {
+            assert!(draws.iter().skip(i + 1).all(|b| a != b));
+        }
+    }
+
+    #[test]
+    fn test_numpy_mixing() {
+        // NumPy's reference data for a four word entropy pool
+        let pool = mix_entropy(&[3735928559, 195939070, 229505742, 305419896]);
+        let seq = SeedSequence { entropy: 0, key: Vec::new(), pool };
+        assert_eq!(seq.generate_state(4), vec![3914649087, 576849849, 3593928901, 2229911004]);
+
+        // Integers take as few words as they need
+        assert_eq!(uint32_words(0), vec![0]);
+        assert_eq!(uint32_words(2020), vec![2020]);
+        assert_eq!(uint32_words((1 << 40) + 5), vec![5, 256]);
+
+        // Spawn keys follow the entropy padded out to the pool size
+        let child = SeedSequence::new(2020).spawn(3).spawn(1 << 40);
+        assert_eq!(child.pool, mix_entropy(&[2020, 0, 0, 0, 3, 0, 256]));
+    }
+
+    #[test]
+    fn test_thread_count_independent() {
+        let fit_fn = RastriginEnv { dims: 4 };
+
+        // Restarts and polishing exercise every stream DE derives
+        let results: Vec<_> = thread_counts().into_iter().map(|execution| {
+            let de = DePlus::builder(4, StandardNormal)
+                .lambda(20)
+                .f(0.1, 1.)
+                .polish_on_stale(3)
+                .restart_on_stale(6)
+                .execution(execution)
+                .build().unwrap();
+            de.fit(&fit_fn, 4000, 2020, None, |_stats, _fns_remaining| {})
+        }).collect();
+        assert!(results[0].restarts > 0);
+        for r in results.iter().skip(1) {
+            assert_eq!(r.population, results[0].population);
+            assert_eq!(r.fitness, results[0].fitness);
+        }
+
+        let results: Vec<_> = thread_counts().into_iter().map(|execution| {
+            let mut ga: FastGA<_,_,_,_,Vec<f32>> = FastGA::new(
+                40,
+                true,
+                1.,
+                Continuous::new(4, Normal::new(0., 1f32).unwrap()),
+                ContinuousMutator::new(Normal::new(0., 1f32).unwrap()),
+                Linear::new(GaCrossover::TwoPoint),
+                Tournament(4));
+            ga.execution = execution;
+            ga.fit(&fit_fn, 4000, 2020, None, |_stats, _fns_remaining| {})
+        }).collect();
+        for r in results.iter().skip(1) {
+            assert_eq!(r.population, results[0].population);
+            assert_eq!(r.fitness, results[0].fitness);
+        }
+    }
+}
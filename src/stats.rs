@@ -0,0 +1,171 @@
+use std::time::Duration;
+
+#[cfg(feature = "serde")]
+use serde::{Deserialize,Serialize};
+
+use crate::objective::Direction;
+use crate::real::Real;
+use crate::result::RestartCause;
+
+/// Summary of a population, emitted after every generation
+#[derive(Clone,Debug)]
+#[cfg_attr(feature = "serde", derive(Serialize,Deserialize))]
+pub struct GenerationStats<D, V = f32> {
+    /// Generation index.  The initial population is generation 0.
+    pub generation: usize,
+
+    /// Function evaluations used so far
+    pub evaluations: usize,
+
+    /// Evaluations so far which failed to produce a usable fitness
+    pub failures: usize,
+
+    /// Times the population has been restarted so far
+    pub restarts: usize,
+
+    /// Why the population was restarted, on the first generation of a new pass
+    pub restarted: Option<RestartCause>,
+
+    /// Which way the fitness values point.  Optimizers report maximized
+    /// values; `fit` converts them to the objective's own direction.
+    pub direction: Direction,
+
+    /// Best fitness seen so far
+    pub best: V,
+
+    /// Mean fitness of the current population
+    pub mean: V,
+
+    /// Median fitness of the current population
+    pub median: V,
+
+    /// Worst fitness in the current population
+    pub worst: V,
+
+    /// Spread of the current population, if the encoding has a notion of distance
+    pub diversity: Option<f32>,
+
+    /// Best candidate seen so far
+    pub best_candidate: D,
+
+    /// Wall clock time since the run started
+    pub elapsed: Duration
+}
+
+impl <D, V: Real> GenerationStats<D, V> {
+
+    /// Summarizes the fitnesses of a population.  Best is tracked separately
+    /// since optimizers keep the best candidate seen across the whole run.
+    /// Failed evaluations, scored as non-finite values, are left out.
+    pub(crate) fn new(
+        generation: usize,
+        evaluations: usize,
+        fitnesses: &[V],
+        diversity: Option<f32>,
+        best: (V, D),
+        elapsed: Duration
+    ) -> Self {
+        let mut sorted: Vec<_> = fitnesses.iter()
+            .filter(|f| f.is_finite())
+            .cloned()
+            .collect();
+        sorted.sort_by(V::cmp_float);
+
+        let n = sorted.len();
+        let (mean, median, worst) = if n == 0 {
+            (V::NAN, V::NAN, V::NAN)
+        } else {
+            let mean = sorted.iter().cloned().sum::<V>() / V::from_usize(n);
+            let median = if n % 2 == 1 {
+                sorted[n / 2]
+            } else {
+                (sorted[n / 2 - 1] + sorted[n / 2]) / V::from_f64(2.)
+            };
+            (mean, median, sorted[0])
+        };
+
+        GenerationStats {
+            generation,
+            evaluations,
+            failures: 0,
+            restarts: 0,
+            restarted: None,
+            direction: Direction::Maximize,
+            best: best.0,
+            mean,
+            median,
+            worst,
+            diversity,
+            best_candidate: best.1,
+            elapsed
+        }
+    }
+
+    /// Drops the best candidate, keeping only the summary numbers
+    pub fn summary(&self) -> GenerationStats<(), V> {
+        GenerationStats {
+            generation: self.generation,
+            evaluations: self.evaluations,
+            failures: self.failures,
+            restarts: self.restarts,
+            restarted: self.restarted,
+            direction: self.direction,
+            best: self.best,
+            mean: self.mean,
+            median: self.median,
+            worst: self.worst,
+            diversity: self.diversity,
+            best_candidate: (),
+            elapsed: self.elapsed
+        }
+    }
+}
+
+/// Mean euclidean distance of each member from the population centroid
+pub fn diversity<T: Real>(population: &[Vec<T>]) -> f32 {
+    if population.is_empty() {
+        return 0.
+    }
+
+    let n = T::from_usize(population.len());
+    let mut centroid = vec![T::ZERO; population[0].len()];
+    population.iter().for_each(|p| {
+        centroid.iter_mut().zip(p.iter()).for_each(|(ci, pi)| *ci += *pi / n);
+    });
+
+    let d = population.iter().map(|p| {
+        p.iter().zip(centroid.iter())
+            .map(|(pi, ci)| (*pi - *ci).powi(2))
+            .sum::<T>()
+            .sqrt()
+    }).sum::<T>() / n;
+    d.to_f64() as f32
+}
+
+#[cfg(test)]
+mod test_stats {
+    use super::*;
+
+    #[test]
+    fn test_summary() {
+        let stats = GenerationStats::new(
+            3, 40, &[1., -4., 2., 0.], None, (2., ()), Duration::from_secs(1));
+
+        assert_eq!(stats.best, 2.);
+        assert_eq!(stats.mean, -0.25);
+        assert_eq!(stats.median, 0.5);
+        assert_eq!(stats.worst, -4.);
+
+        let stats = GenerationStats::new(
+            0, 3, &[1., f32::NEG_INFINITY, 3.], None, (3., ()), Duration::from_secs(1));
+        assert_eq!(stats.mean, 2.);
+        assert_eq!(stats.worst, 1.);
+    }
+
+    #[test]
+    fn test_diversity() {
+        assert_eq!(diversity(&[vec![1f32, 1.], vec![1., 1.]]), 0.);
+        assert_eq!(diversity(&[vec![0f32, 1.], vec![0., -1.]]), 1.);
+        assert_eq!(diversity(&[vec![0f64, 1.], vec![0., -1.]]), 1.);
+    }
+}
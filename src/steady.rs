@@ -0,0 +1,574 @@
+use std::collections::VecDeque;
+use std::panic::{self,AssertUnwindSafe};
+use std::sync::mpsc::{self,RecvTimeoutError,TryRecvError};
+use std::time::Duration;
+
+use rayon::Yield;
+
+#[cfg(feature = "serde")]
+use serde::{Deserialize,Serialize};
+
+use crate::{AskTell,Control,Direction,Fitness,OptimizationResult,Real,Termination,TerminationReason};
+use crate::config::{ConfigError,check};
+use crate::constraints::ConstrainedFitness;
+use crate::fallible::{FailurePolicy,FallibleFitness};
+use crate::objective::Oriented;
+
+/// How a population takes in finished evaluations
+#[derive(Clone,Copy,Debug,PartialEq,Eq,Default)]
+#[cfg_attr(feature = "serde", derive(Serialize,Deserialize))]
+pub enum UpdateMode {
+    /// Each generation is scored as a batch and only replaces the population
+    /// once its slowest evaluation is in
+    #[default]
+    Generational,
+
+    /// Each evaluation updates the population as soon as it finishes, and a
+    /// new candidate takes its worker straight away.  Stats are reported
+    /// after every population's worth of evaluations.  Results depend on the
+    /// order evaluations finish in, so only sequential runs are reproducible.
+    ///
+    /// `fit`, `fit_fallible` and `fit_constrained` run steady-state, while
+    /// ask/tell stays generational.  Checkpointed runs aren't supported and
+    /// fail with `InvalidInput`.
+    SteadyState
+}
+
+/// Which member a steady-state GA child takes the place of.  The child only
+/// moves in if it's at least as fit.
+#[derive(Clone,Copy,Debug,PartialEq,Eq,Default)]
+#[cfg_attr(feature = "serde", derive(Serialize,Deserialize))]
+pub enum Displacement {
+    /// The least fit member
+    #[default]
+    Worst,
+
+    /// The loser of a tournament between this many random members.  With
+    /// elitism, the best member never enters.
+    Tournament(usize)
+}
+
+impl Displacement {
+
+    pub fn validate(&self) -> Result<(), ConfigError> {
+        if let Displacement::Tournament(k) = self {
+            check(*k > 0, "displacement", "tournament needs at least one entrant")?;
+        }
+        Ok(())
+    }
+}
+
+/// What a candidate was handed out for, given back along with its fitness
+#[derive(Clone,Copy,Debug)]
+pub(crate) struct Ticket {
+    /// Pass it was handed out in.  Results from before a restart are dropped.
+    pub pass: usize,
+
+    /// Member it scores, or a trial's target
+    pub slot: usize,
+
+    pub kind: Kind
+}
+
+#[derive(Clone,Copy,Debug,PartialEq)]
+pub(crate) enum Kind {
+    /// Scores the member in its slot as it is
+    Member,
+
+    /// A DE trial, which competes with whatever holds its target's slot when
+    /// it comes back.  Random perturbations replace it outright; adaptive
+    /// trials carry the F and CR they were bred with.
+    Trial { forced: bool, params: Option<(f32, f32)> },
+
+    /// A GA child, which finds its own place
+    Child
+}
+
+/// Steady-state bookkeeping, kept alongside the population
+#[derive(Clone,Debug,Default)]
+pub(crate) struct Slots {
+    /// Members waiting to be scored as they are
+    pub queue: VecDeque<usize>,
+
+    /// Whether each member's fitness is in
+    pub scored: Vec<bool>,
+
+    /// Next target, cycling round the population
+    pub cursor: usize,
+
+    /// Evaluations folded in since the last generation was recorded
+    pub since: usize,
+
+    /// Candidates bred so far.  Each gets its own stream.
+    pub bred: u64,
+
+    /// Adaptive DE's successful trials since the last generation
+    pub successes: Vec<(f32, f32, f64)>
+}
+
+impl Slots {
+
+    /// Every member of a fresh population waits to be scored
+    pub fn new(n: usize) -> Self {
+        Slots { queue: (0..n).collect(), scored: vec![false; n], ..Slots::default() }
+    }
+
+    /// Sends every member but `keep` back to be scored again
+    pub fn rescore_all_but(&mut self, keep: usize) {
+        for i in (0..self.scored.len()).filter(|i| *i != keep) {
+            if self.scored[i] {
+                self.scored[i] = false;
+                self.queue.push_back(i);
+            }
+        }
+    }
+
+    /// Next scored member, cycling round the population
+    pub fn next_target(&mut self) -> Option<usize> {
+        let n = self.scored.len();
+        for _ in 0..n {
+            let i = self.cursor % n;
+            self.cursor = (i + 1) % n;
+            if self.scored[i] {
+                return Some(i)
+            }
+        }
+        None
+    }
+
+    /// Counts an evaluation folded in, returning whether a generation's worth
+    /// of `n` are now in
+    pub fn tally(&mut self, n: usize) -> bool {
+        self.since += 1;
+        if self.since >= n {
+            self.since = 0;
+            true
+        } else {
+            false
+        }
+    }
+}
+
+/// Optimizers which can fold each evaluation in as soon as it finishes
+pub(crate) trait SteadyState: AskTell {
+
+    /// Switches a freshly initialized state over to steady-state updates
+    fn begin(&self, state: &mut Self::State);
+
+    /// Hands out the next candidate to score, if one can be had yet
+    fn propose(&self, state: &mut Self::State) -> Option<(Ticket, Self::Data)>;
+
+    /// Draws a fresh candidate in place of one which failed, if its ticket
+    /// can take one.  Only members can; a failed trial or child just loses.
+    fn redraw(&self, state: &mut Self::State, ticket: Ticket) -> Option<Self::Data>;
+
+    /// Folds a finished evaluation into the population, returning whether it
+    /// completed a generation's worth
+    fn accept(
+        &self,
+        state: &mut Self::State,
+        ticket: Ticket,
+        candidate: Self::Data,
+        fitness: Self::Value,
+        violation: Self::Value
+    ) -> bool;
+}
+
+/// What a worker hands back for a candidate: its score or why it failed, its
+/// constraint violation and whether the score came free
+pub(crate) type Evaluation<V, Er> = (Result<V, Er>, V, bool);
+
+/// Runs a steady-state optimizer to completion, like `crate::run`
+pub(crate) fn run<O, F, T, FN, R>(
+    opt: &O,
+    fit_fn: &F,
+    termination: T,
+    seed: u64,
+    x_in: Option<&O::Data>,
+    callback: FN
+) -> OptimizationResult<O::Data, (), O::Value>
+where
+    O: SteadyState,
+    F: Fitness<Data=O::Data, Value=O::Value>,
+    T: Termination<O::Data, O::Value> + 'static,
+    FN: FnMut(O::Stats, Option<usize>) -> R,
+    R: Into<Control>
+{
+    let result = drive(opt, fit_fn.direction(), FailurePolicy::Worst, termination, seed, x_in, callback,
+                       |candidate| {
+        let (fitness, free) = fit_fn.score_metered(candidate);
+        (Ok(fitness), O::Value::ZERO, free)
+    });
+    result.unwrap_or_else(|()| unreachable!("Infallible evaluation failed"))
+}
+
+/// Runs a steady-state optimizer to completion with a fitness function which
+/// can fail, like `AskTell::fit_fallible`
+#[allow(clippy::type_complexity)]
+pub(crate) fn run_fallible<O, F, T, FN, R>(
+    opt: &O,
+    fit_fn: &F,
+    policy: FailurePolicy,
+    termination: T,
+    seed: u64,
+    x_in: Option<&O::Data>,
+    callback: FN
+) -> Result<OptimizationResult<O::Data, F::Error, O::Value>, F::Error>
+where
+    O: SteadyState,
+    F: FallibleFitness<Data=O::Data, Value=O::Value>,
+    T: Termination<O::Data, O::Value> + 'static,
+    FN: FnMut(O::Stats, Option<usize>) -> R,
+    R: Into<Control>
+{
+    drive(opt, fit_fn.direction(), policy, termination, seed, x_in, callback,
+          |candidate| (fit_fn.try_score(candidate), O::Value::ZERO, false))
+}
+
+/// Runs a steady-state optimizer to completion on a constrained problem,
+/// like `ConstrainedAskTell::fit_constrained`
+pub(crate) fn run_constrained<O, C, T, FN, R>(
+    opt: &O,
+    fit_fn: &C,
+    termination: T,
+    seed: u64,
+    x_in: Option<&O::Data>,
+    callback: FN
+) -> OptimizationResult<O::Data, (), O::Value>
+where
+    O: SteadyState,
+    C: ConstrainedFitness<Data=O::Data, Value=O::Value>,
+    T: Termination<O::Data, O::Value> + 'static,
+    FN: FnMut(O::Stats, Option<usize>) -> R,
+    R: Into<Control>
+{
+    let result = drive(opt, fit_fn.direction(), FailurePolicy::Worst, termination, seed, x_in, callback,
+                       |candidate| (Ok(fit_fn.score(candidate)), fit_fn.violation(candidate), false));
+    result.unwrap_or_else(|()| unreachable!("Infallible evaluation failed"))
+}
+
+/// Runs a steady-state optimizer to completion.  Every worker gets a
+/// candidate of its own, and a new one as soon as it hands back a fitness;
+/// candidates are only held back when the budget can't cover them.  Failed
+/// evaluations are handled under `policy` as they come in, and a panic while
+/// scoring is passed on to the caller once the other workers are done.
+#[allow(clippy::too_many_arguments)]
+pub(crate) fn drive<O, T, FN, R, S, Er>(
+    opt: &O,
+    direction: Direction,
+    policy: FailurePolicy,
+    termination: T,
+    seed: u64,
+    x_in: Option<&O::Data>,
+    mut callback: FN,
+    score: S
+) -> Result<OptimizationResult<O::Data, Er, O::Value>, Er>
+where
+    O: SteadyState,
+    T: Termination<O::Data, O::Value> + 'static,
+    FN: FnMut(O::Stats, Option<usize>) -> R,
+    R: Into<Control>,
+    S: Fn(&O::Data) -> Evaluation<O::Value, Er> + Sync,
+    Er: Send
+{
+    let mut state = opt.init(Oriented(termination, direction), seed, x_in);
+    opt.begin(&mut state);
+
+    let workers = opt.execution().workers();
+    let mut history = Vec::new();
+    let mut errors = Vec::new();
+    let (tx, rx) = mpsc::channel();
+    let aborted = opt.execution().scope(|spawner| {
+        // Every candidate goes out with the attempt it's on
+        let dispatch = |tx: &mpsc::Sender<_>, ticket: Ticket, attempt: usize, candidate: O::Data| {
+            let tx = tx.clone();
+            let score = &score;
+            spawner.spawn(move || {
+                let evaluation = panic::catch_unwind(AssertUnwindSafe(|| score(&candidate)));
+                // A run which has given up on its results doesn't need this one
+                let _ = tx.send((ticket, attempt, candidate, evaluation));
+            });
+        };
+
+        let mut tx = Some(tx);
+        let mut in_flight = 0;
+        loop {
+            // Keep every worker busy, so long as the budget covers what's out
+            while let Some(sender) = &tx {
+                if in_flight >= workers || !affords(opt, &state, in_flight) {
+                    break
+                }
+                let (ticket, candidate) = match opt.propose(&mut state) {
+                    Some(proposal) => proposal,
+                    None => break
+                };
+                dispatch(sender, ticket, 0, candidate);
+                in_flight += 1;
+            }
+
+            // Nothing more goes out once the run has stopped, so only the
+            // jobs still out keep the channel open
+            if opt.is_done(&state) {
+                tx = None;
+            }
+            if in_flight == 0 {
+                return None
+            }
+
+            // Results still out when the run stops are paid for, so they're
+            // folded in all the same
+            let (ticket, attempt, candidate, evaluation) = match wait(&rx) {
+                Some(message) => message,
+                None => return None
+            };
+            in_flight -= 1;
+            let (result, violation, free) = evaluation.unwrap_or_else(|payload| panic::resume_unwind(payload));
+            opt.ledger_mut(&mut state).record_single(free);
+
+            let fitness = match result {
+                Ok(fitness) if !fitness.is_nan() => fitness,
+                Err(e) if policy == FailurePolicy::Abort => return Some(e),
+                failed => {
+                    opt.ledger_mut(&mut state).fail(1);
+                    if let Err(e) = failed {
+                        errors.push(e);
+                    }
+
+                    // Try again straight away on the worker that just freed up,
+                    // unless the result is stale or the budget can't cover it
+                    let retry = if attempt < policy.attempts() && ticket.pass == opt.restarts(&state) &&
+                        affords(opt, &state, in_flight) {
+                        match policy {
+                            FailurePolicy::Resample { .. } => opt.redraw(&mut state, ticket),
+                            _ => Some(candidate.clone())
+                        }
+                    } else {
+                        None
+                    };
+                    if let (Some(sender), Some(candidate)) = (&tx, retry) {
+                        dispatch(sender, ticket, attempt + 1, candidate);
+                        in_flight += 1;
+                        continue
+                    }
+                    O::Value::NEG_INFINITY
+                }
+            };
+
+            let done = opt.is_done(&state);
+            if !opt.accept(&mut state, ticket, candidate, fitness, violation) || done {
+                continue
+            }
+
+            let stats = direction.orient_stats(opt.stats(&state));
+            history.push(stats.summary());
+            match callback(stats, opt.remaining(&state)).into() {
+                Control::Continue => (),
+                Control::Stop     => opt.stop(&mut state, TerminationReason::Callback),
+                Control::Restart  => opt.restart(&mut state)
+            }
+        }
+    });
+    if let Some(e) = aborted {
+        return Err(e)
+    }
+
+    // A run which ran dry between generations never had its criteria checked
+    if opt.termination(&state).is_none() {
+        opt.stop(&mut state, TerminationReason::BudgetExhausted);
+    }
+    Ok(crate::finish(opt, direction, state, errors, history))
+}
+
+/// Whether the budget covers another evaluation on top of those still out
+fn affords<O: AskTell>(opt: &O, state: &O::State, in_flight: usize) -> bool {
+    !opt.is_done(state) && opt.remaining(state).map(|r| r > in_flight).unwrap_or(true)
+}
+
+/// Waits for the next result, or `None` once every sender has hung up.  On a
+/// pool thread it runs other jobs in the meantime rather than blocking, so
+/// steady-state runs nested in a parallel iterator can't starve the pool of
+/// the workers their own jobs need.
+fn wait<M>(rx: &mpsc::Receiver<M>) -> Option<M> {
+    loop {
+        match rx.try_recv() {
+            Ok(message) => return Some(message),
+            Err(TryRecvError::Disconnected) => return None,
+            Err(TryRecvError::Empty) => match rayon::yield_now() {
+                Some(Yield::Executed) => (),
+                Some(Yield::Idle) => match rx.recv_timeout(Duration::from_millis(1)) {
+                    Ok(message) => return Some(message),
+                    Err(RecvTimeoutError::Disconnected) => return None,
+                    Err(RecvTimeoutError::Timeout) => ()
+                },
+                None => return rx.recv().ok()
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test_steady {
+    use super::*;
+    use std::sync::atomic::{AtomicBool,AtomicUsize,Ordering};
+    use std::time::Instant;
+    use rand_distr::StandardNormal;
+    use rayon::prelude::*;
+    use crate::Optimizer;
+    use crate::de::DePlus;
+    use crate::exp::MatyasEnv;
+    use crate::execution::Execution;
+
+    /// Matyas whose first evaluation holds on until `wait_for` others have
+    /// finished, or gives up after a while
+    struct Straggler {
+        wait_for: usize,
+        started: AtomicBool,
+        finished: AtomicUsize,
+        seen: AtomicUsize
+    }
+
+    impl Fitness for Straggler {
+        type Data = Vec<f32>;
+        type Value = f32;
+
+        fn score(&self, candidate: &Vec<f32>) -> f32 {
+            if !self.started.swap(true, Ordering::SeqCst) {
+                let start = Instant::now();
+                while self.finished.load(Ordering::SeqCst) < self.wait_for &&
+                    start.elapsed() < Duration::from_secs(5) {
+                    std::thread::sleep(Duration::from_millis(1));
+                }
+                self.seen.store(self.finished.load(Ordering::SeqCst), Ordering::SeqCst);
+            } else {
+                self.finished.fetch_add(1, Ordering::SeqCst);
+            }
+            MatyasEnv(-10., 10.).score(candidate)
+        }
+    }
+
+    #[test]
+    fn test_no_barrier() {
+        let fitness = Straggler {
+            wait_for: 30,
+            started: AtomicBool::new(false),
+            finished: AtomicUsize::new(0),
+            seen: AtomicUsize::new(0)
+        };
+        let de = DePlus::builder(2, StandardNormal)
+            .lambda(10)
+            .update(UpdateMode::SteadyState)
+            .execution(Execution::threads(2))
+            .build().unwrap();
+        let result = de.fit(&fitness, 200, 2020, None, |_stats, _fns_remaining| {});
+        assert_eq!(result.evaluations, 200);
+
+        // Other workers carried on through three generations' worth while
+        // the first evaluation was still out
+        assert!(fitness.seen.load(Ordering::SeqCst) >= 30);
+    }
+
+    /// Matyas which panics on its tenth evaluation
+    struct Fuse(AtomicUsize);
+
+    impl Fitness for Fuse {
+        type Data = Vec<f32>;
+        type Value = f32;
+
+        fn score(&self, candidate: &Vec<f32>) -> f32 {
+            if self.0.fetch_add(1, Ordering::SeqCst) == 9 {
+                panic!("Fuse blew");
+            }
+            MatyasEnv(-10., 10.).score(candidate)
+        }
+    }
+
+    #[test]
+    fn test_panic() {
+        for execution in [Execution::Sequential, Execution::threads(2)] {
+            let de = DePlus::builder(2, StandardNormal)
+                .lambda(10)
+                .update(UpdateMode::SteadyState)
+                .execution(execution)
+                .build().unwrap();
+            let fitness = Fuse(AtomicUsize::new(0));
+            let result = panic::catch_unwind(AssertUnwindSafe(|| {
+                de.fit(&fitness, 200, 2020, None, |_stats, _fns_remaining| {})
+            }));
+            let payload = result.expect_err("Panic should reach the caller");
+            assert_eq!(payload.downcast_ref::<&str>(), Some(&"Fuse blew"));
+        }
+    }
+
+    #[test]
+    fn test_nested() {
+        // Runs on every worker of the global pool at once, each waiting on
+        // jobs of its own in that same pool
+        let de = DePlus::builder(2, StandardNormal)
+            .lambda(10)
+            .update(UpdateMode::SteadyState)
+            .build().unwrap();
+        let results: Vec<_> = (0..2 * rayon::current_num_threads() as u64).into_par_iter()
+            .map(|seed| de.fit(&MatyasEnv(-10., 10.), 500, seed, None, |_stats, _fns_remaining| {}))
+            .collect();
+        assert!(results.iter().all(|r| r.evaluations == 500));
+    }
+
+    /// Matyas which fails for any candidate far from the origin
+    struct Flaky;
+
+    impl FallibleFitness for Flaky {
+        type Data = Vec<f32>;
+        type Value = f32;
+        type Error = String;
+
+        fn try_score(&self, candidate: &Vec<f32>) -> Result<f32, String> {
+            if candidate.iter().any(|xi| xi.abs() > 1.5) {
+                Err(format!("Diverged at {:?}", candidate))
+            } else {
+                Ok(MatyasEnv(-1., 1.).score(candidate))
+            }
+        }
+    }
+
+    #[test]
+    fn test_fallible() {
+        let de = DePlus::builder(2, StandardNormal)
+            .lambda(30)
+            .f(0.1, 1.)
+            .update(UpdateMode::SteadyState)
+            .execution(Execution::threads(2))
+            .build().unwrap();
+
+        let policies = [FailurePolicy::Worst, FailurePolicy::Retry(2), FailurePolicy::Resample { attempts: 3 }];
+        for policy in policies.iter() {
+            let result = de.fit_fallible(&Flaky, *policy, 3000, 2020, None, |_, _| {}).unwrap();
+            assert!(result.failures > 0, "{:?}", policy);
+            assert_eq!(result.failures, result.errors.len());
+            assert_eq!(result.evaluations, 3000);
+            assert!(result.fitness.is_finite());
+            assert!(result.candidate.iter().all(|xi| xi.abs() <= 1.5));
+        }
+
+        let result = de.fit_fallible(&Flaky, FailurePolicy::Abort, 3000, 2020, None, |_, _| {});
+        assert!(result.unwrap_err().starts_with("Diverged"));
+    }
+
+    #[test]
+    fn test_slots() {
+        let mut slots = Slots::new(3);
+        assert_eq!(slots.next_target(), None);
+
+        slots.queue.clear();
+        slots.scored = vec![true, false, true];
+        let targets: Vec<_> = (0..4).filter_map(|_| slots.next_target()).collect();
+        assert_eq!(targets, vec![0, 2, 0, 2]);
+
+        slots.rescore_all_but(2);
+        assert_eq!(slots.queue, vec![0]);
+        assert_eq!(slots.scored, vec![false, false, true]);
+
+        assert!(!slots.tally(2));
+        assert!(slots.tally(2));
+        assert!(!slots.tally(2));
+    }
+}
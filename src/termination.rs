@@ -0,0 +1,226 @@
+use std::fmt::Debug;
+use std::time::Duration;
+
+use crate::{GenerationStats,Real};
+use crate::result::TerminationReason;
+
+/// Decides when a run should stop.  Checked after every generation.
+pub trait Termination<D, V = f32>: Send + Debug {
+
+    /// Returns why the run should stop, or None to keep going
+    fn check(&mut self, stats: &GenerationStats<D, V>) -> Option<TerminationReason>;
+
+    /// Function evaluations left, if the criterion is budget based
+    fn remaining(&self, _evaluations: usize) -> Option<usize> {
+        None
+    }
+
+    /// Called when the optimizer restarts from a fresh population
+    fn reset(&mut self) {}
+}
+
+/// A bare evaluation count is the same as `MaxEvaluations`
+impl <D, V> Termination<D, V> for usize {
+    fn check(&mut self, stats: &GenerationStats<D, V>) -> Option<TerminationReason> {
+        MaxEvaluations(*self).check(stats)
+    }
+
+    fn remaining(&self, evaluations: usize) -> Option<usize> {
+        Some(self.saturating_sub(evaluations))
+    }
+}
+
+impl <D, V> Termination<D, V> for Box<dyn Termination<D, V>> {
+    fn check(&mut self, stats: &GenerationStats<D, V>) -> Option<TerminationReason> {
+        (**self).check(stats)
+    }
+
+    fn remaining(&self, evaluations: usize) -> Option<usize> {
+        (**self).remaining(evaluations)
+    }
+
+    fn reset(&mut self) {
+        (**self).reset()
+    }
+}
+
+/// Stops once the number of function evaluations has been spent
+#[derive(Clone,Copy,Debug)]
+pub struct MaxEvaluations(pub usize);
+
+impl <D, V> Termination<D, V> for MaxEvaluations {
+    fn check(&mut self, stats: &GenerationStats<D, V>) -> Option<TerminationReason> {
+        if stats.evaluations >= self.0 {
+            Some(TerminationReason::BudgetExhausted)
+        } else {
+            None
+        }
+    }
+
+    fn remaining(&self, evaluations: usize) -> Option<usize> {
+        Some(self.0.saturating_sub(evaluations))
+    }
+}
+
+/// Stops once the run has gone on for longer than the given wall clock time
+#[derive(Clone,Copy,Debug)]
+pub struct MaxTime(pub Duration);
+
+impl <D, V> Termination<D, V> for MaxTime {
+    fn check(&mut self, stats: &GenerationStats<D, V>) -> Option<TerminationReason> {
+        if stats.elapsed >= self.0 {
+            Some(TerminationReason::TimeLimit)
+        } else {
+            None
+        }
+    }
+}
+
+/// Stops once the best fitness reaches the target, in the objective's own
+/// direction.  For a minimized loss, that means at or below the target.
+#[derive(Clone,Copy,Debug)]
+pub struct TargetFitness<V = f32>(pub V);
+
+impl <D, V: Real> Termination<D, V> for TargetFitness<V> {
+    fn check(&mut self, stats: &GenerationStats<D, V>) -> Option<TerminationReason> {
+        if !stats.direction.better(self.0, stats.best) {
+            Some(TerminationReason::TargetReached)
+        } else {
+            None
+        }
+    }
+}
+
+/// Stops when the best fitness hasn't improved by more than `tol` in
+/// `generations` generations
+#[derive(Clone,Copy,Debug)]
+pub struct Stagnation<V = f32> {
+    pub generations: usize,
+    pub tol: V,
+    best: V,
+    stale: usize
+}
+
+impl <V: Real> Stagnation<V> {
+    pub fn new(generations: usize, tol: V) -> Self {
+        Stagnation { generations, tol, best: V::NEG_INFINITY, stale: 0 }
+    }
+}
+
+impl <D, V: Real> Termination<D, V> for Stagnation<V> {
+    fn check(&mut self, stats: &GenerationStats<D, V>) -> Option<TerminationReason> {
+        // Track the maximized value regardless of the objective's direction
+        let best = stats.direction.orient(stats.best);
+        if best > self.best + self.tol || !self.best.is_finite() {
+            self.best = best;
+            self.stale = 0;
+        } else {
+            self.stale += 1;
+        }
+
+        if self.stale >= self.generations {
+            Some(TerminationReason::Stagnation)
+        } else {
+            None
+        }
+    }
+
+    fn reset(&mut self) {
+        self.stale = 0;
+    }
+}
+
+/// Stops once the population has collapsed below the given diversity.
+/// Encodings without a notion of diversity never trigger it.
+#[derive(Clone,Copy,Debug)]
+pub struct DiversityCollapse(pub f32);
+
+impl <D, V> Termination<D, V> for DiversityCollapse {
+    fn check(&mut self, stats: &GenerationStats<D, V>) -> Option<TerminationReason> {
+        match stats.diversity {
+            Some(d) if d < self.0 => Some(TerminationReason::DiversityCollapse),
+            _ => None
+        }
+    }
+}
+
+/// Stops when either criterion is met.  Nest them to combine more than two.
+#[derive(Clone,Copy,Debug)]
+pub struct Any<A,B>(pub A, pub B);
+
+impl <D, V, A: Termination<D, V>, B: Termination<D, V>> Termination<D, V> for Any<A,B> {
+    fn check(&mut self, stats: &GenerationStats<D, V>) -> Option<TerminationReason> {
+        // Check both so stateful criteria see every generation
+        let a = self.0.check(stats);
+        let b = self.1.check(stats);
+        a.or(b)
+    }
+
+    fn remaining(&self, evaluations: usize) -> Option<usize> {
+        match (self.0.remaining(evaluations), self.1.remaining(evaluations)) {
+            (Some(a), Some(b)) => Some(a.min(b)),
+            (a, b) => a.or(b)
+        }
+    }
+
+    fn reset(&mut self) {
+        self.0.reset();
+        self.1.reset();
+    }
+}
+
+/// Stops only once both criteria are met
+#[derive(Clone,Copy,Debug)]
+pub struct All<A,B>(pub A, pub B);
+
+impl <D, V, A: Termination<D, V>, B: Termination<D, V>> Termination<D, V> for All<A,B> {
+    fn check(&mut self, stats: &GenerationStats<D, V>) -> Option<TerminationReason> {
+        // Report the first criterion's reason once both agree
+        let a = self.0.check(stats);
+        let b = self.1.check(stats);
+        b.and(a)
+    }
+
+    fn remaining(&self, evaluations: usize) -> Option<usize> {
+        match (self.0.remaining(evaluations), self.1.remaining(evaluations)) {
+            (Some(a), Some(b)) => Some(a.max(b)),
+            _ => None
+        }
+    }
+
+    fn reset(&mut self) {
+        self.0.reset();
+        self.1.reset();
+    }
+}
+
+#[cfg(test)]
+mod test_termination {
+    use super::*;
+
+    fn stats(generation: usize, best: f32) -> GenerationStats<()> {
+        GenerationStats::new(
+            generation, generation * 10, &[best], Some(1.), (best, ()), Duration::from_secs(0))
+    }
+
+    #[test]
+    fn test_stagnation() {
+        let mut t = Stagnation::new(3, 1e-3);
+        assert_eq!(t.check(&stats(0, -1.)), None);
+        assert_eq!(t.check(&stats(1, -0.5)), None);
+        assert_eq!(t.check(&stats(2, -0.5)), None);
+        assert_eq!(t.check(&stats(3, -0.5)), None);
+        assert_eq!(t.check(&stats(4, -0.5)), Some(TerminationReason::Stagnation));
+    }
+
+    #[test]
+    fn test_combinators() {
+        let mut any = Any(MaxEvaluations(100), TargetFitness(0.));
+        assert_eq!(any.check(&stats(1, 0.)), Some(TerminationReason::TargetReached));
+        assert_eq!(Termination::<()>::remaining(&any, 40), Some(60));
+
+        let mut all = All(MaxEvaluations(100), TargetFitness(0.));
+        assert_eq!(all.check(&stats(1, 0.)), None);
+        assert_eq!(all.check(&stats(10, 0.)), Some(TerminationReason::BudgetExhausted));
+    }
+}